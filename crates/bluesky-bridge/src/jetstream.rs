@@ -0,0 +1,109 @@
+//! Jetstream frames, the JSON rendering of the AT Protocol firehose.
+
+use smartlike_like_queue::bridge::account;
+
+pub const LIKE_COLLECTION: &str = "app.bsky.feed.like";
+pub const PROFILE_COLLECTION: &str = "app.bsky.actor.profile";
+const POST_COLLECTION: &str = "app.bsky.feed.post";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Subject {
+    pub uri: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LikeRecord {
+    pub subject: Subject,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Commit {
+    pub operation: String,
+    pub collection: String,
+    /// Missing in deletes.
+    pub record: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Frame {
+    /// Repository the event is from.
+    pub did: String,
+    /// Cursor of the event.
+    pub time_us: u64,
+    /// "commit", "identity" or "account".
+    pub kind: String,
+    pub commit: Option<Commit>,
+}
+
+/// Post of an `at://{did}/app.bsky.feed.post/{rkey}` URI.
+#[derive(Debug, PartialEq)]
+pub struct Post {
+    pub author: String,
+    pub rkey: String,
+}
+
+impl Post {
+    pub fn parse(uri: &str) -> Option<Post> {
+        let mut parts = uri.strip_prefix("at://")?.split('/');
+        let (author, collection, rkey) = (parts.next()?, parts.next()?, parts.next()?);
+        if collection != POST_COLLECTION || parts.next().is_some() || rkey.is_empty() {
+            return None;
+        }
+        Some(Post {
+            author: author.to_string(),
+            rkey: rkey.to_string(),
+        })
+    }
+
+    /// Link to the post. DIDs don't change with handles.
+    pub fn url(&self) -> String {
+        format!(
+            "https://bsky.app/profile/{}/post/{}",
+            self.author, self.rkey
+        )
+    }
+}
+
+impl Frame {
+    /// Liked post of a like created by the repository owner.
+    pub fn liked(&self) -> Option<Post> {
+        let commit = self.commit.as_ref()?;
+        if self.kind != "commit"
+            || commit.operation != "create"
+            || commit.collection != LIKE_COLLECTION
+        {
+            return None;
+        }
+        let record: LikeRecord = serde_json::from_value(commit.record.clone()?).ok()?;
+        Post::parse(&record.subject.uri)
+    }
+
+    /// Whether the frame changes the profile of the repository owner, and
+    /// whether the new description links a Smartlike account.
+    pub fn profile(&self) -> Option<bool> {
+        let commit = self.commit.as_ref()?;
+        if self.kind != "commit" || commit.collection != PROFILE_COLLECTION {
+            return None;
+        }
+        let description = commit
+            .record
+            .as_ref()
+            .and_then(|record| record.get("description")?.as_str());
+        Some(description.and_then(account).is_some())
+    }
+}
+
+/// Subscription to likes and profiles, resuming at the cursor if there is
+/// one.
+pub fn subscribe_url(base: &str, cursor: Option<u64>) -> String {
+    let mut url = format!(
+        "{}/subscribe?wantedCollections={}&wantedCollections={}",
+        base.trim_end_matches('/'),
+        LIKE_COLLECTION,
+        PROFILE_COLLECTION
+    );
+    if let Some(cursor) = cursor {
+        url.push_str(&format!("&cursor={}", cursor));
+    }
+    url
+}
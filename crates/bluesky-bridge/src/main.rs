@@ -0,0 +1,148 @@
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate log;
+
+use smartlike_embed_lib::client::Client;
+use smartlike_like_queue::LikeQueue;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs::File, io::prelude::*};
+
+mod jetstream;
+mod profiles;
+mod stream;
+
+use profiles::{Announced, Profiles};
+use stream::LikesConfiguration;
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct Configuration {
+    /// Jetstream instance, e.g. "wss://jetstream2.us-east.bsky.network".
+    jetstream_url: String,
+    /// AppView profiles are looked up with.
+    appview_url: String,
+    network_address: String,
+    smartlike_account: String,
+    smartlike_key: String,
+    #[serde(default)]
+    queue_path: String,
+    /// File keeping the position in the stream across restarts.
+    #[serde(default)]
+    cursor_path: String,
+    /// File keeping the DIDs of users who announced an account.
+    #[serde(default)]
+    announced_path: String,
+    likes: LikesConfiguration,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let matches = clap::App::new("smartlike-bluesky-bridge")
+        .version(option_env!("CARGO_PKG_VERSION").unwrap_or(""))
+        .about("smartlike-bluesky-bridge")
+        .arg(
+            clap::Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .help("Configuration file")
+                .takes_value(true)
+                .default_value(""),
+        )
+        .get_matches();
+
+    let config = matches.value_of("config").unwrap();
+    let mut f = File::open(config).unwrap();
+    let mut contents = String::new();
+    f.read_to_string(&mut contents).unwrap();
+    let config = toml::from_str::<Configuration>(&contents).unwrap();
+
+    let queue = LikeQueue::open(if config.queue_path.is_empty() {
+        "./queue"
+    } else {
+        &config.queue_path
+    })
+    .unwrap();
+    let client = Client::new(
+        config.smartlike_account.clone(),
+        config.smartlike_key,
+        config.network_address,
+    );
+    tokio::spawn({
+        let queue = queue.clone();
+        async move { queue.run(client).await }
+    });
+
+    let cursor_path = if config.cursor_path.is_empty() {
+        "./cursor".to_string()
+    } else {
+        config.cursor_path.clone()
+    };
+    let cursor = Arc::new(AtomicU64::new(
+        std::fs::read_to_string(&cursor_path)
+            .ok()
+            .and_then(|cursor| cursor.trim().parse().ok())
+            .unwrap_or_default(),
+    ));
+
+    // Save the position every few seconds rather than on every frame.
+    tokio::spawn({
+        let cursor = cursor.clone();
+        async move {
+            let mut saved = 0;
+            loop {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+                let current = cursor.load(Ordering::Relaxed);
+                if current != saved {
+                    match std::fs::write(&cursor_path, current.to_string()) {
+                        Ok(_) => saved = current,
+                        Err(e) => error!("Failed to save cursor: {}", e),
+                    }
+                }
+            }
+        }
+    });
+
+    let (read_tx, read_rx) = async_channel::bounded(stream::READ_SIZE);
+    let (tx, rx) = async_channel::unbounded();
+    tokio::spawn({
+        let cursor = cursor.clone();
+        async move {
+            // Reconnections continue after the last read like, restarts
+            // after the last resolved one.
+            let mut since = cursor.load(Ordering::Relaxed);
+            loop {
+                match stream::listen(&config.jetstream_url, &mut since, &read_tx).await {
+                    Ok(_) => warn!("{} closed the connection", config.jetstream_url),
+                    Err(e) => error!("{}", e),
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    });
+    tokio::spawn(async move {
+        let announced = Announced::open(if config.announced_path.is_empty() {
+            "./announced"
+        } else {
+            &config.announced_path
+        });
+        let mut profiles = Profiles::new(&config.appview_url, announced);
+        if let Err(e) = stream::resolve(&read_rx, &mut profiles, &config.likes, &cursor, &tx).await
+        {
+            error!("{}", e);
+        }
+    });
+
+    while let Ok(like) = rx.recv().await {
+        queue.push(like).await;
+    }
+}
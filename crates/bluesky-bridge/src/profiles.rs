@@ -0,0 +1,385 @@
+//! Smartlike accounts users publish in their profile descriptions, e.g.
+//! "Smartlike: a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7".
+
+use smartlike_like_queue::bridge::{account, PROFILE_TTL_SECONDS};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Write;
+
+/// DIDs `app.bsky.actor.getProfiles` accepts at once.
+const BATCH_SIZE: usize = 25;
+
+/// Delay before a failed lookup is tried again, doubled with every failure up
+/// to the profile TTL.
+const BACKOFF_SECONDS: u64 = 60;
+
+/// Profiles remembered, the oldest are forgotten first.
+const PROFILES_SIZE: usize = 100000;
+
+#[derive(Deserialize)]
+struct Profile {
+    did: String,
+    #[serde(default)]
+    description: String,
+}
+
+#[derive(Deserialize)]
+struct ProfilesResponse {
+    profiles: Vec<Profile>,
+}
+
+struct Checked {
+    account: Option<String>,
+    /// Whether a lookup succeeded, so that the account is known.
+    resolved: bool,
+    /// When the profile is looked up again.
+    until: u64,
+    /// Lookups failed in a row.
+    failures: u32,
+}
+
+/// Accounts of DIDs, looked up in batches with `app.bsky.actor.getProfiles`
+/// of an AppView. Only announced DIDs are looked up, the others have no
+/// account. Users without a profile or an account are remembered as well,
+/// failed lookups are retried with a backoff.
+pub struct Profiles {
+    appview: String,
+    http: reqwest::Client,
+    announced: Announced,
+    checked: HashMap<String, Checked>,
+    order: VecDeque<String>,
+    capacity: usize,
+}
+
+impl Profiles {
+    pub fn new(appview: &str, announced: Announced) -> Profiles {
+        Profiles {
+            appview: appview.trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+            announced,
+            checked: HashMap::new(),
+            order: VecDeque::new(),
+            capacity: PROFILES_SIZE,
+        }
+    }
+
+    /// Account of the DID if it was looked up and has one.
+    pub fn account(&self, did: &str) -> Option<String> {
+        self.checked.get(did)?.account.clone()
+    }
+
+    /// Whether the account of the DID is known, false while its lookups
+    /// fail.
+    pub fn resolved(&self, did: &str) -> bool {
+        !self.announced.contains(did)
+            || matches!(self.checked.get(did), Some(checked) if checked.resolved)
+    }
+
+    /// When the DID whose account isn't known is looked up again.
+    pub fn retry(&self, did: &str) -> Option<u64> {
+        match self.checked.get(did) {
+            _ if !self.announced.contains(did) => None,
+            Some(checked) if checked.resolved => None,
+            Some(checked) => Some(checked.until),
+            None => Some(0),
+        }
+    }
+
+    /// Handles a profile change read from the stream. A DID is announced
+    /// when its description links an account, the changed profile is looked
+    /// up with the next lookup.
+    pub fn changed(&mut self, did: &str, announces: bool) {
+        if announces {
+            self.announced.insert(did);
+        }
+        if let Some(checked) = self.checked.get_mut(did) {
+            checked.until = 0;
+        }
+    }
+
+    /// Looks up the announced DIDs that aren't known or are due to be
+    /// checked again.
+    pub async fn lookup(&mut self, dids: &[String], now: u64) {
+        let mut due: Vec<&String> = dids
+            .iter()
+            .filter(|did| self.announced.contains(did))
+            .filter(|did| !matches!(self.checked.get(*did), Some(checked) if now < checked.until))
+            .collect();
+        due.sort();
+        due.dedup();
+        for batch in due.chunks(BATCH_SIZE) {
+            match self.descriptions(batch).await {
+                Ok(descriptions) => {
+                    for did in batch {
+                        // Missing from the response without a profile.
+                        let account = descriptions.get(*did).and_then(|d| account(d));
+                        debug!("Account of {}: {:?}", did, account);
+                        self.insert(
+                            did,
+                            Checked {
+                                account,
+                                resolved: true,
+                                until: now + PROFILE_TTL_SECONDS,
+                                failures: 0,
+                            },
+                        );
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to get profiles of {:?}: {}", batch, e);
+                    for did in batch {
+                        // The account known before is used until the retry.
+                        let (account, resolved, failures) = match self.checked.get(*did) {
+                            Some(checked) => (
+                                checked.account.clone(),
+                                checked.resolved,
+                                checked.failures + 1,
+                            ),
+                            None => (None, false, 1),
+                        };
+                        let backoff = BACKOFF_SECONDS
+                            .saturating_mul(1 << (failures - 1).min(16))
+                            .min(PROFILE_TTL_SECONDS);
+                        self.insert(
+                            did,
+                            Checked {
+                                account,
+                                resolved,
+                                until: now + backoff,
+                                failures,
+                            },
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn insert(&mut self, did: &str, checked: Checked) {
+        if self.checked.insert(did.to_string(), checked).is_none() {
+            self.order.push_back(did.to_string());
+            while self.order.len() > self.capacity {
+                if let Some(oldest) = self.order.pop_front() {
+                    self.checked.remove(&oldest);
+                }
+            }
+        }
+    }
+
+    async fn descriptions(&self, dids: &[&String]) -> Result<HashMap<String, String>, String> {
+        let response = self
+            .http
+            .get(format!("{}/xrpc/app.bsky.actor.getProfiles", self.appview))
+            .query(&dids.iter().map(|did| ("actors", did)).collect::<Vec<_>>())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(response.status().to_string());
+        }
+        response
+            .json::<ProfilesResponse>()
+            .await
+            .map(|response| {
+                response
+                    .profiles
+                    .into_iter()
+                    .map(|profile| (profile.did, profile.description))
+                    .collect()
+            })
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// DIDs of users who announced an account by saving it in their profile
+/// while the bridge was reading the stream. The DIDs are kept in a file, one
+/// per line, users who linked their account before can be added to it.
+pub struct Announced {
+    path: String,
+    dids: HashSet<String>,
+}
+
+impl Announced {
+    /// Reads the DIDs announced before, the file may be missing.
+    pub fn open(path: &str) -> Announced {
+        Announced {
+            path: path.to_string(),
+            dids: std::fs::read_to_string(path)
+                .unwrap_or_default()
+                .lines()
+                .map(str::trim)
+                .filter(|did| !did.is_empty())
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    pub fn contains(&self, did: &str) -> bool {
+        self.dids.contains(did)
+    }
+
+    /// Remembers the DID and appends it to the file.
+    pub fn insert(&mut self, did: &str) {
+        if !self.dids.insert(did.to_string()) {
+            return;
+        }
+        let appended = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| writeln!(file, "{}", did));
+        if let Err(e) = appended {
+            error!("Failed to save announced {}: {}", did, e);
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+    use serde_json::json;
+    use smartlike_like_queue::testing::TempDir;
+    use std::sync::{Arc, Mutex};
+
+    /// Local stand-in AppView with profiles of alice, carol and flaky linking
+    /// their accounts. Fails batches with "did:plc:down", and the first one
+    /// with "did:plc:flaky". Records the looked up DIDs of every request.
+    pub fn appview(requests: Arc<Mutex<Vec<Vec<String>>>>) -> String {
+        let server = HttpServer::new(move || {
+            App::new().app_data(web::Data::new(requests.clone())).route(
+                "/xrpc/app.bsky.actor.getProfiles",
+                web::get().to(
+                    |request: HttpRequest, requests: web::Data<Arc<Mutex<Vec<Vec<String>>>>>| async move {
+                        let dids: Vec<String> =
+                            web::Query::<Vec<(String, String)>>::from_query(request.query_string())
+                                .unwrap()
+                                .into_inner()
+                                .into_iter()
+                                .filter(|(name, _)| name == "actors")
+                                .map(|(_, did)| did)
+                                .collect();
+                        let mut requests = requests.lock().unwrap();
+                        requests.push(dids.clone());
+                        let flaky = "did:plc:flaky".to_string();
+                        let flaky_requests =
+                            requests.iter().filter(|dids| dids.contains(&flaky)).count();
+                        if dids.iter().any(|did| did == "did:plc:down")
+                            || dids.contains(&flaky) && flaky_requests == 1
+                        {
+                            return HttpResponse::BadGateway().finish();
+                        }
+                        let profiles: Vec<_> = dids
+                            .iter()
+                            .filter_map(|did| {
+                                let description = match did.as_str() {
+                                    "did:plc:alice" => {
+                                        "Reader. Smartlike: a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7"
+                                    }
+                                    "did:plc:carol" => {
+                                        "Writer\nSmartlike:b0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7"
+                                    }
+                                    "did:plc:flaky" => {
+                                        "Smartlike: c0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7"
+                                    }
+                                    "did:plc:missing" => return None,
+                                    _ => "Just here to look around",
+                                };
+                                Some(json!({ "did": did, "description": description }))
+                            })
+                            .collect();
+                        HttpResponse::Ok().json(json!({ "profiles": profiles }))
+                    },
+                ),
+            )
+        })
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        actix_rt::spawn(server.run());
+        url
+    }
+
+    #[actix_rt::test]
+    async fn test_profiles() {
+        let dir = TempDir::new("smartlike-bluesky-profiles-test");
+        let mut announced = Announced::open(&dir.join("announced"));
+        let all: Vec<String> = (0..30).map(|i| format!("did:plc:user{:02}", i)).collect();
+        for did in [
+            "did:plc:down",
+            "did:plc:alice",
+            "did:plc:missing",
+            "did:plc:bob",
+        ] {
+            announced.insert(did);
+        }
+        all.iter().for_each(|did| announced.insert(did));
+        // Announced DIDs are remembered across restarts.
+        let announced = Announced::open(&dir.join("announced"));
+        assert!(announced.contains("did:plc:user29"));
+
+        let requests = Arc::new(Mutex::new(vec![]));
+        let mut profiles = Profiles::new(&appview(requests.clone()), announced);
+        let dids = |dids: &[&str]| dids.iter().map(|did| did.to_string()).collect::<Vec<_>>();
+        let looked_up = || std::mem::take(&mut *requests.lock().unwrap());
+
+        // Failures are remembered and retried with a backoff.
+        profiles.lookup(&dids(&["did:plc:down"]), 1000).await;
+        profiles.lookup(&dids(&["did:plc:down"]), 1059).await;
+        assert_eq!(looked_up(), [dids(&["did:plc:down"])]);
+        profiles.lookup(&dids(&["did:plc:down"]), 1060).await;
+        profiles.lookup(&dids(&["did:plc:down"]), 1179).await;
+        assert_eq!(looked_up(), [dids(&["did:plc:down"])]);
+        profiles.lookup(&dids(&["did:plc:down"]), 1180).await;
+        assert_eq!(looked_up().len(), 1);
+        assert!(profiles.account("did:plc:down").is_none());
+        assert!(!profiles.resolved("did:plc:down"));
+        assert_eq!(profiles.retry("did:plc:down"), Some(1420));
+
+        // Users without a profile or an account are remembered too.
+        profiles
+            .lookup(
+                &[dids(&["did:plc:alice", "did:plc:missing"]), all.clone()].concat(),
+                2000,
+            )
+            .await;
+        assert_eq!(
+            looked_up().iter().map(Vec::len).collect::<Vec<_>>(),
+            [25, 7]
+        );
+        profiles.lookup(&all, 2000).await;
+        profiles.lookup(&dids(&["did:plc:missing"]), 2000).await;
+        assert!(looked_up().is_empty());
+        assert_eq!(
+            profiles.account("did:plc:alice").unwrap(),
+            "a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7"
+        );
+        assert!(profiles.account("did:plc:missing").is_none());
+        assert!(profiles.resolved("did:plc:missing"));
+        assert_eq!(profiles.retry("did:plc:missing"), None);
+        profiles
+            .lookup(&dids(&["did:plc:missing"]), 2000 + PROFILE_TTL_SECONDS)
+            .await;
+        assert_eq!(looked_up(), [dids(&["did:plc:missing"])]);
+
+        // Only announced DIDs are looked up, a profile change announces.
+        profiles.lookup(&dids(&["did:plc:carol"]), 3000).await;
+        assert!(looked_up().is_empty());
+        assert!(profiles.resolved("did:plc:carol"));
+        profiles.changed("did:plc:carol", true);
+        assert_eq!(profiles.retry("did:plc:carol"), Some(0));
+
+        // The oldest profiles are forgotten.
+        profiles.capacity = 3;
+        profiles
+            .lookup(&dids(&["did:plc:bob", "did:plc:carol"]), 3000)
+            .await;
+        assert_eq!(profiles.checked.len(), 3);
+        assert!(profiles.account("did:plc:alice").is_none());
+        assert!(profiles.account("did:plc:carol").is_some());
+
+        // Changed profiles are looked up again.
+        profiles.changed("did:plc:carol", false);
+        profiles.lookup(&dids(&["did:plc:carol"]), 3000).await;
+        assert_eq!(looked_up().last().unwrap(), &dids(&["did:plc:carol"]));
+    }
+}
@@ -0,0 +1,381 @@
+//! Likes of posts between users who both published their Smartlike account.
+//! The liker's account pays, the author's receives.
+
+use crate::jetstream::{self, Frame, Post};
+use crate::now;
+use crate::profiles::Profiles;
+use futures::StreamExt;
+use smartlike_embed_lib::client::Like;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Likes resolved with one round of profile lookups.
+const BATCH_SIZE: usize = 100;
+
+/// Likes read ahead of the lookups, the reader waits when there are more.
+pub const READ_SIZE: usize = 1000;
+
+/// Likes kept while the lookups of their profiles fail. No more likes are
+/// read when there are more.
+const WAITING_SIZE: usize = 10000;
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct LikesConfiguration {
+    /// Amount of a like.
+    pub amount: f64,
+    pub currency: String,
+}
+
+/// Like of a post, waiting for the profiles of the liker and the author.
+#[derive(Debug)]
+pub struct Liked {
+    pub did: String,
+    pub post: Post,
+    /// Cursor of the frame.
+    pub time_us: u64,
+}
+
+/// Frame read from the stream.
+#[derive(Debug)]
+pub enum Read {
+    Liked(Liked),
+    /// Profile change, whether it announces an account.
+    Profile {
+        did: String,
+        announces: bool,
+        time_us: u64,
+    },
+}
+
+/// Like of a post between connected users. DIDs aren't connected on the
+/// network, so the like carries the accounts of the liker and the author.
+fn like(liked: &Liked, profiles: &Profiles, config: &LikesConfiguration) -> Option<Like> {
+    let account = profiles.account(&liked.did)?;
+    let recipient = profiles.account(&liked.post.author)?;
+    Some(Like {
+        platform: "bluesky".to_string(),
+        id: liked.did.clone(),
+        target: liked.post.url(),
+        amount: config.amount,
+        currency: config.currency.clone(),
+        account: Some(account),
+        recipient: Some(recipient),
+    })
+}
+
+/// Reads likes and profile changes from Jetstream until it closes the
+/// connection and passes them to `resolve`. `since` follows the stream and
+/// is where the next connection resumes, zero to start with live events.
+pub async fn listen(
+    base: &str,
+    since: &mut u64,
+    read: &async_channel::Sender<Read>,
+) -> Result<(), String> {
+    let url = jetstream::subscribe_url(base, Some(*since).filter(|since| *since > 0));
+    let (mut socket, _) = tokio_tungstenite::connect_async(&url)
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", url, e))?;
+    while let Some(message) = socket.next().await {
+        let text = match message.map_err(|e| format!("{}: {}", base, e))? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let frame = match serde_json::from_str::<Frame>(&text) {
+            Ok(frame) => frame,
+            Err(e) => {
+                warn!("Invalid frame {}: {}", text, e);
+                continue;
+            }
+        };
+        let event = match (frame.liked(), frame.profile()) {
+            (Some(post), _) => Some(Read::Liked(Liked {
+                did: frame.did.clone(),
+                post,
+                time_us: frame.time_us,
+            })),
+            (None, Some(announces)) => Some(Read::Profile {
+                did: frame.did.clone(),
+                announces,
+                time_us: frame.time_us,
+            }),
+            (None, None) => None,
+        };
+        if let Some(event) = event {
+            read.send(event)
+                .await
+                .map_err(|e| format!("TX Error: {}", e))?;
+        }
+        // The cursor is inclusive, the frame isn't read again.
+        *since = frame.time_us + 1;
+    }
+    Ok(())
+}
+
+/// Whether the like can be sent or dropped: the accounts of both users are
+/// known, or one of them is known to have none.
+fn decided(liked: &Liked, profiles: &Profiles) -> bool {
+    let without_account = |did: &str| profiles.resolved(did) && profiles.account(did).is_none();
+    (profiles.resolved(&liked.did) && profiles.resolved(&liked.post.author))
+        || without_account(&liked.did)
+        || without_account(&liked.post.author)
+}
+
+/// When the lookups of a waiting like are tried again.
+fn retry(liked: &Liked, profiles: &Profiles) -> Option<u64> {
+    [&liked.did, &liked.post.author]
+        .iter()
+        .filter_map(|did| profiles.retry(did))
+        .min()
+}
+
+/// Looks up the profiles of the waiting likes and sends the ones between
+/// connected users to `likes`. Likes whose lookups failed keep waiting.
+async fn round(
+    waiting: &mut Vec<Liked>,
+    profiles: &mut Profiles,
+    config: &LikesConfiguration,
+    now: u64,
+    likes: &async_channel::Sender<Like>,
+) -> Result<(), String> {
+    let dids: Vec<String> = waiting
+        .iter()
+        .flat_map(|liked| [liked.did.clone(), liked.post.author.clone()])
+        .collect();
+    profiles.lookup(&dids, now).await;
+    for liked in std::mem::take(waiting) {
+        if !decided(&liked, profiles) {
+            waiting.push(liked);
+            continue;
+        }
+        if let Some(like) = like(&liked, profiles, config) {
+            likes
+                .send(like)
+                .await
+                .map_err(|e| format!("TX Error: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+/// Looks up the profiles of the read likes in batches, so that slow lookups
+/// don't hold up reading the stream, and sends likes between connected
+/// users to `likes`. Likes whose lookups failed wait and are tried again
+/// after the backoff. `cursor` follows the resolved likes, it doesn't pass
+/// a waiting one, and is where the bridge resumes after a restart.
+pub async fn resolve(
+    read: &async_channel::Receiver<Read>,
+    profiles: &mut Profiles,
+    config: &LikesConfiguration,
+    cursor: &AtomicU64,
+    likes: &async_channel::Sender<Like>,
+) -> Result<(), String> {
+    let mut waiting: Vec<Liked> = vec![];
+    // Cursor after the last read frame.
+    let mut position = cursor.load(Ordering::Relaxed);
+    loop {
+        let delay = waiting
+            .iter()
+            .filter_map(|liked| retry(liked, profiles))
+            .min()
+            .map(|at| Duration::from_secs(at.saturating_sub(now())));
+        if waiting.len() < WAITING_SIZE {
+            // New likes are read until the next retry.
+            let received = match delay {
+                Some(delay) => tokio::time::timeout(delay, read.recv()).await.ok(),
+                None => Some(read.recv().await),
+            };
+            match received {
+                Some(Ok(first)) => {
+                    let mut batch = vec![first];
+                    while batch.len() < BATCH_SIZE {
+                        match read.try_recv() {
+                            Ok(next) => batch.push(next),
+                            Err(_) => break,
+                        }
+                    }
+                    for event in batch {
+                        position = match event {
+                            Read::Liked(liked) => {
+                                let time_us = liked.time_us;
+                                waiting.push(liked);
+                                time_us + 1
+                            }
+                            Read::Profile {
+                                did,
+                                announces,
+                                time_us,
+                            } => {
+                                profiles.changed(&did, announces);
+                                time_us + 1
+                            }
+                        };
+                    }
+                }
+                // The waiting likes are read again after the restart.
+                Some(Err(_)) => return Ok(()),
+                None => {}
+            }
+        } else if let Some(delay) = delay {
+            // The reader waits as well once the channel is full.
+            tokio::time::sleep(delay).await;
+        }
+        round(&mut waiting, profiles, config, now(), likes).await?;
+        cursor.store(
+            waiting.first().map_or(position, |liked| liked.time_us),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profiles::tests::appview;
+    use crate::profiles::Announced;
+    use futures::SinkExt;
+    use smartlike_like_queue::testing::TempDir;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+
+    /// Local stand-in Jetstream replaying the recorded frames.
+    async fn jetstream() -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut path = String::new();
+            // The error type is tungstenite's.
+            #[allow(clippy::result_large_err)]
+            let callback =
+                |request: &tokio_tungstenite::tungstenite::handshake::server::Request, response| {
+                    path = request.uri().to_string();
+                    Ok(response)
+                };
+            let mut socket = tokio_tungstenite::accept_hdr_async(stream, callback)
+                .await
+                .unwrap();
+            let frames = std::fs::read_to_string("./test/jetstream.jsonl").unwrap();
+            for frame in frames.lines().filter(|line| !line.is_empty()) {
+                socket.send(Message::Text(frame.to_string())).await.unwrap();
+            }
+            socket.close(None).await.unwrap();
+            path
+        });
+        (url, server)
+    }
+
+    #[actix_rt::test]
+    async fn test_stream() {
+        assert_eq!(
+            Post::parse("at://did:plc:carol/app.bsky.feed.post/3l3qo2vutsw2b"),
+            Some(Post {
+                author: "did:plc:carol".to_string(),
+                rkey: "3l3qo2vutsw2b".to_string()
+            })
+        );
+        assert!(Post::parse("at://did:plc:carol/app.bsky.feed.generator/feed").is_none());
+        assert!(Post::parse("https://bsky.app/profile/carol").is_none());
+
+        let dir = TempDir::new("smartlike-bluesky-stream-test");
+        let requests = Arc::new(Mutex::new(vec![]));
+        let announced = Announced::open(&dir.join("announced"));
+        let mut profiles = Profiles::new(&appview(requests.clone()), announced);
+        let (url, server) = jetstream().await;
+        let config = LikesConfiguration {
+            amount: 0.1,
+            currency: "USD".to_string(),
+        };
+        let (read_tx, read_rx) = async_channel::bounded(READ_SIZE);
+        let mut since = 1725911162000000;
+        listen(&url, &mut since, &read_tx).await.unwrap();
+        assert_eq!(
+            server.await.unwrap(),
+            "/subscribe?wantedCollections=app.bsky.feed.like&wantedCollections=app.bsky.actor.profile&cursor=1725911162000000"
+        );
+        assert_eq!(since, 1725911169000001);
+
+        // Likes read while the profiles are looked up wait in the channel.
+        drop(read_tx);
+        let (tx, rx) = async_channel::unbounded();
+        let cursor = AtomicU64::new(1725911162000000);
+        resolve(&read_rx, &mut profiles, &config, &cursor, &tx)
+            .await
+            .unwrap();
+        assert_eq!(cursor.load(Ordering::Relaxed), 1725911169000001);
+
+        let likes: Vec<Like> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(likes.len(), 2, "{:?}", likes);
+        assert_eq!(likes[0].platform, "bluesky");
+        assert_eq!(likes[0].id, "did:plc:alice");
+        assert_eq!(
+            likes[0].target,
+            "https://bsky.app/profile/did:plc:carol/post/3l3qo2vutsw2b"
+        );
+        assert_eq!((likes[0].amount, likes[0].currency.as_str()), (0.1, "USD"));
+        assert_eq!(
+            likes[0].account.as_deref(),
+            Some("a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7")
+        );
+        assert_eq!(
+            likes[0].recipient.as_deref(),
+            Some("b0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7")
+        );
+        assert_eq!(
+            likes[1].target,
+            "https://bsky.app/profile/did:plc:carol/post/3l3qpxa2b5k2c"
+        );
+
+        // Profiles of the users who announced an account are looked up
+        // once, in one batch.
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [["did:plc:alice", "did:plc:carol"]]
+        );
+
+        // Likes whose lookups fail wait without holding up the others, the
+        // cursor doesn't pass them.
+        let liked = |did: &str, rkey: &str, time_us| Liked {
+            did: did.to_string(),
+            post: Post {
+                author: "did:plc:carol".to_string(),
+                rkey: rkey.to_string(),
+            },
+            time_us,
+        };
+        profiles.changed("did:plc:down", true);
+        profiles.changed("did:plc:flaky", true);
+        let (read_tx, read_rx) = async_channel::bounded(READ_SIZE);
+        for read in [
+            liked("did:plc:down", "down", 1725911170000000),
+            liked("did:plc:alice", "alice", 1725911171000000),
+        ] {
+            read_tx.send(Read::Liked(read)).await.unwrap();
+        }
+        drop(read_tx);
+        resolve(&read_rx, &mut profiles, &config, &cursor, &tx)
+            .await
+            .unwrap();
+        assert_eq!(cursor.load(Ordering::Relaxed), 1725911170000000);
+        let likes: Vec<Like> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(likes.len(), 1);
+        assert!(likes[0].target.ends_with("/post/alice"));
+
+        // They're sent once a lookup after the backoff succeeds.
+        let mut waiting = vec![liked("did:plc:flaky", "flaky", 1725911172000000)];
+        round(&mut waiting, &mut profiles, &config, 1000, &tx)
+            .await
+            .unwrap();
+        assert_eq!(waiting.len(), 1);
+        assert_eq!(retry(&waiting[0], &profiles), Some(1060));
+        round(&mut waiting, &mut profiles, &config, 1060, &tx)
+            .await
+            .unwrap();
+        assert!(waiting.is_empty());
+        assert_eq!(
+            rx.try_recv().unwrap().account.as_deref(),
+            Some("c0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7")
+        );
+    }
+}
@@ -0,0 +1,127 @@
+use anyhow::anyhow;
+use smartlike_embed_lib::challenge::{Challenge, ChallengeResponse};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Keeps issued challenges until they are redeemed or expire.
+pub struct ChallengeStore {
+    issuer: String,
+    ttl: u32,
+    scopes: Vec<String>,
+    max_pending: usize,
+    pending: Mutex<HashMap<String, Challenge>>,
+}
+
+impl ChallengeStore {
+    pub fn new(issuer: &str, ttl: u32, scopes: Vec<String>, max_pending: usize) -> ChallengeStore {
+        ChallengeStore {
+            issuer: issuer.to_string(),
+            ttl,
+            scopes,
+            max_pending,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn issue(&self, scope: &str, domain: &str, now: u32) -> anyhow::Result<Challenge> {
+        if !self.scopes.iter().any(|s| s == scope) {
+            return Err(anyhow!("Unsupported scope: {}", scope));
+        }
+        if domain.is_empty() {
+            return Err(anyhow!("Missing domain"));
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, c| !c.is_expired(now));
+        if pending.len() >= self.max_pending {
+            return Err(anyhow!("Too many pending challenges"));
+        }
+
+        let challenge = Challenge::new(&self.issuer, scope, domain, now, self.ttl);
+        pending.insert(challenge.nonce.clone(), challenge.clone());
+        Ok(challenge)
+    }
+
+    /// Verifies the response and consumes the challenge, so that every
+    /// challenge can be redeemed only once.
+    pub fn redeem(&self, response: &ChallengeResponse, now: u32) -> anyhow::Result<()> {
+        let challenge = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(&response.challenge.nonce)
+            .ok_or(anyhow!("Unknown challenge"))?;
+        if challenge != response.challenge {
+            return Err(anyhow!("Challenge mismatch"));
+        }
+        response.verify(now)
+    }
+}
+
+pub fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smartlike_embed_lib::client::Client;
+
+    #[test]
+    fn test_challenge_redemption() {
+        let store = ChallengeStore::new("gateway", 60, vec!["login".to_string()], 2);
+        let user = Client::new(
+            "a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7".to_string(),
+            "secret".to_string(),
+            "".to_string(),
+        );
+
+        assert_eq!(
+            store
+                .issue("payment", "example.com", 1000)
+                .err()
+                .unwrap()
+                .to_string(),
+            "Unsupported scope: payment"
+        );
+
+        let challenge = store.issue("login", "example.com", 1000).unwrap();
+        let response = user.respond_to_challenge(&challenge).unwrap();
+        assert!(store.redeem(&response, 1010).is_ok());
+        assert_eq!(
+            store.redeem(&response, 1010).err().unwrap().to_string(),
+            "Unknown challenge"
+        );
+
+        // Domain swapped by the client.
+        let mut challenge = store.issue("login", "example.com", 1000).unwrap();
+        challenge.domain = "example.org".to_string();
+        let response = user.respond_to_challenge(&challenge).unwrap();
+        assert_eq!(
+            store.redeem(&response, 1010).err().unwrap().to_string(),
+            "Challenge mismatch"
+        );
+
+        // Expired challenges are dropped.
+        let challenge = store.issue("login", "example.com", 1000).unwrap();
+        let response = user.respond_to_challenge(&challenge).unwrap();
+        assert!(store.issue("login", "example.com", 1100).is_ok());
+        assert!(store.issue("login", "example.com", 1100).is_ok());
+        assert_eq!(
+            store
+                .issue("login", "example.com", 1100)
+                .err()
+                .unwrap()
+                .to_string(),
+            "Too many pending challenges"
+        );
+        assert_eq!(
+            store.redeem(&response, 1100).err().unwrap().to_string(),
+            "Unknown challenge"
+        );
+    }
+}
@@ -8,13 +8,16 @@ extern crate rocksdb;
 #[macro_use]
 extern crate log;
 
+mod challenge;
 mod paypal;
 
 use actix_cors::Cors;
 use actix_web::{web, App, HttpResponse, HttpServer};
 use async_channel::Sender;
+use challenge::ChallengeStore;
 use rocksdb::{DBWithThreadMode, IteratorMode, MultiThreaded};
 use serde_json::json;
+use smartlike_embed_lib::challenge::ChallengeResponse;
 use smartlike_embed_lib::client::{Client, DonationReceipt};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -30,6 +33,9 @@ pub struct Configuration {
     network_address: String,
     smartlike_account: String,
     smartlike_key: String,
+    challenge_ttl: u32,
+    challenge_scopes: Vec<String>,
+    max_pending_challenges: usize,
 }
 
 async fn paypal_handler(
@@ -69,19 +75,61 @@ async fn paypal_handler(
     }
 }
 
-async fn test_ping_handler(
+async fn challenge_handler(
     query: web::Query<HashMap<String, String>>,
     client: web::Data<Client>,
+    challenges: web::Data<ChallengeStore>,
 ) -> actix_web::Result<HttpResponse> {
-    if query.contains_key("token") {
-        let token = query.get("token").unwrap();
-        let signature = client.sign(&token);
-        debug!("{:?}", token);
-        Ok(HttpResponse::Ok()
+    match (query.get("scope"), query.get("domain")) {
+        (Some(scope), Some(domain)) => {
+            match challenges
+                .issue(scope, domain, challenge::now())
+                .and_then(|c| client.issue_challenge(c))
+            {
+                Ok(signed) => Ok(HttpResponse::Ok()
+                    .content_type("application/json")
+                    .body(serde_json::to_string(&signed).unwrap())),
+                Err(e) => {
+                    warn!("Failed to issue challenge: {}", e);
+                    Ok(HttpResponse::BadRequest()
+                        .content_type("text/plain")
+                        .body(e.to_string()))
+                }
+            }
+        }
+        _ => Ok(HttpResponse::BadRequest()
             .content_type("text/plain")
-            .body(json!({ "token": token, "signature": signature }).to_string()))
-    } else {
-        Ok(HttpResponse::Ok().content_type("text/plain").body(""))
+            .body("Missing scope or domain")),
+    }
+}
+
+async fn verify_handler(
+    text: String,
+    challenges: web::Data<ChallengeStore>,
+) -> actix_web::Result<HttpResponse> {
+    match serde_json::from_str::<ChallengeResponse>(&text) {
+        Ok(response) => match challenges.redeem(&response, challenge::now()) {
+            Ok(_) => Ok(HttpResponse::Ok().content_type("application/json").body(
+                json!({
+                    "status": "ok",
+                    "account": response.account,
+                    "public_key": response.public_key,
+                })
+                .to_string(),
+            )),
+            Err(e) => {
+                debug!("Challenge rejected: {} {}", e, text);
+                Ok(HttpResponse::Forbidden()
+                    .content_type("application/json")
+                    .body(json!({ "status": e.to_string() }).to_string()))
+            }
+        },
+        Err(e) => {
+            error!("Failed to parse challenge response: {}", e);
+            Ok(HttpResponse::BadRequest()
+                .content_type("application/json")
+                .body(json!({ "status": "Failed to parse challenge response" }).to_string()))
+        }
     }
 }
 
@@ -119,6 +167,13 @@ async fn main() -> anyhow::Result<()> {
         config.network_address,
     );
 
+    let challenges = web::Data::new(ChallengeStore::new(
+        &client.account,
+        config.challenge_ttl,
+        config.challenge_scopes,
+        config.max_pending_challenges,
+    ));
+
     let (tx, rx) = async_channel::unbounded::<(String, DonationReceipt)>();
 
     // Load pending receipts from previous runs and retry them.
@@ -193,7 +248,9 @@ async fn main() -> anyhow::Result<()> {
             .app_data(web::Data::new(tx.clone()))
             .app_data(web::Data::new(client.clone()))
             .app_data(db.clone())
-            .service(web::resource("/ping").route(web::get().to(test_ping_handler)))
+            .app_data(challenges.clone())
+            .service(web::resource("/challenge").route(web::get().to(challenge_handler)))
+            .service(web::resource("/verify").route(web::post().to(verify_handler)))
             .service(web::resource("/paypal").route(web::post().to(paypal_handler)))
     })
     .workers(config.num_threads)
@@ -0,0 +1,66 @@
+//! Configuration and profile helpers shared by the bridges.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+lazy_static! {
+    static ref RE_ACCOUNT: Regex =
+        Regex::new(r"Smartlike:\s?([0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12})")
+            .unwrap();
+}
+
+/// How long a profile is trusted before it's fetched again.
+pub const PROFILE_TTL_SECONDS: u64 = 3600;
+
+/// Smartlike account users publish in their profiles, e.g.
+/// "Smartlike: a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7".
+pub fn account(text: &str) -> Option<String> {
+    RE_ACCOUNT
+        .captures(text)
+        .map(|captures| captures[1].to_string())
+}
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct ReactionsConfiguration {
+    /// Currency of the amounts.
+    pub currency: String,
+    /// Liked reactions and their amounts, e.g. "👍" = 0.1.
+    pub amounts: HashMap<String, f64>,
+}
+
+impl ReactionsConfiguration {
+    /// Amount of the reaction, if it's liked.
+    pub fn amount(&self, reaction: &str) -> Option<f64> {
+        self.amounts
+            .iter()
+            .find(|(configured, _)| same_emoji(configured, reaction))
+            .map(|(_, amount)| *amount)
+    }
+}
+
+/// Clients differ in whether they send emoji with the variation selector.
+pub fn same_emoji(a: &str, b: &str) -> bool {
+    a.trim_end_matches('\u{fe0f}') == b.trim_end_matches('\u{fe0f}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_helpers() {
+        let id = "a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7";
+        assert_eq!(account(&format!("Writer. Smartlike: {}", id)).unwrap(), id);
+        assert_eq!(account(&format!("Smartlike:{}", id)).unwrap(), id);
+        assert!(account(id).is_none());
+
+        let reactions = ReactionsConfiguration {
+            currency: "USD".to_string(),
+            amounts: [("❤️".to_string(), 0.5)].into_iter().collect(),
+        };
+        assert_eq!(reactions.amount("❤"), Some(0.5));
+        assert_eq!(reactions.amount("❤️"), Some(0.5));
+        assert_eq!(reactions.amount("👍"), None);
+    }
+}
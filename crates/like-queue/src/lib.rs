@@ -0,0 +1,241 @@
+//! Durable queue of likes shared by the bot and the bridges.
+//!
+//! Likes are stored before they are sent and deleted once the network
+//! processed them, so that likes pending when a bridge stops are sent after
+//! it restarts.
+
+#[macro_use]
+extern crate log;
+#[macro_use]
+extern crate lazy_static;
+
+pub mod bridge;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod testing;
+
+use rocksdb::{DBWithThreadMode, IteratorMode, MultiThreaded};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use sha2::Digest;
+use smartlike_embed_lib::client::{Client, Like};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Delay before a like that failed to reach the network is sent again.
+const RETRY_SECONDS: u64 = 5;
+
+/// Record of a queue.
+pub trait Queued: Serialize + DeserializeOwned + Send {
+    /// Queue key. The same record is queued only once.
+    fn key(&self) -> String;
+
+    /// Parses a stored record, e.g. of an older version.
+    fn parse(value: &[u8]) -> Option<Self> {
+        serde_json::from_slice(value).ok()
+    }
+}
+
+/// Hash of the like, the same for the same user, target and amount.
+pub fn like_key(like: &Like) -> String {
+    let message = serde_json::to_string(like).unwrap_or_default();
+    let mut hasher = sha2::Sha256::new();
+    sha2::Digest::input(&mut hasher, message.as_bytes());
+    hex::encode(hasher.result().as_slice())
+}
+
+impl Queued for Like {
+    fn key(&self) -> String {
+        like_key(self)
+    }
+}
+
+pub struct Queue<T> {
+    db: Arc<DBWithThreadMode<MultiThreaded>>,
+    tx: async_channel::Sender<(String, T)>,
+    rx: async_channel::Receiver<(String, T)>,
+}
+
+impl<T> Clone for Queue<T> {
+    fn clone(&self) -> Self {
+        Queue {
+            db: self.db.clone(),
+            tx: self.tx.clone(),
+            rx: self.rx.clone(),
+        }
+    }
+}
+
+/// Queue of likes sent as they are.
+pub type LikeQueue = Queue<Like>;
+
+impl<T: Queued> Queue<T> {
+    pub fn open(path: &str) -> Result<Queue<T>, String> {
+        let db = DBWithThreadMode::<MultiThreaded>::open_default(path)
+            .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        let (tx, rx) = async_channel::unbounded();
+        Ok(Queue {
+            db: Arc::new(db),
+            tx,
+            rx,
+        })
+    }
+
+    /// Stores the record unless the same one is pending. Returns its key if
+    /// it was stored, it's sent with `send`.
+    pub fn store(&self, record: &T) -> Option<String> {
+        let key = record.key();
+        match self.db.get(&key) {
+            Ok(Some(_)) => {
+                debug!("{} is already queued", key);
+                return None;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!("DB error: {}", e);
+                return None;
+            }
+        }
+        let message = match serde_json::to_string(record) {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Failed to serialize {}: {}", key, e);
+                return None;
+            }
+        };
+        match self.db.put(&key, message) {
+            Ok(_) => Some(key),
+            Err(e) => {
+                error!("DB error: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Schedules a stored record.
+    pub async fn send(&self, key: String, record: T) {
+        if let Err(e) = self.tx.send((key, record)).await {
+            error!("TX Error: {}", e);
+        }
+    }
+
+    /// Stores and schedules the record unless the same one is pending.
+    /// Returns whether it was queued.
+    pub async fn push(&self, record: T) -> bool {
+        match self.store(&record) {
+            Some(key) => {
+                self.send(key, record).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Records stored by the previous run. Unreadable ones are dropped.
+    pub fn pending(&self) -> Vec<(String, T)> {
+        self.db
+            .iterator(IteratorMode::Start)
+            .filter_map(|(key, value)| {
+                let key = String::from_utf8(key.to_vec()).ok()?;
+                match T::parse(&value) {
+                    Some(record) => Some((key, record)),
+                    None => {
+                        error!("Failed to parse queued {}, dropped", key);
+                        self.done(&key);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Schedules records left from the previous run.
+    pub async fn resume(&self) {
+        for (key, record) in self.pending() {
+            info!("Found pending {}", key);
+            self.send(key, record).await;
+        }
+    }
+
+    /// Next scheduled record, None once the queue is dropped.
+    pub async fn next(&self) -> Option<(String, T)> {
+        self.rx.recv().await.ok()
+    }
+
+    /// Deletes a processed record.
+    pub fn done(&self, key: &str) {
+        if let Err(e) = self.db.delete(key) {
+            error!("Failed to delete db record: {}", e);
+        }
+    }
+
+    /// Schedules the record again after a delay, e.g. when the network
+    /// couldn't be reached.
+    pub async fn retry(&self, key: String, record: T) {
+        tokio::time::sleep(Duration::from_secs(RETRY_SECONDS)).await;
+        self.send(key, record).await;
+    }
+}
+
+impl LikeQueue {
+    /// Sends likes left from the previous run and then the queued ones.
+    /// Runs until the queue is dropped.
+    pub async fn run(&self, client: Client) {
+        self.resume().await;
+        while let Some((key, like)) = self.next().await {
+            match client.forward_like(&like).await {
+                Ok(status) => {
+                    if status == "ok" {
+                        debug!("Liked {} by {}", like.target, like.id);
+                    } else {
+                        warn!(
+                            "Like of {} by {} rejected: {}",
+                            like.target, like.id, status
+                        );
+                    }
+                    self.done(&key);
+                }
+                Err(e) => {
+                    // Communications issues? - Wait and retry.
+                    error!("Failed to forward like: {}", e);
+                    self.retry(key, like).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_queue() {
+        let dir = TempDir::new("smartlike-like-queue-test");
+        let like = Like {
+            platform: "matrix".to_string(),
+            id: "@alice:example.org".to_string(),
+            target: "https://example.org/post".to_string(),
+            amount: 0.5,
+            currency: "EUR".to_string(),
+            account: None,
+            recipient: None,
+        };
+
+        let queue = LikeQueue::open(dir.path()).unwrap();
+        assert!(futures::executor::block_on(queue.push(like.clone())));
+        // Liked again while the first one is pending.
+        assert!(!futures::executor::block_on(queue.push(like.clone())));
+        let (queued_key, queued) = queue.rx.try_recv().unwrap();
+        assert_eq!(queued_key, like_key(&like));
+        assert_eq!(queued.target, like.target);
+        assert!(queue.rx.try_recv().is_err());
+        drop(queue);
+
+        // Pending likes survive restarts.
+        let queue = LikeQueue::open(dir.path()).unwrap();
+        let pending = queue.pending();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].1.id, "@alice:example.org");
+    }
+}
@@ -0,0 +1,34 @@
+//! Helpers for tests of the queue and of the crates using it.
+
+use std::path::PathBuf;
+
+/// Empty directory under the system temp directory, deleted with everything
+/// in it when dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Creates the directory, removing what a failed run may have left.
+    pub fn new(name: &str) -> TempDir {
+        let path = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir { path }
+    }
+
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap()
+    }
+
+    /// Path of an entry of the directory, e.g. of a database.
+    pub fn join(&self, name: &str) -> String {
+        self.path.join(name).to_str().unwrap().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
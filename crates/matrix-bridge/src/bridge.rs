@@ -0,0 +1,350 @@
+//! Reactions to messages with links become likes of the links, paid from the
+//! Smartlike account the reacting user connected with `!start`.
+
+use crate::matrix::{Event, Homeserver, Sync};
+use smartlike_embed_lib::client::{Client, Like};
+use smartlike_embed_lib::confirmation::{Confirmation, CONFIRM_URL};
+use smartlike_like_queue::bridge::ReactionsConfiguration;
+use uuid::Uuid;
+
+const SETTINGS_URL: &str = "https://smartlike.org/docs/how-to-connect-matrix";
+
+/// How long confirmation links are valid.
+const CONFIRMATION_TTL_SECONDS: u32 = 86400;
+
+/// `!start` parameters from the settings page, `{account}_{donation}`.
+#[derive(Debug, PartialEq)]
+pub struct Connect {
+    pub account: Uuid,
+    /// Amount in cents.
+    pub donation: u32,
+}
+
+impl Connect {
+    pub fn parse(args: &str) -> Option<Connect> {
+        let (account, donation) = args.split_once('_')?;
+        Some(Connect {
+            account: Uuid::parse_str(account).ok()?,
+            donation: donation.parse::<u32>().ok()?,
+        })
+    }
+}
+
+/// First link of a message.
+pub fn link(body: &str) -> Option<String> {
+    body.split_whitespace()
+        .map(|word| word.trim_start_matches(['<', '(']))
+        .find(|word| word.starts_with("https://") || word.starts_with("http://"))
+        .map(|word| {
+            word.trim_end_matches(['>', ')', '.', ',', '!', '?'])
+                .to_string()
+        })
+}
+
+pub struct Bridge {
+    homeserver: Homeserver,
+    client: Client,
+    reactions: ReactionsConfiguration,
+}
+
+impl Bridge {
+    pub fn new(
+        homeserver: Homeserver,
+        client: Client,
+        reactions: ReactionsConfiguration,
+    ) -> Bridge {
+        Bridge {
+            homeserver,
+            client,
+            reactions,
+        }
+    }
+
+    pub fn homeserver(&self) -> &Homeserver {
+        &self.homeserver
+    }
+
+    /// Joins rooms the bridge is invited to.
+    pub async fn join_invited(&self, sync: &Sync) {
+        for room in sync.rooms.invite.keys() {
+            info!("Joining {}", room);
+            if let Err(e) = self.homeserver.join(room).await {
+                error!("Failed to join {}: {}", room, e);
+            }
+        }
+    }
+
+    /// Joins rooms the bridge is invited to, answers commands and returns
+    /// likes of the reactions.
+    pub async fn handle(&self, sync: &Sync, now: u64) -> Vec<Like> {
+        self.join_invited(sync).await;
+        let mut likes = vec![];
+        for (room, joined) in &sync.rooms.join {
+            for event in &joined.timeline.events {
+                if event.sender == self.homeserver.user_id {
+                    continue;
+                }
+                match event.kind.as_str() {
+                    "m.room.message" => self.command(room, event, now).await,
+                    "m.reaction" => likes.extend(self.react(room, event).await),
+                    _ => {}
+                }
+            }
+        }
+        likes
+    }
+
+    async fn react(&self, room: &str, event: &Event) -> Option<Like> {
+        let (reacted, key) = event.annotation()?;
+        let amount = self.reactions.amount(key)?;
+        let message = match self.homeserver.event(room, reacted).await {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Failed to get {} in {}: {}", reacted, room, e);
+                return None;
+            }
+        };
+        Some(Like {
+            platform: "matrix".to_string(),
+            id: event.sender.clone(),
+            target: link(message.body()?)?,
+            amount,
+            currency: self.reactions.currency.clone(),
+            account: None,
+            recipient: None,
+        })
+    }
+
+    async fn command(&self, room: &str, event: &Event, now: u64) {
+        let text = event.body().unwrap_or_default().trim();
+        let (command, args) = text.split_once(' ').unwrap_or((text, ""));
+        let reply = match command {
+            "!start" if args.is_empty() => format!(
+                "Please follow the link to connect your Smartlike account: {}",
+                SETTINGS_URL
+            ),
+            "!start" => match Connect::parse(args.trim()) {
+                Some(connect) => match self.connect_url(&event.sender, &connect, now) {
+                    Ok(url) => format!(
+                        "Please follow the link to confirm connection to your Smartlike account: {}",
+                        url
+                    ),
+                    Err(e) => {
+                        error!("Failed to build confirmation link: {}", e);
+                        "Wrong parameter(s).".to_string()
+                    }
+                },
+                None => "Wrong parameter(s).".to_string(),
+            },
+            "!help" => format!(
+                "React to messages with links to support their authors. Reactions are liked with: {}. Use !start to connect your Smartlike account.",
+                self.liked_reactions()
+            ),
+            _ => return,
+        };
+        if let Err(e) = self.homeserver.send_text(room, &reply).await {
+            error!("Failed to reply in {}: {}", room, e);
+        }
+    }
+
+    /// Signed link confirming that the user's likes are paid from the account.
+    fn connect_url(&self, user: &str, connect: &Connect, now: u64) -> Result<String, String> {
+        let confirmation = Confirmation::new(
+            "matrix",
+            user,
+            &connect.account.to_string(),
+            connect.donation as f64 / 100.0,
+            now as u32,
+            CONFIRMATION_TTL_SECONDS,
+        )
+        .name(user);
+        self.client
+            .sign_confirmation(confirmation)
+            .and_then(|signed| signed.url(CONFIRM_URL))
+            .map_err(|e| e.to_string())
+    }
+
+    fn liked_reactions(&self) -> String {
+        let mut reactions: Vec<String> = self
+            .reactions
+            .amounts
+            .iter()
+            .map(|(key, amount)| format!("{} {} {}", key, amount, self.reactions.currency))
+            .collect();
+        reactions.sort();
+        reactions.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use serde_json::{json, Value};
+    use smartlike_embed_lib::confirmation::SignedConfirmation;
+    use std::sync::{Arc, Mutex};
+
+    /// Replies and joins received by the stand-in homeserver.
+    #[derive(Default)]
+    struct Received {
+        messages: Vec<(String, String)>,
+        joined: Vec<String>,
+    }
+
+    /// Local stand-in homeserver serving the recorded sync response and the
+    /// events it refers to.
+    fn homeserver(received: Arc<Mutex<Received>>) -> String {
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(web::Data::new(received.clone()))
+                .route(
+                    "/_matrix/client/v3/login",
+                    web::post().to(|| async {
+                        HttpResponse::Ok().json(json!({
+                            "access_token": "token",
+                            "user_id": "@smartlike:localhost",
+                        }))
+                    }),
+                )
+                .route(
+                    "/_matrix/client/v3/sync",
+                    web::get().to(|| async {
+                        HttpResponse::Ok()
+                            .content_type("application/json")
+                            .body(std::fs::read_to_string("./test/sync.json").unwrap())
+                    }),
+                )
+                .route(
+                    "/_matrix/client/v3/rooms/{room}/event/{event}",
+                    web::get().to(|path: web::Path<(String, String)>| async move {
+                        let events: Value = serde_json::from_str(
+                            &std::fs::read_to_string("./test/events.json").unwrap(),
+                        )
+                        .unwrap();
+                        match events.get(&path.1) {
+                            Some(event) => HttpResponse::Ok().json(event),
+                            None => HttpResponse::NotFound().json(json!({
+                                "errcode": "M_NOT_FOUND",
+                                "error": "Event not found",
+                            })),
+                        }
+                    }),
+                )
+                .route(
+                    "/_matrix/client/v3/rooms/{room}/send/m.room.message/{txn}",
+                    web::put().to(
+                        |path: web::Path<(String, String)>,
+                         body: web::Json<Value>,
+                         received: web::Data<Arc<Mutex<Received>>>| async move {
+                            received.lock().unwrap().messages.push((
+                                path.0.clone(),
+                                body["body"].as_str().unwrap_or_default().to_string(),
+                            ));
+                            HttpResponse::Ok().json(json!({ "event_id": "$reply" }))
+                        },
+                    ),
+                )
+                .route(
+                    "/_matrix/client/v3/rooms/{room}/join",
+                    web::post().to(
+                        |path: web::Path<String>,
+                         received: web::Data<Arc<Mutex<Received>>>| async move {
+                            received.lock().unwrap().joined.push(path.clone());
+                            HttpResponse::Ok().json(json!({ "room_id": path.into_inner() }))
+                        },
+                    ),
+                )
+        })
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let url = format!("http://{}", server.addrs()[0]);
+        actix_rt::spawn(server.run());
+        url
+    }
+
+    #[test]
+    fn test_links() {
+        for (body, expected) in [
+            ("https://example.org/post", Some("https://example.org/post")),
+            (
+                "Read this (https://example.org/a?b=c).",
+                Some("https://example.org/a?b=c"),
+            ),
+            ("<http://example.org>", Some("http://example.org")),
+            ("no links here", None),
+        ] {
+            assert_eq!(link(body).as_deref(), expected, "{}", body);
+        }
+        assert_eq!(
+            Connect::parse("a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7_50"),
+            Some(Connect {
+                account: Uuid::parse_str("a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7").unwrap(),
+                donation: 50,
+            })
+        );
+        assert_eq!(Connect::parse("account_50"), None);
+    }
+
+    #[actix_rt::test]
+    async fn test_bridge() {
+        let received = Arc::new(Mutex::new(Received::default()));
+        let url = homeserver(received.clone());
+        let homeserver = Homeserver::login(&url, "smartlike", "password")
+            .await
+            .unwrap();
+        assert_eq!(homeserver.user_id, "@smartlike:localhost");
+        let client = Client::new(
+            "bridge".to_string(),
+            "bridge secret".to_string(),
+            "".to_string(),
+        );
+        let bridge = Bridge::new(
+            homeserver,
+            client.clone(),
+            ReactionsConfiguration {
+                currency: "EUR".to_string(),
+                amounts: [("👍".to_string(), 0.1), ("❤️".to_string(), 0.5)]
+                    .into_iter()
+                    .collect(),
+            },
+        );
+
+        let sync = bridge.homeserver().sync(None, 0).await.unwrap();
+        assert_eq!(sync.next_batch, "s2");
+
+        // Invitations pending at the first start are accepted without
+        // answering or liking the history.
+        bridge.join_invited(&sync).await;
+        {
+            let mut received = received.lock().unwrap();
+            assert_eq!(received.joined, ["!invited:localhost"]);
+            assert!(received.messages.is_empty());
+            received.joined.clear();
+        }
+
+        let likes = bridge.handle(&sync, 1000).await;
+
+        // Reactions to messages with links, but not other reactions, reactions
+        // to messages without links or the bridge's own reactions.
+        assert_eq!(likes.len(), 2, "{:?}", likes);
+        assert_eq!(likes[0].platform, "matrix");
+        assert_eq!(likes[0].id, "@alice:localhost");
+        assert_eq!(likes[0].target, "https://example.org/post");
+        assert_eq!(likes[0].amount, 0.5);
+        assert_eq!(likes[0].currency, "EUR");
+        assert_eq!(likes[1].id, "@bob:localhost");
+        assert_eq!(likes[1].amount, 0.1);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.joined, ["!invited:localhost"]);
+        assert_eq!(received.messages.len(), 1);
+        let (room, text) = &received.messages[0];
+        assert_eq!(room, "!room:localhost");
+        let url = text.split_whitespace().last().unwrap();
+        let signed = SignedConfirmation::from_url(url).unwrap();
+        assert_eq!(signed.confirmation.platform, "matrix");
+        assert_eq!(signed.confirmation.id, "@alice:localhost");
+        assert_eq!(signed.confirmation.amount, 0.5);
+        assert!(signed.verify(&client.public_key(), 1000).is_ok());
+    }
+}
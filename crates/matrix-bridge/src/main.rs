@@ -0,0 +1,149 @@
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate log;
+
+use smartlike_embed_lib::client::Client;
+use smartlike_like_queue::LikeQueue;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs::File, io::prelude::*};
+
+mod bridge;
+mod matrix;
+
+use bridge::Bridge;
+use matrix::Homeserver;
+use smartlike_like_queue::bridge::ReactionsConfiguration;
+
+/// How long a sync waits for new events.
+const SYNC_TIMEOUT_MS: u64 = 30000;
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct Configuration {
+    homeserver_url: String,
+    /// Access token of the bridge's account. `matrix_user` and
+    /// `matrix_password` log in if empty.
+    #[serde(default)]
+    matrix_access_token: String,
+    /// Matrix id of the bridge's account, needed with an access token.
+    #[serde(default)]
+    matrix_user_id: String,
+    #[serde(default)]
+    matrix_user: String,
+    #[serde(default)]
+    matrix_password: String,
+    network_address: String,
+    smartlike_account: String,
+    smartlike_key: String,
+    #[serde(default)]
+    queue_path: String,
+    /// File keeping the position in the event stream across restarts.
+    #[serde(default)]
+    sync_token_path: String,
+    /// Liked reactions.
+    #[serde(default)]
+    reactions: ReactionsConfiguration,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let matches = clap::App::new("smartlike-matrix-bridge")
+        .version(option_env!("CARGO_PKG_VERSION").unwrap_or(""))
+        .about("smartlike-matrix-bridge")
+        .arg(
+            clap::Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .help("Configuration file")
+                .takes_value(true)
+                .default_value(""),
+        )
+        .get_matches();
+
+    let config = matches.value_of("config").unwrap();
+    let mut f = File::open(config).unwrap();
+    let mut contents = String::new();
+    f.read_to_string(&mut contents).unwrap();
+    let config = toml::from_str::<Configuration>(&contents).unwrap();
+
+    let queue = LikeQueue::open(if config.queue_path.is_empty() {
+        "./queue"
+    } else {
+        &config.queue_path
+    })
+    .unwrap();
+    let sync_token_path = if config.sync_token_path.is_empty() {
+        "./sync_token".to_string()
+    } else {
+        config.sync_token_path.clone()
+    };
+
+    let client = Client::new(
+        config.smartlike_account.clone(),
+        config.smartlike_key,
+        config.network_address,
+    );
+    tokio::spawn({
+        let queue = queue.clone();
+        let client = client.clone();
+        async move { queue.run(client).await }
+    });
+
+    let homeserver = if config.matrix_access_token.is_empty() {
+        Homeserver::login(
+            &config.homeserver_url,
+            &config.matrix_user,
+            &config.matrix_password,
+        )
+        .await
+        .unwrap()
+    } else {
+        Homeserver::new(
+            &config.homeserver_url,
+            &config.matrix_access_token,
+            &config.matrix_user_id,
+        )
+    };
+    info!("Logged in as {}", homeserver.user_id);
+    let bridge = Bridge::new(homeserver, client, config.reactions);
+
+    let mut since = std::fs::read_to_string(&sync_token_path)
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty());
+    loop {
+        let sync = match bridge
+            .homeserver()
+            .sync(since.as_deref(), SYNC_TIMEOUT_MS)
+            .await
+        {
+            Ok(sync) => sync,
+            Err(e) => {
+                error!("Failed to sync: {}", e);
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        // The first sync returns recent history, which isn't liked again.
+        // Invitations pending since before the start are still accepted.
+        if since.is_some() {
+            for like in bridge.handle(&sync, now()).await {
+                queue.push(like).await;
+            }
+        } else {
+            bridge.join_invited(&sync).await;
+        }
+        if let Err(e) = std::fs::write(&sync_token_path, &sync.next_batch) {
+            error!("Failed to save sync token: {}", e);
+        }
+        since = Some(sync.next_batch);
+    }
+}
@@ -0,0 +1,202 @@
+//! Matrix client-server API calls the bridge needs.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const API: &str = "/_matrix/client/v3";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Event {
+    #[serde(default)]
+    pub sender: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub content: Value,
+}
+
+impl Event {
+    /// Text of a message.
+    pub fn body(&self) -> Option<&str> {
+        self.content.get("body")?.as_str()
+    }
+
+    /// Reacted event and the reaction key, e.g. an emoji.
+    pub fn annotation(&self) -> Option<(&str, &str)> {
+        let relation = self.content.get("m.relates_to")?;
+        if relation.get("rel_type")?.as_str()? != "m.annotation" {
+            return None;
+        }
+        Some((
+            relation.get("event_id")?.as_str()?,
+            relation.get("key")?.as_str()?,
+        ))
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Timeline {
+    #[serde(default)]
+    pub events: Vec<Event>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct JoinedRoom {
+    #[serde(default)]
+    pub timeline: Timeline,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Rooms {
+    #[serde(default)]
+    pub join: HashMap<String, JoinedRoom>,
+    #[serde(default)]
+    pub invite: HashMap<String, Value>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Sync {
+    pub next_batch: String,
+    #[serde(default)]
+    pub rooms: Rooms,
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    access_token: String,
+    user_id: String,
+}
+
+pub struct Homeserver {
+    url: String,
+    access_token: String,
+    /// Matrix id of the bridge.
+    pub user_id: String,
+    http: reqwest::Client,
+    txn: AtomicU64,
+}
+
+fn room_path(room: &str) -> String {
+    format!("{}/rooms/{}", API, urlencoding::encode(room))
+}
+
+async fn json<T: serde::de::DeserializeOwned>(
+    response: Result<reqwest::Response, reqwest::Error>,
+) -> Result<T, String> {
+    let response = response.map_err(|e| format!("Request failed: {}", e))?;
+    let status = response.status();
+    let body = response
+        .text()
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+    if !status.is_success() {
+        return Err(format!("{}: {}", status, body));
+    }
+    serde_json::from_str(&body).map_err(|e| format!("Failed to parse {}: {}", body, e))
+}
+
+impl Homeserver {
+    pub fn new(url: &str, access_token: &str, user_id: &str) -> Homeserver {
+        // Transaction ids have to be unique across restarts.
+        let start = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        Homeserver {
+            url: url.trim_end_matches('/').to_string(),
+            access_token: access_token.to_string(),
+            user_id: user_id.to_string(),
+            http: reqwest::Client::new(),
+            txn: AtomicU64::new(start),
+        }
+    }
+
+    /// Logs in with a password.
+    pub async fn login(url: &str, user: &str, password: &str) -> Result<Homeserver, String> {
+        let request = json!({
+            "type": "m.login.password",
+            "identifier": { "type": "m.id.user", "user": user },
+            "password": password,
+            "initial_device_display_name": "Smartlike bridge",
+        });
+        let response: LoginResponse = json(
+            reqwest::Client::new()
+                .post(format!("{}{}/login", url.trim_end_matches('/'), API))
+                .json(&request)
+                .send()
+                .await,
+        )
+        .await?;
+        Ok(Homeserver::new(
+            url,
+            &response.access_token,
+            &response.user_id,
+        ))
+    }
+
+    /// Events since the batch, waiting up to `timeout_ms` for new ones.
+    pub async fn sync(&self, since: Option<&str>, timeout_ms: u64) -> Result<Sync, String> {
+        let mut query = vec![("timeout", timeout_ms.to_string())];
+        if let Some(since) = since {
+            query.push(("since", since.to_string()));
+        }
+        json(
+            self.http
+                .get(format!("{}{}/sync", self.url, API))
+                .bearer_auth(&self.access_token)
+                .query(&query)
+                .send()
+                .await,
+        )
+        .await
+    }
+
+    pub async fn event(&self, room: &str, event_id: &str) -> Result<Event, String> {
+        json(
+            self.http
+                .get(format!(
+                    "{}{}/event/{}",
+                    self.url,
+                    room_path(room),
+                    urlencoding::encode(event_id)
+                ))
+                .bearer_auth(&self.access_token)
+                .send()
+                .await,
+        )
+        .await
+    }
+
+    pub async fn join(&self, room: &str) -> Result<(), String> {
+        json::<Value>(
+            self.http
+                .post(format!("{}{}/join", self.url, room_path(room)))
+                .bearer_auth(&self.access_token)
+                .json(&json!({}))
+                .send()
+                .await,
+        )
+        .await
+        .map(|_| ())
+    }
+
+    pub async fn send_text(&self, room: &str, text: &str) -> Result<(), String> {
+        let txn = self.txn.fetch_add(1, Ordering::Relaxed);
+        json::<Value>(
+            self.http
+                .put(format!(
+                    "{}{}/send/m.room.message/{}",
+                    self.url,
+                    room_path(room),
+                    txn
+                ))
+                .bearer_auth(&self.access_token)
+                .json(&json!({ "msgtype": "m.notice", "body": text }))
+                .send()
+                .await,
+        )
+        .await
+        .map(|_| ())
+    }
+}
@@ -0,0 +1,308 @@
+//! Verified reactions of users who published their Smartlike account in
+//! their profile become likes of the reacted notes.
+//!
+//! Profiles are fetched from the relay the reaction came from, so reactions
+//! of users the bridge doesn't know wait for the end of the profile
+//! subscription. Subscriptions are kept per relay and dropped when the
+//! relay disconnects, the reactions that waited for them are received again
+//! after the reconnection.
+
+use crate::nostr::{self, Event, RelayMessage, KIND_PROFILE, KIND_REACTION};
+use serde_json::json;
+use smartlike_embed_lib::client::Like;
+use smartlike_like_queue::bridge::{self, ReactionsConfiguration, PROFILE_TTL_SECONDS};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Subscription to reactions.
+pub const REACTIONS: &str = "reactions";
+
+/// Reactions remembered to skip copies from other relays.
+const SEEN_SIZE: usize = 10000;
+
+/// Amount of the reaction, e.g. "+" = 0.1 or "🤙" = 0.5.
+fn amount(reactions: &ReactionsConfiguration, content: &str) -> Option<f64> {
+    // An empty reaction is a like.
+    reactions.amount(if content.is_empty() { "+" } else { content })
+}
+
+/// Smartlike account published in a kind-0 profile.
+pub fn account(profile: &Event) -> Option<String> {
+    let content: serde_json::Value = serde_json::from_str(&profile.content).ok()?;
+    let about = content.get("about")?.as_str()?;
+    bridge::account(about)
+}
+
+struct Profile {
+    account: Option<String>,
+    created_at: u64,
+    checked: u64,
+}
+
+/// Likes to forward and requests to send to the relay.
+#[derive(Default, Debug)]
+pub struct Handled {
+    pub likes: Vec<Like>,
+    pub requests: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct Bridge {
+    reactions: ReactionsConfiguration,
+    profiles: HashMap<String, Profile>,
+    /// Profile subscriptions by relay and subscription id, and their public
+    /// keys.
+    subscriptions: HashMap<(String, String), String>,
+    /// Reactions waiting for the profile of their author by relay and public
+    /// key.
+    waiting: HashMap<(String, String), Vec<Event>>,
+    seen: HashSet<String>,
+    seen_order: VecDeque<String>,
+}
+
+impl Bridge {
+    pub fn new(reactions: ReactionsConfiguration) -> Bridge {
+        Bridge {
+            reactions,
+            ..Default::default()
+        }
+    }
+
+    /// Subscription to reactions published since the time.
+    pub fn subscribe(since: u64) -> String {
+        nostr::request(
+            REACTIONS,
+            json!({ "kinds": [KIND_REACTION], "since": since }),
+        )
+    }
+
+    /// Handles a message received from the relay.
+    pub fn handle(&mut self, relay: &str, text: &str, now: u64) -> Handled {
+        match RelayMessage::parse(text) {
+            Ok(RelayMessage::Event(subscription, event)) => {
+                if subscription == REACTIONS && event.kind == KIND_REACTION {
+                    return self.reaction(relay, event, now);
+                }
+                if event.kind == KIND_PROFILE
+                    && self.subscriptions.get(&(relay.to_string(), subscription))
+                        == Some(&event.pubkey)
+                {
+                    self.profile(event, now);
+                }
+                Handled::default()
+            }
+            Ok(RelayMessage::Eose(subscription)) => {
+                match self
+                    .subscriptions
+                    .remove(&(relay.to_string(), subscription.clone()))
+                {
+                    Some(pubkey) => {
+                        let mut handled = self.profile_loaded(relay, &pubkey, now);
+                        handled.requests.push(nostr::close(&subscription));
+                        handled
+                    }
+                    None => Handled::default(),
+                }
+            }
+            Ok(RelayMessage::Notice(notice)) => {
+                warn!("Relay notice: {}", notice);
+                Handled::default()
+            }
+            Ok(RelayMessage::Other) => Handled::default(),
+            Err(e) => {
+                warn!("{}", e);
+                Handled::default()
+            }
+        }
+    }
+
+    /// Drops the profile subscriptions of the disconnected relay. The
+    /// reactions waiting for them are forgotten, so that they are handled
+    /// again when the relay sends them after the reconnection.
+    pub fn disconnected(&mut self, relay: &str) {
+        self.subscriptions.retain(|(url, _), _| url != relay);
+        let mut forgotten = HashSet::new();
+        self.waiting.retain(|(url, _), events| {
+            if url != relay {
+                return true;
+            }
+            forgotten.extend(events.drain(..).map(|event| event.id));
+            false
+        });
+        if !forgotten.is_empty() {
+            self.seen.retain(|id| !forgotten.contains(id));
+            self.seen_order.retain(|id| !forgotten.contains(id));
+        }
+    }
+
+    fn reaction(&mut self, relay: &str, event: Event, now: u64) -> Handled {
+        let mut handled = Handled::default();
+        if amount(&self.reactions, &event.content).is_none() || self.seen.contains(&event.id) {
+            return handled;
+        }
+        if let Err(e) = event.verify() {
+            warn!("{}", e);
+            return handled;
+        }
+        self.remember(&event.id);
+        match self.profiles.get(&event.pubkey) {
+            Some(profile) if now < profile.checked + PROFILE_TTL_SECONDS => {
+                handled
+                    .likes
+                    .extend(self.like(&event, profile.account.as_deref()));
+            }
+            _ => {
+                let waiting = self
+                    .waiting
+                    .entry((relay.to_string(), event.pubkey.clone()))
+                    .or_default();
+                if waiting.is_empty() {
+                    let subscription = format!("profile-{}", &event.pubkey[..16]);
+                    handled.requests.push(nostr::request(
+                        &subscription,
+                        json!({ "kinds": [KIND_PROFILE], "authors": [event.pubkey], "limit": 1 }),
+                    ));
+                    self.subscriptions
+                        .insert((relay.to_string(), subscription), event.pubkey.clone());
+                }
+                waiting.push(event);
+            }
+        }
+        handled
+    }
+
+    fn profile(&mut self, event: Event, now: u64) {
+        if let Err(e) = event.verify() {
+            warn!("{}", e);
+            return;
+        }
+        // Profiles are replaceable, older versions may still be stored.
+        if matches!(self.profiles.get(&event.pubkey), Some(profile) if profile.created_at > event.created_at)
+        {
+            return;
+        }
+        let account = account(&event);
+        debug!("Account of {}: {:?}", event.pubkey, account);
+        self.profiles.insert(
+            event.pubkey.clone(),
+            Profile {
+                account,
+                created_at: event.created_at,
+                checked: now,
+            },
+        );
+    }
+
+    /// Likes of reactions that waited for the profile.
+    fn profile_loaded(&mut self, relay: &str, pubkey: &str, now: u64) -> Handled {
+        let profile = self.profiles.entry(pubkey.to_string()).or_insert(Profile {
+            account: None,
+            created_at: 0,
+            checked: now,
+        });
+        // Also when the relay has no profile of the user.
+        profile.checked = now;
+        let account = profile.account.clone();
+        let waiting = self
+            .waiting
+            .remove(&(relay.to_string(), pubkey.to_string()))
+            .unwrap_or_default();
+        Handled {
+            likes: waiting
+                .iter()
+                .filter_map(|event| self.like(event, account.as_deref()))
+                .collect(),
+            requests: vec![],
+        }
+    }
+
+    /// Like paid from the account of the verified profile. Nostr keys aren't
+    /// connected on the network, so the account is sent with the like.
+    fn like(&self, event: &Event, account: Option<&str>) -> Option<Like> {
+        let account = match account {
+            Some(account) => account,
+            None => {
+                debug!("{} has no Smartlike account", event.pubkey);
+                return None;
+            }
+        };
+        Some(Like {
+            platform: "nostr".to_string(),
+            id: event.pubkey.clone(),
+            target: nostr::note_link(event.tag("e")?)?,
+            amount: amount(&self.reactions, &event.content)?,
+            currency: self.reactions.currency.clone(),
+            account: Some(account.to_string()),
+            recipient: None,
+        })
+    }
+
+    fn remember(&mut self, id: &str) {
+        self.seen.insert(id.to_string());
+        self.seen_order.push_back(id.to_string());
+        if self.seen_order.len() > SEEN_SIZE {
+            if let Some(oldest) = self.seen_order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr::tests::signed;
+
+    const NOTE: &str = "6ee1b6d4c5a0cdbd0a47d1e1a3d4b6bb1e0a6a7b19b1c55bd7a4e1d3dbe3b7c2";
+
+    #[test]
+    fn test_disconnected() {
+        let mut bridge = Bridge::new(ReactionsConfiguration {
+            currency: "USD".to_string(),
+            amounts: [("+".to_string(), 0.1)].into_iter().collect(),
+        });
+        let reaction = signed(1, 1001, KIND_REACTION, &[&["e", NOTE]], "+");
+        let received = json!(["EVENT", REACTIONS, reaction]).to_string();
+        let subscription = format!("profile-{}", &reaction.pubkey[..16]);
+        let profile = signed(
+            1,
+            900,
+            KIND_PROFILE,
+            &[],
+            r#"{"about": "Smartlike: a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7"}"#,
+        );
+
+        let handled = bridge.handle("wss://a", &received, 1000);
+        assert_eq!(handled.requests.len(), 1);
+        // A copy from another relay waits for the same profile.
+        assert!(bridge
+            .handle("wss://b", &received, 1000)
+            .requests
+            .is_empty());
+
+        // The profile subscription doesn't survive the connection.
+        bridge.disconnected("wss://a");
+        let late = json!(["EVENT", subscription, profile]).to_string();
+        bridge.handle("wss://a", &late, 1000);
+        let eose = json!(["EOSE", subscription]).to_string();
+        assert!(bridge.handle("wss://a", &eose, 1000).likes.is_empty());
+
+        // The reaction sent again after the reconnection is handled.
+        let handled = bridge.handle("wss://a", &received, 1000);
+        assert_eq!(
+            handled.requests,
+            vec![nostr::request(
+                &subscription,
+                json!({ "kinds": [KIND_PROFILE], "authors": [reaction.pubkey], "limit": 1 }),
+            )]
+        );
+        bridge.handle("wss://a", &late, 1000);
+        let handled = bridge.handle("wss://a", &eose, 1000);
+        assert_eq!(handled.likes.len(), 1);
+        assert_eq!(
+            handled.likes[0].account.as_deref(),
+            Some("a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7")
+        );
+        assert_eq!(handled.requests, vec![nostr::close(&subscription)]);
+        assert!(bridge.handle("wss://a", &received, 1000).likes.is_empty());
+    }
+}
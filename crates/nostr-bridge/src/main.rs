@@ -0,0 +1,101 @@
+#[macro_use]
+extern crate serde;
+#[macro_use]
+extern crate log;
+
+use smartlike_embed_lib::client::Client;
+use smartlike_like_queue::bridge::ReactionsConfiguration;
+use smartlike_like_queue::LikeQueue;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{fs::File, io::prelude::*};
+
+mod bridge;
+mod nostr;
+mod relay;
+
+use bridge::Bridge;
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct Configuration {
+    /// Relays to read reactions from, e.g. "wss://relay.damus.io".
+    relays: Vec<String>,
+    network_address: String,
+    smartlike_account: String,
+    smartlike_key: String,
+    #[serde(default)]
+    queue_path: String,
+    /// Liked reactions.
+    #[serde(default)]
+    reactions: ReactionsConfiguration,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::init();
+    let matches = clap::App::new("smartlike-nostr-bridge")
+        .version(option_env!("CARGO_PKG_VERSION").unwrap_or(""))
+        .about("smartlike-nostr-bridge")
+        .arg(
+            clap::Arg::with_name("config")
+                .short("c")
+                .long("config")
+                .help("Configuration file")
+                .takes_value(true)
+                .default_value(""),
+        )
+        .get_matches();
+
+    let config = matches.value_of("config").unwrap();
+    let mut f = File::open(config).unwrap();
+    let mut contents = String::new();
+    f.read_to_string(&mut contents).unwrap();
+    let config = toml::from_str::<Configuration>(&contents).unwrap();
+
+    let queue = LikeQueue::open(if config.queue_path.is_empty() {
+        "./queue"
+    } else {
+        &config.queue_path
+    })
+    .unwrap();
+    let client = Client::new(
+        config.smartlike_account.clone(),
+        config.smartlike_key,
+        config.network_address,
+    );
+    tokio::spawn({
+        let queue = queue.clone();
+        async move { queue.run(client).await }
+    });
+
+    let bridge = Arc::new(Mutex::new(Bridge::new(config.reactions)));
+    let (tx, rx) = async_channel::unbounded();
+    let started = now();
+    for url in config.relays {
+        let bridge = bridge.clone();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            // Reactions published before the start aren't liked, the ones
+            // published while disconnected are.
+            let mut since = started;
+            loop {
+                match relay::listen(&url, &mut since, &bridge, &tx).await {
+                    Ok(_) => warn!("{} closed the connection", url),
+                    Err(e) => error!("{}", e),
+                }
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        });
+    }
+
+    while let Ok(like) = rx.recv().await {
+        queue.push(like).await;
+    }
+}
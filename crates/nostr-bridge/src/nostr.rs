@@ -0,0 +1,211 @@
+//! Nostr events and relay messages (NIP-01).
+
+use bech32::ToBase32;
+use secp256k1::{schnorr, Message, Secp256k1, XOnlyPublicKey};
+use serde_json::{json, Value};
+use sha2::Digest;
+
+pub const KIND_PROFILE: u32 = 0;
+pub const KIND_REACTION: u32 = 7;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Event {
+    pub id: String,
+    pub pubkey: String,
+    pub created_at: u64,
+    pub kind: u32,
+    #[serde(default)]
+    pub tags: Vec<Vec<String>>,
+    #[serde(default)]
+    pub content: String,
+    pub sig: String,
+}
+
+impl Event {
+    /// Hash of the serialized event the id has to match.
+    pub fn hash(&self) -> [u8; 32] {
+        let serialized = json!([
+            0,
+            self.pubkey,
+            self.created_at,
+            self.kind,
+            self.tags,
+            self.content
+        ])
+        .to_string();
+        let mut hasher = sha2::Sha256::new();
+        sha2::Digest::input(&mut hasher, serialized.as_bytes());
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(hasher.result().as_slice());
+        hash
+    }
+
+    /// Checks the id and the Schnorr signature of the author.
+    pub fn verify(&self) -> Result<(), String> {
+        let hash = self.hash();
+        if hex::encode(hash) != self.id {
+            return Err(format!("Wrong id of {}", self.id));
+        }
+        let pubkey = hex::decode(&self.pubkey)
+            .ok()
+            .and_then(|key| XOnlyPublicKey::from_slice(&key).ok())
+            .ok_or_else(|| format!("Invalid public key of {}", self.id))?;
+        let sig = hex::decode(&self.sig)
+            .ok()
+            .and_then(|sig| schnorr::Signature::from_slice(&sig).ok())
+            .ok_or_else(|| format!("Invalid signature of {}", self.id))?;
+        let message = Message::from_slice(&hash).map_err(|e| e.to_string())?;
+        Secp256k1::verification_only()
+            .verify_schnorr(&sig, &message, &pubkey)
+            .map_err(|e| format!("Wrong signature of {}: {}", self.id, e))
+    }
+
+    /// Value of the last tag with the name, e.g. the reacted event of `e`.
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .rev()
+            .find(|tag| tag.first().map(String::as_str) == Some(name))
+            .and_then(|tag| tag.get(1))
+            .map(String::as_str)
+    }
+}
+
+/// NIP-21 link of a note, `nostr:note1...`.
+pub fn note_link(id: &str) -> Option<String> {
+    let id = hex::decode(id).ok().filter(|id| id.len() == 32)?;
+    bech32::encode("note", id.to_base32(), bech32::Variant::Bech32)
+        .ok()
+        .map(|note| format!("nostr:{}", note))
+}
+
+/// Message received from a relay.
+#[derive(Debug, PartialEq)]
+pub enum RelayMessage {
+    Event(String, Event),
+    /// End of stored events of the subscription.
+    Eose(String),
+    Notice(String),
+    Other,
+}
+
+impl RelayMessage {
+    pub fn parse(text: &str) -> Result<RelayMessage, String> {
+        let message: Vec<Value> =
+            serde_json::from_str(text).map_err(|e| format!("Invalid message {}: {}", text, e))?;
+        let subscription = || {
+            message
+                .get(1)
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        Ok(match message.first().and_then(Value::as_str) {
+            Some("EVENT") => {
+                let event = message
+                    .get(2)
+                    .cloned()
+                    .ok_or_else(|| format!("Event missing in {}", text))?;
+                RelayMessage::Event(
+                    subscription(),
+                    serde_json::from_value(event)
+                        .map_err(|e| format!("Invalid event {}: {}", text, e))?,
+                )
+            }
+            Some("EOSE") => RelayMessage::Eose(subscription()),
+            Some("NOTICE") => RelayMessage::Notice(subscription()),
+            _ => RelayMessage::Other,
+        })
+    }
+}
+
+pub fn request(subscription: &str, filter: Value) -> String {
+    json!(["REQ", subscription, filter]).to_string()
+}
+
+pub fn close(subscription: &str) -> String {
+    json!(["CLOSE", subscription]).to_string()
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use secp256k1::KeyPair;
+
+    /// Event signed with the secret key.
+    pub fn signed(
+        secret: u8,
+        created_at: u64,
+        kind: u32,
+        tags: &[&[&str]],
+        content: &str,
+    ) -> Event {
+        let secp = Secp256k1::new();
+        let keys = KeyPair::from_seckey_slice(&secp, &[secret; 32]).unwrap();
+        let mut event = Event {
+            id: String::new(),
+            pubkey: keys.x_only_public_key().0.to_string(),
+            created_at,
+            kind,
+            tags: tags
+                .iter()
+                .map(|tag| tag.iter().map(|v| v.to_string()).collect())
+                .collect(),
+            content: content.to_string(),
+            sig: String::new(),
+        };
+        let hash = event.hash();
+        event.id = hex::encode(hash);
+        event.sig = secp
+            .sign_schnorr_no_aux_rand(&Message::from_slice(&hash).unwrap(), &keys)
+            .to_string();
+        event
+    }
+
+    #[test]
+    fn test_events() {
+        let event = signed(
+            1,
+            1000,
+            KIND_REACTION,
+            &[&["e", "aa"], &["e", "bb"], &["p", "cc"]],
+            "+",
+        );
+        assert!(event.verify().is_ok());
+        assert_eq!(event.tag("e"), Some("bb"));
+        assert_eq!(event.tag("p"), Some("cc"));
+        assert_eq!(event.tag("a"), None);
+
+        let tampered = Event {
+            content: "-".to_string(),
+            ..event.clone()
+        };
+        assert!(tampered.verify().is_err());
+        let other = signed(2, 1000, KIND_REACTION, &[], "+");
+        let forged = Event {
+            pubkey: other.pubkey,
+            ..event.clone()
+        };
+        assert!(forged.verify().is_err());
+
+        let message = format!(
+            r#"["EVENT","reactions",{}]"#,
+            serde_json::to_string(&event).unwrap()
+        );
+        assert_eq!(
+            RelayMessage::parse(&message).unwrap(),
+            RelayMessage::Event("reactions".to_string(), event)
+        );
+        assert_eq!(
+            RelayMessage::parse(r#"["EOSE","reactions"]"#).unwrap(),
+            RelayMessage::Eose("reactions".to_string())
+        );
+        assert!(RelayMessage::parse("EVENT").is_err());
+
+        assert_eq!(
+            note_link("0000000000000000000000000000000000000000000000000000000000000000").unwrap(),
+            "nostr:note1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqn2l0z3"
+        );
+        assert!(note_link("00").is_none());
+    }
+}
@@ -0,0 +1,180 @@
+//! Connection to a relay.
+
+use crate::bridge::Bridge;
+use crate::now;
+use futures::{SinkExt, StreamExt};
+use smartlike_embed_lib::client::Like;
+use std::sync::Mutex;
+use tokio_tungstenite::tungstenite::{Error, Message};
+
+/// Reads reactions published since the time until the relay closes the
+/// connection, sending likes of the verified ones to `likes`. Once
+/// subscribed, `since` moves to the time of the subscription, so that the
+/// next connection gets what was published in between. Profile
+/// subscriptions left open are dropped when the connection ends.
+pub async fn listen(
+    url: &str,
+    since: &mut u64,
+    bridge: &Mutex<Bridge>,
+    likes: &async_channel::Sender<Like>,
+) -> Result<(), String> {
+    let (mut socket, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", url, e))?;
+    let subscribed = now();
+    socket
+        .send(Message::Text(Bridge::subscribe(*since)))
+        .await
+        .map_err(|e| format!("Failed to subscribe to {}: {}", url, e))?;
+    *since = subscribed;
+    let result = receive(&mut socket, url, bridge, likes).await;
+    bridge.lock().unwrap().disconnected(url);
+    result
+}
+
+async fn receive<S>(
+    socket: &mut S,
+    url: &str,
+    bridge: &Mutex<Bridge>,
+    likes: &async_channel::Sender<Like>,
+) -> Result<(), String>
+where
+    S: StreamExt<Item = Result<Message, Error>> + SinkExt<Message, Error = Error> + Unpin,
+{
+    while let Some(message) = socket.next().await {
+        let handled = match message.map_err(|e| format!("{}: {}", url, e))? {
+            Message::Text(text) => bridge.lock().unwrap().handle(url, &text, now()),
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        for request in handled.requests {
+            socket
+                .send(Message::Text(request))
+                .await
+                .map_err(|e| format!("Failed to send to {}: {}", url, e))?;
+        }
+        for like in handled.likes {
+            likes
+                .send(like)
+                .await
+                .map_err(|e| format!("TX Error: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nostr::tests::signed;
+    use crate::nostr::{Event, KIND_PROFILE, KIND_REACTION};
+    use serde_json::{json, Value};
+    use smartlike_like_queue::bridge::ReactionsConfiguration;
+    use tokio::net::TcpListener;
+
+    const NOTE: &str = "6ee1b6d4c5a0cdbd0a47d1e1a3d4b6bb1e0a6a7b19b1c55bd7a4e1d3dbe3b7c2";
+    const OTHER_NOTE: &str = "1d3dbe3b7c26ee1b6d4c5a0cdbd0a47d1e1a3d4b6bb1e0a6a7b19b1c55bd7a4e";
+
+    fn matches(filter: &Value, event: &Event) -> bool {
+        let kinds = filter["kinds"].as_array().cloned().unwrap_or_default();
+        let authors = filter["authors"].as_array().cloned().unwrap_or_default();
+        kinds.contains(&json!(event.kind))
+            && (authors.is_empty() || authors.contains(&json!(event.pubkey)))
+    }
+
+    /// Local stand-in relay answering subscriptions from the stored events.
+    /// Closes the connection once the bridge closed `closes` subscriptions.
+    async fn relay(events: Vec<Event>, closes: usize) -> (String, tokio::task::JoinHandle<usize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let (mut requests, mut closed) = (0, 0);
+            while let Some(Ok(Message::Text(text))) = socket.next().await {
+                let message: Vec<Value> = serde_json::from_str(&text).unwrap();
+                let subscription = message[1].as_str().unwrap();
+                match message[0].as_str().unwrap() {
+                    "REQ" => {
+                        requests += 1;
+                        for event in events.iter().filter(|e| matches(&message[2], e)) {
+                            let frame = json!(["EVENT", subscription, event]).to_string();
+                            socket.send(Message::Text(frame)).await.unwrap();
+                        }
+                        let eose = json!(["EOSE", subscription]).to_string();
+                        socket.send(Message::Text(eose)).await.unwrap();
+                    }
+                    "CLOSE" => {
+                        closed += 1;
+                        if closed == closes {
+                            socket.close(None).await.unwrap();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn test_relay() {
+        let account = "a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7";
+        let alice = |created_at, tags: &[&[&str]], content: &str| {
+            signed(1, created_at, KIND_REACTION, tags, content)
+        };
+        let liked = alice(1001, &[&["e", NOTE], &["p", "cc"]], "+");
+        let mut forged = alice(1002, &[&["e", OTHER_NOTE]], "+");
+        forged.content = "🤙".to_string();
+        let events = vec![
+            signed(1, 900, KIND_PROFILE, &[], r#"{"name": "alice"}"#),
+            signed(
+                1,
+                950,
+                KIND_PROFILE,
+                &[],
+                &json!({ "name": "alice", "about": format!("Writer. Smartlike: {}", account) })
+                    .to_string(),
+            ),
+            signed(
+                2,
+                900,
+                KIND_PROFILE,
+                &[],
+                r#"{"name": "bob", "about": "Reader"}"#,
+            ),
+            liked.clone(),
+            // Copy from another relay.
+            liked,
+            alice(1003, &[&["e", OTHER_NOTE]], "🤙"),
+            alice(1004, &[&["e", OTHER_NOTE]], "-"),
+            alice(1005, &[&["e", OTHER_NOTE]], "🔥"),
+            forged,
+            signed(2, 1006, KIND_REACTION, &[&["e", NOTE]], "+"),
+        ];
+
+        let (url, server) = relay(events, 2).await;
+        let bridge = Mutex::new(Bridge::new(ReactionsConfiguration {
+            currency: "USD".to_string(),
+            amounts: [("+".to_string(), 0.1), ("🤙".to_string(), 0.5)]
+                .into_iter()
+                .collect(),
+        }));
+        let (tx, rx) = async_channel::unbounded();
+        let mut since = 1000;
+        listen(&url, &mut since, &bridge, &tx).await.unwrap();
+        assert!(since > 1000);
+        // Reactions and the profiles of alice and bob.
+        assert_eq!(server.await.unwrap(), 3);
+
+        let likes: Vec<Like> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(likes.len(), 2, "{:?}", likes);
+        assert_eq!(likes[0].platform, "nostr");
+        assert_eq!(likes[0].id, signed(1, 0, 0, &[], "").pubkey);
+        assert_eq!(likes[0].target, crate::nostr::note_link(NOTE).unwrap());
+        assert_eq!(likes[0].amount, 0.1);
+        assert_eq!(likes[0].currency, "USD");
+        assert_eq!(likes[1].amount, 0.5);
+    }
+}
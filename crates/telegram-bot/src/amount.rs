@@ -0,0 +1,260 @@
+//! Donation amounts of forwarded posts.
+//!
+//! A forward is held for a few seconds so that `/amount` sent right after it
+//! can still change the amount. A comment typed when forwarding arrives before
+//! the forward itself and applies to it directly.
+
+use crate::queue::QueuedLike;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct Amount {
+    pub amount: f64,
+    pub currency: String,
+}
+
+/// Parses "0.5 EUR", "0,5 eur" or "0.5". Returns the amount and the currency
+/// if given.
+pub fn parse(text: &str) -> Option<(f64, Option<String>)> {
+    let mut parts = text.split_whitespace();
+    let amount = parts.next()?.replace(',', ".").parse::<f64>().ok()?;
+    let currency = parts.next().map(|c| c.to_uppercase());
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((amount, currency))
+}
+
+/// Reasons why an amount is rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AmountError {
+    NotPositive,
+    TooLarge,
+    UnsupportedCurrency,
+}
+
+impl AmountError {
+    /// Message key of the error.
+    pub fn key(&self) -> &'static str {
+        match self {
+            AmountError::NotPositive => "amount_not_positive",
+            AmountError::TooLarge => "amount_too_large",
+            AmountError::UnsupportedCurrency => "amount_unsupported_currency",
+        }
+    }
+}
+
+/// Checks the amount against the configured limits.
+pub fn validate(
+    amount: f64,
+    currency: &str,
+    allowed: &[String],
+    max_amount: f64,
+) -> Result<Amount, AmountError> {
+    if !amount.is_finite() || amount <= 0.0 {
+        return Err(AmountError::NotPositive);
+    }
+    if max_amount > 0.0 && amount > max_amount {
+        return Err(AmountError::TooLarge);
+    }
+    if !allowed.is_empty() && !allowed.iter().any(|c| c == currency) {
+        return Err(AmountError::UnsupportedCurrency);
+    }
+    Ok(Amount {
+        amount,
+        currency: currency.to_string(),
+    })
+}
+
+struct Draft {
+    amount: Option<(Amount, u64)>,
+    likes: Vec<(QueuedLike, u64)>,
+}
+
+/// Forwards waiting for an amount, per user.
+pub struct Drafts {
+    wait_seconds: u64,
+    drafts: HashMap<String, Draft>,
+}
+
+impl Drafts {
+    pub fn new(wait_seconds: u64) -> Drafts {
+        Drafts {
+            wait_seconds,
+            drafts: HashMap::new(),
+        }
+    }
+
+    fn draft(&mut self, user: &str) -> &mut Draft {
+        self.drafts.entry(user.to_string()).or_insert(Draft {
+            amount: None,
+            likes: vec![],
+        })
+    }
+
+    /// Applies the amount to held forwards and returns them. The amount is
+    /// kept for the next forward if there are none.
+    pub fn set_amount(&mut self, user: &str, amount: Amount, now: u64) -> Vec<QueuedLike> {
+        let draft = self.draft(user);
+        if draft.likes.is_empty() {
+            draft.amount = Some((amount, now));
+            return vec![];
+        }
+        let likes = draft
+            .likes
+            .drain(..)
+            .map(|(mut queued, _)| {
+                queued.like.amount = amount.amount;
+                queued.like.currency = amount.currency.clone();
+                queued
+            })
+            .collect();
+        self.drafts.remove(user);
+        likes
+    }
+
+    /// Returns the like if it's ready to be sent, otherwise holds it.
+    pub fn add_forward(&mut self, mut queued: QueuedLike, now: u64) -> Option<QueuedLike> {
+        let wait_seconds = self.wait_seconds;
+        let draft = self.draft(&queued.like.id);
+        match draft.amount.take() {
+            Some((amount, ts)) if ts + wait_seconds >= now => {
+                queued.like.amount = amount.amount;
+                queued.like.currency = amount.currency;
+                Some(queued)
+            }
+            _ if wait_seconds == 0 => Some(queued),
+            _ => {
+                draft.likes.push((queued, now));
+                None
+            }
+        }
+    }
+
+    /// Drops the last held forward of the user and returns it.
+    pub fn undo(&mut self, user: &str) -> Option<QueuedLike> {
+        let draft = self.drafts.get_mut(user)?;
+        let (queued, _) = draft.likes.pop()?;
+        if draft.likes.is_empty() && draft.amount.is_none() {
+            self.drafts.remove(user);
+        }
+        Some(queued)
+    }
+
+    /// Returns held forwards whose waiting time is over.
+    pub fn expired(&mut self, now: u64) -> Vec<QueuedLike> {
+        let wait_seconds = self.wait_seconds;
+        let mut likes = vec![];
+        for draft in self.drafts.values_mut() {
+            let (ready, held) = draft
+                .likes
+                .drain(..)
+                .partition(|(_, ts)| ts + wait_seconds <= now);
+            draft.likes = held;
+            likes.extend(ready.into_iter().map(|(like, _)| like));
+            if matches!(draft.amount, Some((_, ts)) if ts + wait_seconds < now) {
+                draft.amount = None;
+            }
+        }
+        self.drafts
+            .retain(|_, draft| !draft.likes.is_empty() || draft.amount.is_some());
+        likes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smartlike_embed_lib::client::Like;
+
+    fn like(user: &str, target: &str) -> QueuedLike {
+        let like = Like {
+            platform: "telegram".to_string(),
+            id: user.to_string(),
+            target: target.to_string(),
+            amount: 0.0,
+            currency: "".to_string(),
+            account: None,
+            recipient: None,
+        };
+        QueuedLike::new(like, 1, 1)
+    }
+
+    fn eur(amount: f64) -> Amount {
+        Amount {
+            amount,
+            currency: "EUR".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse("0.5 EUR"), Some((0.5, Some("EUR".to_string()))));
+        assert_eq!(parse(" 1,25 usd "), Some((1.25, Some("USD".to_string()))));
+        assert_eq!(parse("2"), Some((2.0, None)));
+        assert_eq!(parse("great post"), None);
+        assert_eq!(parse("1 EUR please"), None);
+
+        let allowed = vec!["EUR".to_string(), "USD".to_string()];
+        assert!(validate(0.5, "EUR", &allowed, 10.0).is_ok());
+        assert_eq!(
+            validate(0.0, "EUR", &allowed, 10.0),
+            Err(AmountError::NotPositive)
+        );
+        assert_eq!(
+            validate(20.0, "EUR", &allowed, 10.0),
+            Err(AmountError::TooLarge)
+        );
+        assert_eq!(
+            validate(0.5, "GBP", &allowed, 10.0),
+            Err(AmountError::UnsupportedCurrency)
+        );
+        assert!(validate(0.5, "GBP", &[], 0.0).is_ok());
+    }
+
+    #[test]
+    fn test_drafts() {
+        let mut drafts = Drafts::new(10);
+
+        // Forward followed by /amount.
+        assert!(drafts.add_forward(like("1", "a"), 100).is_none());
+        assert!(drafts.add_forward(like("1", "b"), 101).is_none());
+        let likes = drafts.set_amount("1", eur(0.5), 105);
+        assert_eq!(likes.len(), 2);
+        assert!(likes
+            .iter()
+            .all(|l| l.like.amount == 0.5 && l.like.currency == "EUR"));
+        assert!(drafts.expired(200).is_empty());
+
+        // Comment sent with the forward.
+        assert!(drafts.set_amount("2", eur(1.0), 100).is_empty());
+        assert_eq!(
+            drafts.add_forward(like("2", "c"), 100).unwrap().like.amount,
+            1.0
+        );
+        // The comment applies only once.
+        assert!(drafts.add_forward(like("2", "d"), 101).is_none());
+
+        // Forwards without an amount are sent with defaults.
+        assert!(drafts.expired(105).is_empty());
+        let likes = drafts.expired(111);
+        assert_eq!(likes.len(), 1);
+        assert_eq!(likes[0].like.target, "d");
+        assert_eq!(likes[0].like.amount, 0.0);
+
+        // Stale amounts are dropped.
+        drafts.set_amount("3", eur(1.0), 100);
+        drafts.expired(200);
+        assert!(drafts.add_forward(like("3", "e"), 200).is_none());
+
+        // Undone forwards aren't sent.
+        assert!(drafts.add_forward(like("3", "f"), 201).is_none());
+        assert_eq!(drafts.undo("3").unwrap().like.target, "f");
+        assert_eq!(drafts.undo("3").unwrap().like.target, "e");
+        assert!(drafts.undo("3").is_none());
+        assert!(drafts.expired(300).is_empty());
+
+        let mut immediate = Drafts::new(0);
+        assert!(immediate.add_forward(like("1", "a"), 100).is_some());
+    }
+}
@@ -0,0 +1,1479 @@
+//! Message handling, independent of how updates are received and replies are
+//! sent.
+
+use crate::amount::{self, Amount, Drafts};
+use crate::claim::{self, ClaimCheck};
+use crate::group::{GroupCommand, GroupSettings};
+use crate::history::{Entry, History, Status, HISTORY_SIZE};
+use crate::limits::{Limiter, LimitsConfiguration};
+use crate::locale::{Locales, DEFAULT_LANGUAGE};
+use crate::queue::QueuedLike;
+use crate::reaction::{Change, Reacted};
+use crate::seen::Seen;
+use crate::settings::Settings;
+use crate::target::Unsupported;
+use smartlike_embed_lib::client::{Client, Like};
+use smartlike_embed_lib::confirmation::{Confirmation, CONFIRM_URL};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+const SETTINGS_URL: &str = "https://smartlike.org/docs/how-to-connect-telegram";
+/// Likes listed by /history.
+const HISTORY_COUNT: usize = 10;
+/// Claim codes can be posted within a day.
+const CLAIM_TTL_SECONDS: u32 = 86400;
+/// Confirmation links expire after a day.
+const CONFIRMATION_TTL_SECONDS: u32 = 86400;
+
+/// Author of an incoming message.
+#[derive(Debug, Clone, Default)]
+pub struct Sender {
+    pub id: i64,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+    pub is_bot: bool,
+    /// Language of the sender's Telegram app.
+    pub language_code: Option<String>,
+}
+
+/// Message received by the bot.
+#[derive(Debug, Clone, Default)]
+pub struct Incoming {
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub from: Sender,
+    pub text: Option<String>,
+    /// Set for messages sent as an album.
+    pub media_group_id: Option<String>,
+    /// Like target of a forwarded message.
+    pub forward: Option<Result<String, Unsupported>>,
+    /// Sent to a group rather than to the bot.
+    pub group: bool,
+    /// The sender administers the group. Checked only for `/group`.
+    pub admin: bool,
+    /// Like target of the forwarded channel post the message replies to.
+    pub reply_to: Option<Result<String, Unsupported>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Button {
+    pub text: String,
+    pub url: String,
+}
+
+/// Message sent by the bot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reply {
+    pub chat_id: i64,
+    pub reply_to: Option<i64>,
+    pub text: String,
+    pub html: bool,
+    pub button: Option<Button>,
+}
+
+impl Reply {
+    pub fn new(chat_id: i64, text: String) -> Reply {
+        Reply {
+            chat_id,
+            reply_to: None,
+            text,
+            html: false,
+            button: None,
+        }
+    }
+
+    pub fn reply_to(mut self, message_id: i64) -> Reply {
+        self.reply_to = Some(message_id);
+        self
+    }
+
+    pub fn html(mut self) -> Reply {
+        self.html = true;
+        self
+    }
+
+    pub fn button(mut self, text: &str, url: String) -> Reply {
+        self.button = Some(Button {
+            text: text.to_string(),
+            url,
+        });
+        self
+    }
+}
+
+/// Sends the bot's messages.
+pub trait BotApi {
+    fn send(&self, reply: Reply);
+}
+
+/// Parameters of `/start <account>_<amount>` opened from the account page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Connect {
+    pub account: Uuid,
+    /// Amount in cents.
+    pub donation: u32,
+}
+
+impl Connect {
+    pub fn parse(args: &str) -> Option<Connect> {
+        let (account, donation) = args.split_once('_')?;
+        Some(Connect {
+            account: Uuid::parse_str(account).ok()?,
+            donation: donation.parse::<u32>().ok()?,
+        })
+    }
+}
+
+/// Like of a forwarded message with the user's default amount.
+pub fn forward_like(user: &str, target: &str, defaults: Option<Amount>) -> Like {
+    Like {
+        platform: "telegram".to_string(),
+        id: user.to_string(),
+        target: target.to_string(),
+        amount: defaults.as_ref().map_or(0.0, |d| d.amount),
+        currency: defaults.map(|d| d.currency).unwrap_or_default(),
+        account: None,
+        recipient: None,
+    }
+}
+
+#[derive(Clone, Default, Debug)]
+pub struct BotConfiguration {
+    pub currencies: Vec<String>,
+    pub max_amount: f64,
+    /// How long media groups are remembered.
+    pub media_group_ttl_seconds: u64,
+    pub limits: LimitsConfiguration,
+}
+
+/// Handles a command. Gets the arguments and the current time and returns
+/// likes to send.
+type CommandHandler<A> = fn(&mut Bot<A>, &Incoming, &str, u64) -> Vec<QueuedLike>;
+
+pub struct Bot<A: BotApi> {
+    api: A,
+    client: Client,
+    config: BotConfiguration,
+    settings: Settings,
+    locales: Arc<Locales>,
+    drafts: Arc<Mutex<Drafts>>,
+    limiter: Limiter,
+    seen: Arc<Seen>,
+    history: Arc<History>,
+    /// Claims to check, see `take_claims`.
+    claims: Vec<ClaimCheck>,
+    commands: HashMap<&'static str, CommandHandler<A>>,
+}
+
+impl<A: BotApi> Bot<A> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        api: A,
+        client: Client,
+        config: BotConfiguration,
+        settings: Settings,
+        locales: Arc<Locales>,
+        drafts: Arc<Mutex<Drafts>>,
+        seen: Arc<Seen>,
+        history: Arc<History>,
+    ) -> Bot<A> {
+        let mut commands: HashMap<&'static str, CommandHandler<A>> = HashMap::new();
+        commands.insert("/start", Bot::start);
+        commands.insert("/help", Bot::help);
+        commands.insert("/settings", Bot::settings);
+        commands.insert("/amount", Bot::amount);
+        commands.insert("/language", Bot::language);
+        commands.insert("/history", Bot::history);
+        commands.insert("/undo", Bot::undo);
+        commands.insert("/claim", Bot::claim);
+        Bot {
+            api,
+            client,
+            limiter: Limiter::new(&config.limits, seen.clone()),
+            config,
+            settings,
+            locales,
+            drafts,
+            seen,
+            history,
+            claims: vec![],
+            commands,
+        }
+    }
+
+    /// Claims whose codes should be looked for in their channels.
+    pub fn take_claims(&mut self) -> Vec<ClaimCheck> {
+        std::mem::take(&mut self.claims)
+    }
+
+    /// Handles a message and returns likes ready to be sent.
+    pub fn handle(&mut self, msg: &Incoming, now: u64) -> Vec<QueuedLike> {
+        if msg.from.is_bot {
+            warn!("Bot ignored");
+            return vec![];
+        }
+        if msg.group {
+            return self.group(msg, now);
+        }
+        if let Some(id) = &msg.media_group_id {
+            let key = format!("media_group/{}", id);
+            if !self
+                .seen
+                .first(&key, self.config.media_group_ttl_seconds, now)
+            {
+                debug!("Skipping media group {}", id);
+                return vec![];
+            }
+        }
+        if let Some(forward) = &msg.forward {
+            return self.forward(msg, forward, now);
+        }
+
+        let text = msg.text.as_deref().unwrap_or_default().trim();
+        let (command, args) = text.split_once(' ').unwrap_or((text, ""));
+        // Commands in groups are addressed as /command@bot.
+        let command = command.split('@').next().unwrap_or_default();
+        if let Some(handler) = self.commands.get(command).copied() {
+            return handler(self, msg, args.trim(), now);
+        }
+        if amount::parse(text).is_some() {
+            // A comment with the amount sent along with a forward.
+            return self.amount(msg, text, now);
+        }
+        self.reply(msg, "unknown_command", &[]);
+        vec![]
+    }
+
+    /// Language of the sender: the one chosen with /language or the one of
+    /// their Telegram app.
+    fn user_language(&self, from: &Sender) -> String {
+        self.settings
+            .language(&from.id.to_string())
+            .filter(|language| self.locales.has(language))
+            .unwrap_or_else(|| self.locales.language(from.language_code.as_deref()))
+    }
+
+    fn text(&self, msg: &Incoming, key: &str, args: &[(&str, &str)]) -> String {
+        self.locales.text(&self.user_language(&msg.from), key, args)
+    }
+
+    fn reply(&self, msg: &Incoming, key: &str, args: &[(&str, &str)]) {
+        self.api
+            .send(Reply::new(msg.chat_id, self.text(msg, key, args)));
+    }
+
+    fn forward(
+        &mut self,
+        msg: &Incoming,
+        forward: &Result<String, Unsupported>,
+        now: u64,
+    ) -> Vec<QueuedLike> {
+        match forward {
+            Ok(target) => {
+                let user = msg.from.id.to_string();
+                if let Err(limited) = self.limiter.check(&user, target, now) {
+                    debug!("Like of {} by {} rejected: {:?}", target, user, limited);
+                    self.reply(
+                        msg,
+                        limited.key(),
+                        &[("minutes", &limited.minutes().to_string())],
+                    );
+                    return vec![];
+                }
+                let like = forward_like(&user, target, self.settings.get(&user));
+                let mut queued = QueuedLike::new(like, msg.chat_id, msg.message_id);
+                queued.language = Some(self.user_language(&msg.from));
+                self.drafts
+                    .lock()
+                    .unwrap()
+                    .add_forward(queued, now)
+                    .into_iter()
+                    .collect()
+            }
+            Err(reason) => {
+                debug!("Unsupported forward: {:?}", reason);
+                self.reply(msg, reason.key(), &[]);
+                vec![]
+            }
+        }
+    }
+
+    fn start(&mut self, msg: &Incoming, args: &str, now: u64) -> Vec<QueuedLike> {
+        let welcome = self.text(msg, "welcome", &[]);
+        if args.is_empty() {
+            self.api.send(
+                Reply::new(
+                    msg.chat_id,
+                    self.text(msg, "start", &[("welcome", &welcome)]),
+                )
+                .html()
+                .button(
+                    &self.text(msg, "button_connect", &[]),
+                    SETTINGS_URL.to_string(),
+                ),
+            );
+            return vec![];
+        }
+        match Connect::parse(args) {
+            Some(connect) => match self.connect_url(&msg.from, &connect, now) {
+                Ok(url) => self.api.send(
+                    Reply::new(
+                        msg.chat_id,
+                        self.text(msg, "confirm", &[("welcome", &welcome)]),
+                    )
+                    .html()
+                    .button(&self.text(msg, "button_confirm", &[]), url),
+                ),
+                Err(e) => {
+                    error!("Failed to build confirmation link: {}", e);
+                    self.reply(msg, "wrong_parameters", &[]);
+                }
+            },
+            None => self.reply(msg, "wrong_parameters", &[]),
+        }
+        vec![]
+    }
+
+    /// Signed link confirming that the user's likes are paid from the account.
+    fn connect_url(&self, from: &Sender, connect: &Connect, now: u64) -> Result<String, String> {
+        let mut name = from.first_name.clone();
+        if let Some(v) = &from.last_name {
+            name.push(' ');
+            name.push_str(v);
+        }
+        if let Some(v) = &from.username {
+            name.push_str(" (@");
+            name.push_str(v);
+            name.push(')');
+        }
+        let confirmation = Confirmation::new(
+            "telegram",
+            &from.id.to_string(),
+            &connect.account.to_string(),
+            connect.donation as f64 / 100.0,
+            now as u32,
+            CONFIRMATION_TTL_SECONDS,
+        )
+        .name(&name);
+        self.client
+            .sign_confirmation(confirmation)
+            .and_then(|signed| signed.url(CONFIRM_URL))
+            .map_err(|e| e.to_string())
+    }
+
+    fn help(&mut self, msg: &Incoming, _args: &str, _now: u64) -> Vec<QueuedLike> {
+        let welcome = self.text(msg, "welcome", &[]);
+        self.api.send(
+            Reply::new(
+                msg.chat_id,
+                self.text(
+                    msg,
+                    "help",
+                    &[("welcome", &welcome), ("settings_url", SETTINGS_URL)],
+                ),
+            )
+            .html(),
+        );
+        vec![]
+    }
+
+    /// Parses and validates an amount. The currency defaults to the one in
+    /// the user's settings. Errors are messages to the user.
+    fn parse_amount(&self, msg: &Incoming, args: &str) -> Option<Result<Amount, String>> {
+        let (value, currency) = amount::parse(args)?;
+        let currency = currency
+            .or_else(|| {
+                self.settings
+                    .get(&msg.from.id.to_string())
+                    .map(|d| d.currency)
+            })
+            .unwrap_or_default();
+        if currency.is_empty() {
+            return Some(Err(self.text(msg, "amount_no_currency", &[])));
+        }
+        Some(
+            amount::validate(
+                value,
+                &currency,
+                &self.config.currencies,
+                self.config.max_amount,
+            )
+            .map_err(|e| {
+                self.text(
+                    msg,
+                    e.key(),
+                    &[
+                        ("currency", &currency),
+                        ("currencies", &self.config.currencies.join(", ")),
+                        ("max_amount", &self.config.max_amount.to_string()),
+                    ],
+                )
+            }),
+        )
+    }
+
+    fn settings(&mut self, msg: &Incoming, args: &str, _now: u64) -> Vec<QueuedLike> {
+        let user = msg.from.id.to_string();
+        if args.is_empty() {
+            let current = match self.settings.get(&user) {
+                Some(d) => self.text(
+                    msg,
+                    "settings_amount",
+                    &[("amount", &d.amount.to_string()), ("currency", &d.currency)],
+                ),
+                None => self.text(msg, "settings_account_amount", &[]),
+            };
+            self.api.send(
+                Reply::new(
+                    msg.chat_id,
+                    self.text(msg, "settings", &[("current", &current)]),
+                )
+                .button(
+                    &self.text(msg, "button_settings", &[]),
+                    SETTINGS_URL.to_string(),
+                ),
+            );
+            return vec![];
+        }
+        let reply = match self.parse_amount(msg, args) {
+            Some(Ok(amount)) => match self.settings.set(&user, &amount) {
+                Ok(_) => self.text(
+                    msg,
+                    "settings_saved",
+                    &[
+                        ("amount", &amount.amount.to_string()),
+                        ("currency", &amount.currency),
+                    ],
+                ),
+                Err(e) => {
+                    error!("{}", e);
+                    self.text(msg, "settings_failed", &[])
+                }
+            },
+            Some(Err(e)) => e,
+            None => self.text(msg, "settings_usage", &[]),
+        };
+        self.api.send(Reply::new(msg.chat_id, reply));
+        vec![]
+    }
+
+    /// `/amount 0.5 EUR` or a bare amount sent as a comment.
+    fn amount(&mut self, msg: &Incoming, args: &str, now: u64) -> Vec<QueuedLike> {
+        let user = msg.from.id.to_string();
+        let command = msg.text.as_deref().unwrap_or_default().starts_with('/');
+        let amount = match self.parse_amount(msg, args) {
+            Some(Ok(amount)) => amount,
+            Some(Err(e)) => {
+                self.api.send(Reply::new(msg.chat_id, e));
+                return vec![];
+            }
+            None => {
+                self.reply(msg, "amount_usage", &[]);
+                return vec![];
+            }
+        };
+        let value = amount.amount.to_string();
+        let currency = amount.currency.clone();
+        let likes = self.drafts.lock().unwrap().set_amount(&user, amount, now);
+        let args = [("amount", value.as_str()), ("currency", currency.as_str())];
+        match (likes.len(), command) {
+            (0, true) => self.reply(msg, "amount_next", &args),
+            (0, false) => {}
+            (sent, _) => self.reply(
+                msg,
+                "amount_sending",
+                &[args[0], args[1], ("count", &sent.to_string())],
+            ),
+        }
+        likes
+    }
+
+    /// Shows or changes the language of the bot's messages.
+    fn language(&mut self, msg: &Incoming, args: &str, _now: u64) -> Vec<QueuedLike> {
+        let languages = self
+            .locales
+            .languages()
+            .iter()
+            .map(|l| format!("{} ({})", l, self.locales.text(l, "language_name", &[])))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let language = args.to_lowercase();
+        if language.is_empty() {
+            let current = self.user_language(&msg.from);
+            let example = self
+                .locales
+                .languages()
+                .into_iter()
+                .find(|l| *l != current)
+                .unwrap_or(DEFAULT_LANGUAGE);
+            self.reply(
+                msg,
+                "language",
+                &[
+                    ("language", &current),
+                    ("languages", &languages),
+                    ("example", example),
+                ],
+            );
+        } else if !self.locales.has(&language) {
+            self.reply(
+                msg,
+                "language_unknown",
+                &[("language", args), ("languages", &languages)],
+            );
+        } else {
+            match self
+                .settings
+                .set_language(&msg.from.id.to_string(), &language)
+            {
+                Ok(_) => self.reply(msg, "language_set", &[]),
+                Err(e) => {
+                    error!("{}", e);
+                    self.reply(msg, "settings_failed", &[]);
+                }
+            }
+        }
+        vec![]
+    }
+
+    /// Lists recent likes of the user.
+    fn history(&mut self, msg: &Incoming, _args: &str, _now: u64) -> Vec<QueuedLike> {
+        let entries = self.history.recent(&msg.from.id.to_string(), HISTORY_COUNT);
+        if entries.is_empty() {
+            self.reply(msg, "history_empty", &[]);
+            return vec![];
+        }
+        let likes = entries
+            .iter()
+            .map(|entry| {
+                let status = self.text(msg, entry.status.key(), &[]);
+                if entry.like.amount > 0.0 {
+                    format!(
+                        "{} {} {}, {}",
+                        entry.like.target, entry.like.amount, entry.like.currency, status
+                    )
+                } else {
+                    format!("{}, {}", entry.like.target, status)
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        self.reply(msg, "history", &[("likes", &likes)]);
+        vec![]
+    }
+
+    /// Takes back the last like of the user: drops it if it waits for an
+    /// amount or in the queue, otherwise sends a retraction.
+    fn undo(&mut self, msg: &Incoming, _args: &str, _now: u64) -> Vec<QueuedLike> {
+        let user = msg.from.id.to_string();
+        let held = self.drafts.lock().unwrap().undo(&user);
+        if let Some(queued) = held {
+            self.limiter.forget(&user, &queued.like.target);
+            self.reply(msg, "undo_removed", &[("target", &queued.like.target)]);
+            return vec![];
+        }
+        let entry = self
+            .history
+            .recent(&user, HISTORY_COUNT)
+            .into_iter()
+            .find(|entry| matches!(entry.status, Status::Pending | Status::Sent));
+        let entry = match entry {
+            Some(entry) => entry,
+            None => {
+                self.reply(msg, "undo_nothing", &[]);
+                return vec![];
+            }
+        };
+        let args = [("target", entry.like.target.as_str())];
+        if !self.take_back(&entry) {
+            self.reply(msg, "undo_removed", &args);
+            return vec![];
+        }
+        self.reply(msg, "undo_retracting", &args);
+        let mut queued = QueuedLike::new(entry.like, msg.chat_id, msg.message_id);
+        queued.language = Some(self.user_language(&msg.from));
+        vec![queued.retraction()]
+    }
+
+    /// Takes back a like of the history. Returns true if it was sent and
+    /// needs a retraction, pending likes are dropped by the queue worker.
+    fn take_back(&mut self, entry: &Entry) -> bool {
+        self.limiter.forget(&entry.like.id, &entry.like.target);
+        if entry.status == Status::Pending {
+            self.history.set_status(&entry.key, Status::Undone);
+            return false;
+        }
+        self.history.set_status(&entry.key, Status::Retracting);
+        true
+    }
+
+    /// `/claim @channel` issues a code to post in the channel. Sent again, it
+    /// has the code looked for.
+    fn claim(&mut self, msg: &Incoming, args: &str, now: u64) -> Vec<QueuedLike> {
+        let channel = match claim::channel(args) {
+            Some(channel) => channel,
+            None => {
+                self.reply(msg, "claim_usage", &[]);
+                return vec![];
+            }
+        };
+        let user = msg.from.id.to_string();
+        match self.settings.claim(&user, &channel) {
+            Some(challenge) if !challenge.challenge.is_expired(now as u32) => {
+                self.reply(msg, "claim_checking", &[("channel", &channel)]);
+                self.claims.push(ClaimCheck {
+                    user,
+                    channel,
+                    challenge,
+                    chat_id: msg.chat_id,
+                    message_id: msg.message_id,
+                    language: self.user_language(&msg.from),
+                });
+            }
+            _ => {
+                let challenge =
+                    claim::challenge(&self.client.account, &channel, now, CLAIM_TTL_SECONDS);
+                let code = claim::code(&challenge);
+                let saved = self
+                    .client
+                    .issue_challenge(challenge)
+                    .map_err(|e| e.to_string())
+                    .and_then(|signed| self.settings.set_claim(&user, &channel, &signed));
+                match saved {
+                    Ok(_) => self.reply(
+                        msg,
+                        "claim_code",
+                        &[
+                            ("channel", &channel),
+                            ("code", &code),
+                            ("hours", &(CLAIM_TTL_SECONDS / 3600).to_string()),
+                        ],
+                    ),
+                    Err(e) => {
+                        error!("Failed to issue claim of {}: {}", channel, e);
+                        self.reply(msg, "settings_failed", &[]);
+                    }
+                }
+            }
+        }
+        vec![]
+    }
+
+    /// Messages in groups. Anything but likes and settings is ignored, so that
+    /// the bot doesn't interrupt conversations.
+    fn group(&mut self, msg: &Incoming, now: u64) -> Vec<QueuedLike> {
+        let text = msg.text.as_deref().unwrap_or_default().trim();
+        let (command, args) = text.split_once(' ').unwrap_or((text, ""));
+        let command = command.split('@').next().unwrap_or_default();
+        let settings = self.settings.group(msg.chat_id);
+        match command {
+            "+1" if args.is_empty() && settings.plus_one => {
+                self.group_like(msg, "", &settings, now, false)
+            }
+            "/like" if settings.like => self.group_like(msg, args.trim(), &settings, now, true),
+            "/group" => self.group_settings(msg, args.trim(), settings),
+            _ => vec![],
+        }
+    }
+
+    /// Likes the channel post the message replies to. Only `/like` gets
+    /// replies explaining why nothing was liked, `+1` is often just agreement.
+    fn group_like(
+        &mut self,
+        msg: &Incoming,
+        args: &str,
+        settings: &GroupSettings,
+        now: u64,
+        command: bool,
+    ) -> Vec<QueuedLike> {
+        let target = match &msg.reply_to {
+            Some(Ok(target)) => target.clone(),
+            Some(Err(reason)) if command => {
+                self.reply(msg, reason.key(), &[]);
+                return vec![];
+            }
+            None if command => {
+                self.reply(msg, "group_like_usage", &[]);
+                return vec![];
+            }
+            _ => return vec![],
+        };
+        let user = msg.from.id.to_string();
+        let amount = if args.is_empty() {
+            self.settings.get(&user).or_else(|| settings.amount.clone())
+        } else {
+            match self.parse_amount(msg, args) {
+                Some(Ok(amount)) => Some(amount),
+                Some(Err(e)) => {
+                    self.api.send(Reply::new(msg.chat_id, e));
+                    return vec![];
+                }
+                None => {
+                    self.reply(msg, "group_like_usage", &[]);
+                    return vec![];
+                }
+            }
+        };
+        if matches!(&amount, Some(amount) if settings.exceeds(amount.amount)) {
+            if command {
+                self.reply(
+                    msg,
+                    "amount_too_large",
+                    &[("max_amount", &settings.max_amount.to_string())],
+                );
+            }
+            return vec![];
+        }
+        if let Err(limited) = self.limiter.check(&user, &target, now) {
+            debug!("Like of {} by {} rejected: {:?}", target, user, limited);
+            if command {
+                self.reply(
+                    msg,
+                    limited.key(),
+                    &[("minutes", &limited.minutes().to_string())],
+                );
+            }
+            return vec![];
+        }
+        let like = forward_like(&user, &target, amount);
+        let mut queued = QueuedLike::new(like, msg.chat_id, msg.message_id);
+        queued.language = Some(self.user_language(&msg.from));
+        vec![queued]
+    }
+
+    /// `/group` shows the settings of the group, admins change them.
+    fn group_settings(
+        &mut self,
+        msg: &Incoming,
+        args: &str,
+        mut settings: GroupSettings,
+    ) -> Vec<QueuedLike> {
+        if !args.is_empty() {
+            if !msg.admin {
+                self.reply(msg, "group_admin_only", &[]);
+                return vec![];
+            }
+            match GroupCommand::parse(args) {
+                Some(GroupCommand::Like(on)) => settings.like = on,
+                Some(GroupCommand::PlusOne(on)) => settings.plus_one = on,
+                Some(GroupCommand::Amount(None)) => settings.amount = None,
+                Some(GroupCommand::Amount(Some(amount))) => match self.parse_amount(msg, &amount) {
+                    Some(Ok(amount)) if settings.exceeds(amount.amount) => {
+                        self.reply(
+                            msg,
+                            "amount_too_large",
+                            &[("max_amount", &settings.max_amount.to_string())],
+                        );
+                        return vec![];
+                    }
+                    Some(Ok(amount)) => settings.amount = Some(amount),
+                    Some(Err(e)) => {
+                        self.api.send(Reply::new(msg.chat_id, e));
+                        return vec![];
+                    }
+                    None => {
+                        self.reply(msg, "group_usage", &[]);
+                        return vec![];
+                    }
+                },
+                Some(GroupCommand::MaxAmount(max)) => {
+                    settings.max_amount = max;
+                    if let Some(amount) = settings
+                        .amount
+                        .as_ref()
+                        .filter(|a| settings.exceeds(a.amount))
+                    {
+                        self.reply(
+                            msg,
+                            "group_max_below_amount",
+                            &[("amount", &format!("{} {}", amount.amount, amount.currency))],
+                        );
+                        return vec![];
+                    }
+                }
+                None => {
+                    self.reply(msg, "group_usage", &[]);
+                    return vec![];
+                }
+            }
+            if let Err(e) = self.settings.set_group(msg.chat_id, &settings) {
+                error!("{}", e);
+                self.reply(msg, "settings_failed", &[]);
+                return vec![];
+            }
+        }
+        let on_off = |on: bool| self.text(msg, if on { "group_on" } else { "group_off" }, &[]);
+        let amount = match &settings.amount {
+            Some(amount) => format!("{} {}", amount.amount, amount.currency),
+            None => self.text(msg, "group_amount_default", &[]),
+        };
+        let max_amount = if settings.max_amount > 0.0 {
+            settings.max_amount
+        } else {
+            self.config.max_amount
+        };
+        self.reply(
+            msg,
+            "group_settings",
+            &[
+                ("like", &on_off(settings.like)),
+                ("plus_one", &on_off(settings.plus_one)),
+                ("amount", &amount),
+                ("max_amount", &max_amount.to_string()),
+            ],
+        );
+        vec![]
+    }
+
+    /// Likes a post the user reacted to or takes back the like when the
+    /// reaction is withdrawn. Reactions aren't replied to, so that the chat
+    /// isn't spammed.
+    pub fn react(&mut self, reacted: &Reacted, now: u64) -> Vec<QueuedLike> {
+        if reacted.from.is_bot {
+            return vec![];
+        }
+        let user = reacted.from.id.to_string();
+        match &reacted.change {
+            Change::Like(amount) => {
+                // Configured amounts are held to the same rules as typed ones.
+                let amount = match amount::validate(
+                    amount.amount,
+                    &amount.currency,
+                    &self.config.currencies,
+                    self.config.max_amount,
+                ) {
+                    Ok(amount) => amount,
+                    Err(e) => {
+                        warn!(
+                            "Reaction to {} by {} rejected: {:?} {} {}",
+                            reacted.target, user, e, amount.amount, amount.currency
+                        );
+                        return vec![];
+                    }
+                };
+                if let Err(limited) = self.limiter.check(&user, &reacted.target, now) {
+                    debug!(
+                        "Reaction to {} by {} rejected: {:?}",
+                        reacted.target, user, limited
+                    );
+                    return vec![];
+                }
+                let like = forward_like(&user, &reacted.target, Some(amount));
+                vec![QueuedLike::without_reply(like)]
+            }
+            Change::Unlike => {
+                let entry = self
+                    .history
+                    .recent(&user, HISTORY_SIZE)
+                    .into_iter()
+                    .find(|entry| {
+                        entry.like.target == reacted.target
+                            && matches!(entry.status, Status::Pending | Status::Sent)
+                    });
+                match entry {
+                    Some(entry) if self.take_back(&entry) => {
+                        vec![QueuedLike::without_reply(entry.like).retraction()]
+                    }
+                    _ => vec![],
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smartlike_embed_lib::confirmation::SignedConfirmation;
+    use smartlike_like_queue::testing::TempDir;
+    use std::cell::RefCell;
+
+    /// Records replies instead of sending them.
+    #[derive(Default)]
+    struct Recorder {
+        replies: RefCell<Vec<Reply>>,
+    }
+
+    impl BotApi for &Recorder {
+        fn send(&self, reply: Reply) {
+            self.replies.borrow_mut().push(reply);
+        }
+    }
+
+    impl Recorder {
+        fn take(&self) -> Vec<String> {
+            self.replies
+                .borrow_mut()
+                .drain(..)
+                .map(|reply| reply.text)
+                .collect()
+        }
+    }
+
+    fn bot<'a>(api: &'a Recorder, dir: &TempDir, wait_seconds: u64) -> Bot<&'a Recorder> {
+        bot_with_limits(api, dir, wait_seconds, LimitsConfiguration::default())
+    }
+
+    /// Opens a bot keeping its state in the directory, also to restart it.
+    fn bot_with_limits<'a>(
+        api: &'a Recorder,
+        dir: &TempDir,
+        wait_seconds: u64,
+        limits: LimitsConfiguration,
+    ) -> Bot<&'a Recorder> {
+        Bot::new(
+            api,
+            Client::new("proxy".to_string(), "secret".to_string(), "".to_string()),
+            BotConfiguration {
+                currencies: vec!["EUR".to_string(), "USD".to_string()],
+                max_amount: 10.0,
+                media_group_ttl_seconds: 3600,
+                limits,
+            },
+            Settings::open(&dir.join("settings")).unwrap(),
+            Arc::new(Locales::load("./locales").unwrap()),
+            Arc::new(Mutex::new(Drafts::new(wait_seconds))),
+            Arc::new(Seen::open(&dir.join("seen")).unwrap()),
+            Arc::new(History::open(&dir.join("history")).unwrap()),
+        )
+    }
+
+    fn text(text: &str) -> Incoming {
+        Incoming {
+            chat_id: 1,
+            message_id: 10,
+            from: Sender {
+                id: 7,
+                first_name: "Alice".to_string(),
+                ..Default::default()
+            },
+            text: Some(text.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn forward(target: &str, media_group_id: Option<&str>) -> Incoming {
+        Incoming {
+            text: None,
+            media_group_id: media_group_id.map(|id| id.to_string()),
+            forward: Some(Ok(target.to_string())),
+            ..text("")
+        }
+    }
+
+    #[test]
+    fn test_start() {
+        let account = "0f4b5d4a-6a55-4a6e-8a3e-8f1a4a4e2b9c";
+        for (args, expected) in [
+            (
+                format!("{}_50", account),
+                Some(Connect {
+                    account: Uuid::parse_str(account).unwrap(),
+                    donation: 50,
+                }),
+            ),
+            (format!("{}_0.5", account), None),
+            (format!("{}_-1", account), None),
+            (format!("{}_50_1", account), None),
+            ("not-a-uuid_50".to_string(), None),
+            (account.to_string(), None),
+            ("".to_string(), None),
+        ] {
+            assert_eq!(Connect::parse(&args), expected, "{}", args);
+        }
+
+        let api = Recorder::default();
+        let dir = TempDir::new("smartlike-telegram-bot-test-start");
+        let mut bot = bot(&api, &dir, 0);
+        for (message, expected) in [
+            ("/start", "Please follow the link to connect:"),
+            (
+                "/start@smartlike_org_bot",
+                "Please follow the link to connect:",
+            ),
+            ("/start x_1", "Wrong parameter(s)."),
+            ("/help", "settings</a>"),
+            ("/amount", "Please forward a post"),
+            ("hello", "Unknown command"),
+        ] {
+            assert!(bot.handle(&text(message), 100).is_empty());
+            let replies = api.take();
+            assert_eq!(replies.len(), 1, "{}", message);
+            assert!(replies[0].ends_with(expected) || replies[0].starts_with(expected));
+        }
+
+        bot.handle(&text(&format!("/start {}_50", account)), 100);
+        let reply = api.replies.borrow_mut().pop().unwrap();
+        let url = reply.button.unwrap().url;
+        assert!(url.starts_with("https://smartlike.org/confirm?platform=telegram&id=7&name=Alice&"));
+        assert!(url.contains("&amount=0.500000&proxy=proxy&"));
+        let signed = SignedConfirmation::from_url(&url).unwrap();
+        assert_eq!(signed.confirmation.expires, 100 + CONFIRMATION_TTL_SECONDS);
+        assert!(signed.verify(&bot.client.public_key(), 100).is_ok());
+    }
+
+    #[test]
+    fn test_forwards() {
+        let api = Recorder::default();
+        let dir = TempDir::new("smartlike-telegram-bot-test-forwards");
+        let mut bot = bot(&api, &dir, 0);
+
+        let likes = bot.handle(&forward("https://t.me/smartlike_org/42", None), 100);
+        assert_eq!(likes.len(), 1);
+        assert_eq!(likes[0].like.platform, "telegram");
+        assert_eq!(likes[0].like.id, "7");
+        assert_eq!(likes[0].like.target, "https://t.me/smartlike_org/42");
+        assert_eq!(
+            (likes[0].like.amount, likes[0].like.currency.as_str()),
+            (0.0, "")
+        );
+        assert_eq!((likes[0].chat_id, likes[0].message_id), (Some(1), Some(10)));
+
+        // Likes take the default amount once it's set.
+        assert!(bot.handle(&text("/settings 0.5 EUR"), 100).is_empty());
+        assert_eq!(api.take(), vec!["Default amount set to 0.5 EUR."]);
+        let likes = bot.handle(&forward("https://t.me/smartlike_org/43", None), 100);
+        assert_eq!(
+            (likes[0].like.amount, likes[0].like.currency.as_str()),
+            (0.5, "EUR")
+        );
+
+        let mut unsupported = forward("", None);
+        unsupported.forward = Some(Err(Unsupported::HiddenUser));
+        assert!(bot.handle(&unsupported, 100).is_empty());
+        assert_eq!(
+            api.take(),
+            vec!["The author of this message hides their account in forwarded messages, so it can't be liked."]
+        );
+
+        let mut from_bot = forward("https://t.me/smartlike_org/44", None);
+        from_bot.from.is_bot = true;
+        assert!(bot.handle(&from_bot, 100).is_empty());
+        assert!(api.take().is_empty());
+    }
+
+    #[test]
+    fn test_media_groups() {
+        let api = Recorder::default();
+        let dir = TempDir::new("smartlike-telegram-bot-test-media-groups");
+        let mut bot = bot(&api, &dir, 0);
+        for (target, group, now, liked) in [
+            ("https://t.me/a/1", Some("album1"), 100, true),
+            ("https://t.me/a/2", Some("album1"), 100, false),
+            ("https://t.me/a/3", Some("album1"), 101, false),
+            ("https://t.me/a/4", Some("album2"), 101, true),
+            ("https://t.me/a/5", None, 101, true),
+            ("https://t.me/a/6", None, 101, true),
+            // Restarted in the middle of an album.
+            ("restart", None, 0, false),
+            ("https://t.me/a/7", Some("album2"), 102, false),
+            ("https://t.me/a/8", Some("album3"), 102, true),
+            // Expired.
+            ("https://t.me/a/9", Some("album1"), 3700, true),
+        ] {
+            if target == "restart" {
+                drop(bot);
+                bot = bot_with_limits(&api, &dir, 0, LimitsConfiguration::default());
+                continue;
+            }
+            let likes = bot.handle(&forward(target, group), now);
+            assert_eq!(likes.len(), liked as usize, "{}", target);
+        }
+    }
+
+    #[test]
+    fn test_amounts() {
+        let api = Recorder::default();
+        let dir = TempDir::new("smartlike-telegram-bot-test-amounts");
+        let mut bot = bot(&api, &dir, 10);
+
+        // Forward followed by /amount.
+        assert!(bot
+            .handle(&forward("https://t.me/a/1", None), 100)
+            .is_empty());
+        let likes = bot.handle(&text("/amount 1 USD"), 101);
+        assert_eq!(likes.len(), 1);
+        assert_eq!(likes[0].like.currency, "USD");
+        assert_eq!(api.take(), vec!["Sending 1 like(s), 1 USD each."]);
+
+        // Comment sent with the forward.
+        assert!(bot.handle(&text("2 EUR"), 102).is_empty());
+        assert!(api.take().is_empty());
+        let likes = bot.handle(&forward("https://t.me/a/2", None), 102);
+        assert_eq!(likes[0].like.amount, 2.0);
+
+        for (message, expected) in [
+            ("/amount 3 EUR", "3 EUR will be used for your next forward."),
+            (
+                "/amount 3",
+                "Please specify a currency, e.g. /amount 0.5 EUR",
+            ),
+            ("/amount 30 EUR", "The amount can't exceed 10."),
+            (
+                "/settings 1 GBP",
+                "Unsupported currency GBP. Please use one of: EUR, USD.",
+            ),
+            ("/settings 1 EUR", "Default amount set to 1 EUR."),
+            ("/settings", "Default amount: 1 EUR."),
+            (
+                "/settings EUR",
+                "Please specify the amount, e.g. /settings 0.5 EUR",
+            ),
+        ] {
+            bot.handle(&text(message), 103);
+            let replies = api.take();
+            assert!(
+                replies[0].starts_with(expected),
+                "{}: {:?}",
+                message,
+                replies
+            );
+        }
+    }
+
+    #[test]
+    fn test_languages() {
+        let api = Recorder::default();
+        let dir = TempDir::new("smartlike-telegram-bot-test-languages");
+        let mut bot = bot(&api, &dir, 0);
+
+        let mut msg = text("/help");
+        msg.from.language_code = Some("ru-RU".to_string());
+        bot.handle(&msg, 100);
+        assert!(api.take()[0].contains("настройки</a>"));
+
+        // Likes are reported in the user's language.
+        let mut forwarded = forward("https://t.me/a/1", None);
+        forwarded.from.language_code = Some("ru".to_string());
+        assert_eq!(
+            bot.handle(&forwarded, 100)[0].language.as_deref(),
+            Some("ru")
+        );
+
+        for (message, expected) in [
+            (
+                "/language",
+                "Язык: ru. Доступные языки: en (English), ru (Русский).",
+            ),
+            ("/language xx", "Неизвестный язык xx."),
+            ("/language EN", "Messages will be shown in English."),
+            ("/help", "settings</a>"),
+            ("/language", "Language: en."),
+        ] {
+            msg.text = Some(message.to_string());
+            bot.handle(&msg, 100);
+            let replies = api.take();
+            assert!(
+                replies[0].starts_with(expected) || replies[0].ends_with(expected),
+                "{}: {:?}",
+                message,
+                replies
+            );
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let api = Recorder::default();
+        let dir = TempDir::new("smartlike-telegram-bot-test-bot-limits");
+        let mut bot = bot_with_limits(
+            &api,
+            &dir,
+            0,
+            LimitsConfiguration {
+                user_likes: 2,
+                user_window_seconds: 3600,
+                duplicate_window_seconds: 86400,
+                ..Default::default()
+            },
+        );
+        assert_eq!(bot.handle(&forward("https://t.me/a/1", None), 100).len(), 1);
+        assert!(bot
+            .handle(&forward("https://t.me/a/1", None), 200)
+            .is_empty());
+        assert_eq!(
+            api.take(),
+            vec!["You have already liked this post recently."]
+        );
+        assert_eq!(bot.handle(&forward("https://t.me/a/2", None), 300).len(), 1);
+        assert!(bot
+            .handle(&forward("https://t.me/a/3", None), 400)
+            .is_empty());
+        assert_eq!(
+            api.take(),
+            vec!["You are sending likes too fast. Please try again in 55 min."]
+        );
+    }
+
+    #[test]
+    fn test_undo() {
+        let api = Recorder::default();
+        let dir = TempDir::new("smartlike-telegram-bot-test-undo");
+        let mut bot = bot_with_limits(
+            &api,
+            &dir,
+            0,
+            LimitsConfiguration {
+                duplicate_window_seconds: 3600,
+                ..Default::default()
+            },
+        );
+        bot.handle(&text("/history"), 100);
+        bot.handle(&text("/undo"), 100);
+        assert_eq!(
+            api.take(),
+            [
+                "You haven't liked anything yet.",
+                "There is no like to take back."
+            ]
+        );
+
+        // Queued like.
+        let likes = bot.handle(&forward("https://t.me/a/1", None), 100);
+        bot.history.add(&likes[0], 100);
+        bot.handle(&text("/history"), 101);
+        assert!(api.take()[0].contains("https://t.me/a/1, queued"));
+        assert!(bot.handle(&text("/undo"), 102).is_empty());
+        assert_eq!(
+            api.take(),
+            ["The like for https://t.me/a/1 was removed from the queue."]
+        );
+        let entry = bot.history.find(&likes[0].like_key()).unwrap();
+        assert_eq!(entry.status, Status::Undone);
+        // It can be liked again.
+        assert_eq!(bot.handle(&forward("https://t.me/a/1", None), 103).len(), 1);
+
+        // Sent like.
+        let likes = bot.handle(&forward("https://t.me/b/1", None), 104);
+        bot.history.add(&likes[0], 104);
+        bot.history.set_status(&likes[0].like_key(), Status::Sent);
+        let retractions = bot.handle(&text("/undo"), 105);
+        assert_eq!(retractions.len(), 1);
+        assert!(retractions[0].retract);
+        assert_eq!(retractions[0].like.target, "https://t.me/b/1");
+        assert_eq!(api.take(), ["Taking back the like for https://t.me/b/1."]);
+        bot.handle(&text("/undo"), 106);
+        assert_eq!(api.take(), ["There is no like to take back."]);
+
+        // Forward waiting for an amount.
+        let api = Recorder::default();
+        let dir = TempDir::new("smartlike-telegram-bot-test-undo-drafts");
+        let mut bot = bot_with_limits(&api, &dir, 10, LimitsConfiguration::default());
+        assert!(bot
+            .handle(&forward("https://t.me/c/1", None), 100)
+            .is_empty());
+        bot.handle(&text("/undo"), 101);
+        assert_eq!(
+            api.take(),
+            ["The like for https://t.me/c/1 was removed from the queue."]
+        );
+        assert!(bot.drafts.lock().unwrap().expired(200).is_empty());
+    }
+
+    #[test]
+    fn test_reactions() {
+        let api = Recorder::default();
+        let dir = TempDir::new("smartlike-telegram-bot-test-reactions");
+        let mut bot = bot(&api, &dir, 10);
+        let reacted = |change| Reacted {
+            from: text("").from,
+            target: "https://t.me/a/1".to_string(),
+            change,
+        };
+        let like = Change::Like(Amount {
+            amount: 0.5,
+            currency: "USD".to_string(),
+        });
+
+        // Reactions aren't held for an amount.
+        let likes = bot.react(&reacted(like.clone()), 100);
+        assert_eq!(likes.len(), 1);
+        assert_eq!((likes[0].like.amount, likes[0].chat_id), (0.5, None));
+        bot.history.add(&likes[0], 100);
+
+        // Withdrawn before the like was sent.
+        assert!(bot.react(&reacted(Change::Unlike), 101).is_empty());
+        let entry = bot.history.find(&likes[0].like_key()).unwrap();
+        assert_eq!(entry.status, Status::Undone);
+
+        // Withdrawn after.
+        let likes = bot.react(&reacted(like), 102);
+        bot.history.add(&likes[0], 102);
+        bot.history.set_status(&likes[0].like_key(), Status::Sent);
+        let retractions = bot.react(&reacted(Change::Unlike), 103);
+        assert_eq!(retractions.len(), 1);
+        assert!(retractions[0].retract);
+        assert!(bot.react(&reacted(Change::Unlike), 104).is_empty());
+
+        // Amounts outside the limits.
+        for (amount, currency) in [(20.0, "USD"), (0.5, "GBP"), (-0.5, "USD")] {
+            let like = Change::Like(Amount {
+                amount,
+                currency: currency.to_string(),
+            });
+            assert!(bot.react(&reacted(like), 105).is_empty());
+        }
+
+        assert!(api.take().is_empty());
+    }
+
+    #[test]
+    fn test_claims() {
+        let api = Recorder::default();
+        let dir = TempDir::new("smartlike-telegram-bot-test-claims");
+        let mut bot = bot(&api, &dir, 0);
+        bot.handle(&text("/claim"), 100);
+        assert_eq!(
+            api.take(),
+            ["Please specify your channel, e.g. /claim @channel"]
+        );
+
+        bot.handle(&text("/claim @Smartlike_org"), 100);
+        let reply = api.take().remove(0);
+        let challenge = bot.settings.claim("7", "smartlike_org").unwrap();
+        assert!(reply.contains(&claim::code(&challenge.challenge)));
+        assert!(bot.take_claims().is_empty());
+
+        // Posted.
+        bot.handle(&text("/claim https://t.me/smartlike_org"), 200);
+        assert!(api.take()[0].contains("@smartlike_org"));
+        let claims = bot.take_claims();
+        assert_eq!(claims.len(), 1);
+        assert_eq!(claims[0].challenge.signature, challenge.signature);
+        let attestation = claims[0].attestation();
+        assert_eq!(
+            (attestation.id.as_str(), attestation.target.as_str()),
+            ("7", "https://t.me/smartlike_org")
+        );
+        assert!(attestation
+            .challenge
+            .verify(&bot.client.public_key())
+            .is_ok());
+
+        // Expired codes are replaced.
+        bot.handle(&text("/claim @smartlike_org"), 100 + 86400);
+        assert!(bot.take_claims().is_empty());
+        let renewed = bot.settings.claim("7", "smartlike_org").unwrap();
+        assert_ne!(renewed.challenge.nonce, challenge.challenge.nonce);
+    }
+
+    #[test]
+    fn test_groups() {
+        let api = Recorder::default();
+        let dir = TempDir::new("smartlike-telegram-bot-test-groups");
+        let mut bot = bot(&api, &dir, 10);
+        let group = |text_: &str, reply_to: Option<Result<String, Unsupported>>| Incoming {
+            chat_id: -5,
+            group: true,
+            reply_to,
+            ..text(text_)
+        };
+        let post = || Some(Ok("https://t.me/smartlike_org/42".to_string()));
+
+        // Conversations and forwards are ignored.
+        for msg in [
+            group("hello", None),
+            group("/help", None),
+            group("+1", None),
+            group("+1 agreed", post()),
+            Incoming {
+                forward: post(),
+                ..group("", None)
+            },
+        ] {
+            assert!(bot.handle(&msg, 100).is_empty());
+        }
+        assert!(api.take().is_empty());
+
+        // Likes aren't held for /amount.
+        let likes = bot.handle(&group("+1", post()), 100);
+        assert_eq!(likes.len(), 1);
+        assert_eq!(likes[0].like.target, "https://t.me/smartlike_org/42");
+        assert_eq!(likes[0].chat_id, Some(-5));
+        let likes = bot.handle(
+            &Incoming {
+                from: Sender {
+                    id: 8,
+                    ..Default::default()
+                },
+                ..group("/like@smartlike_org_bot 0.2 EUR", post())
+            },
+            100,
+        );
+        assert_eq!(likes[0].like.amount, 0.2);
+
+        for (msg, expected) in [
+            (group("/like", None), "Please reply /like"),
+            (
+                group("/like", Some(Err(Unsupported::NotChannelPost))),
+                "Only posts forwarded from channels",
+            ),
+            (group("/like 20 EUR", post()), "The amount can't exceed 10."),
+            (group("/group like off", None), "Only group admins"),
+        ] {
+            assert!(bot.handle(&msg, 101).is_empty());
+            let replies = api.take();
+            assert!(replies[0].starts_with(expected), "{}", replies[0]);
+        }
+
+        // Admins.
+        let admin = |text_: &str| Incoming {
+            admin: true,
+            ..group(text_, None)
+        };
+        for (text, expected) in [
+            ("/group +1 off", "+1: off"),
+            ("/group amount 0.3 EUR", "Amount: 0.3 EUR"),
+            ("/group max 1", "Maximum amount: 1"),
+            ("/group amount 30 EUR", "The amount can't exceed 10."),
+            ("/group amount 2 EUR", "The amount can't exceed 1."),
+            (
+                "/group max 0.2",
+                "can't be below the group's amount 0.3 EUR",
+            ),
+            ("/group max inf", "Admins change the settings"),
+            ("/group volume 11", "Admins change the settings"),
+        ] {
+            bot.handle(&admin(text), 102);
+            let replies = api.take();
+            assert!(replies[0].contains(expected), "{}", replies[0]);
+        }
+        assert!(bot.handle(&group("+1", post()), 103).is_empty());
+        let likes = bot.handle(
+            &Incoming {
+                from: Sender {
+                    id: 9,
+                    ..Default::default()
+                },
+                ..group("/like", post())
+            },
+            103,
+        );
+        assert_eq!(likes[0].like.amount, 0.3);
+        bot.handle(&group("/like 2 EUR", post()), 103);
+        assert_eq!(api.take(), ["The amount can't exceed 1."]);
+
+        // Members' own defaults come first and are held to the group's maximum.
+        bot.handle(&text("/settings 0.5 EUR"), 104);
+        api.take();
+        let likes = bot.handle(
+            &group("/like", Some(Ok("https://t.me/a/1".to_string()))),
+            104,
+        );
+        assert_eq!(
+            (likes[0].like.amount, likes[0].like.currency.as_str()),
+            (0.5, "EUR")
+        );
+        bot.handle(&admin("/group max 0.4"), 105);
+        api.take();
+        let reply = || Some(Ok("https://t.me/a/2".to_string()));
+        bot.handle(&admin("/group +1 on"), 105);
+        api.take();
+        assert!(bot.handle(&group("+1", reply()), 105).is_empty());
+        assert!(api.take().is_empty());
+        assert!(bot.handle(&group("/like", reply()), 105).is_empty());
+        assert_eq!(api.take(), ["The amount can't exceed 0.4."]);
+    }
+}
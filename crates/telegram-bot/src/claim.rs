@@ -0,0 +1,194 @@
+//! Channel ownership claims.
+//!
+//! `/claim @channel` issues a challenge signed by the bot. The creator posts
+//! its code in the channel description or a pinned message, sends the same
+//! command again, and the bot finds the code with `getChat` and attests that
+//! the user owns the channel. The network links the channel to the Smartlike
+//! account connected to the user.
+
+use crate::bot::{BotApi, Reply};
+use crate::locale::Locales;
+use crate::target;
+use smartlike_embed_lib::challenge::{Challenge, OwnershipAttestation, SignedChallenge};
+use smartlike_embed_lib::client::Client;
+
+/// Scope of claim challenges.
+pub const CLAIM_SCOPE: &str = "telegram-channel";
+
+/// Channel username from `@channel`, `channel` or a t.me link.
+pub fn channel(args: &str) -> Option<String> {
+    let name = args.trim();
+    let name = ["https://t.me/", "http://t.me/", "t.me/", "@"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+        .trim_end_matches('/');
+    let valid = (5..=32).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    valid.then(|| name.to_lowercase())
+}
+
+/// Text the creator posts in the channel.
+pub fn code(challenge: &Challenge) -> String {
+    format!("smartlike-claim-{}", challenge.nonce)
+}
+
+pub fn challenge(account: &str, channel: &str, now: u64, ttl: u32) -> Challenge {
+    Challenge::new(
+        account,
+        CLAIM_SCOPE,
+        &target::user_profile(channel),
+        now as u32,
+        ttl,
+    )
+}
+
+/// Claim to check once the creator says the code is posted.
+#[derive(Debug, Clone)]
+pub struct ClaimCheck {
+    pub user: String,
+    pub channel: String,
+    pub challenge: SignedChallenge,
+    pub chat_id: i64,
+    pub message_id: i64,
+    pub language: String,
+}
+
+impl ClaimCheck {
+    pub fn attestation(&self) -> OwnershipAttestation {
+        OwnershipAttestation {
+            platform: "telegram".to_string(),
+            id: self.user.clone(),
+            target: target::user_profile(&self.channel),
+            challenge: self.challenge.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct PinnedMessage {
+    text: Option<String>,
+    caption: Option<String>,
+}
+
+/// Part of `getChat` result the code can be posted in.
+#[derive(Deserialize, Debug)]
+struct ChatInfo {
+    description: Option<String>,
+    pinned_message: Option<PinnedMessage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatResponse {
+    ok: bool,
+    result: Option<ChatInfo>,
+    description: Option<String>,
+}
+
+fn contains_code(chat: &ChatInfo, code: &str) -> bool {
+    let pinned = chat.pinned_message.as_ref();
+    [
+        chat.description.as_deref(),
+        pinned.and_then(|m| m.text.as_deref()),
+        pinned.and_then(|m| m.caption.as_deref()),
+    ]
+    .iter()
+    .flatten()
+    .any(|text| text.contains(code))
+}
+
+/// Checks whether the code is posted in the channel. The client library's
+/// `Chat` has neither the description nor the pinned message, so the Bot API
+/// is called directly.
+async fn posted(token: &str, channel: &str, code: &str) -> Result<bool, String> {
+    let url = format!(
+        "https://api.telegram.org/bot{}/getChat?chat_id=@{}",
+        token, channel
+    );
+    let body = reqwest::get(&url)
+        .await
+        .map_err(|e| format!("getChat failed: {}", e))?
+        .text()
+        .await
+        .map_err(|e| format!("getChat failed: {}", e))?;
+    let response = serde_json::from_str::<ChatResponse>(&body)
+        .map_err(|e| format!("Failed to parse getChat response: {}", e))?;
+    match response.result {
+        Some(chat) if response.ok => Ok(contains_code(&chat, code)),
+        _ => Err(response.description.unwrap_or(body)),
+    }
+}
+
+/// Looks for the code in the channel, attests the ownership and replies with
+/// the result.
+pub async fn verify<A: BotApi>(
+    check: ClaimCheck,
+    token: &str,
+    client: &Client,
+    api: &A,
+    locales: &Locales,
+) {
+    let code = code(&check.challenge.challenge);
+    let channel = check.channel.as_str();
+    let (key, status) = match posted(token, channel, &code).await {
+        Ok(true) => match client.attest_ownership(&check.attestation()).await {
+            Ok(status) if status == "ok" => ("claim_confirmed", status),
+            Ok(status) => ("claim_failed", status),
+            Err(e) => ("claim_failed", e.to_string()),
+        },
+        Ok(false) => ("claim_not_found", String::new()),
+        Err(e) => {
+            warn!("Failed to check @{}: {}", channel, e);
+            ("claim_failed", e)
+        }
+    };
+    let text = locales.text(
+        &check.language,
+        key,
+        &[("channel", channel), ("code", &code), ("status", &status)],
+    );
+    api.send(Reply::new(check.chat_id, text).reply_to(check.message_id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claims() {
+        for (args, expected) in [
+            ("@Smartlike_org", Some("smartlike_org")),
+            ("smartlike_org", Some("smartlike_org")),
+            ("https://t.me/smartlike_org/", Some("smartlike_org")),
+            ("@abc", None),
+            ("@smart-like", None),
+            ("", None),
+        ] {
+            assert_eq!(channel(args).as_deref(), expected, "{}", args);
+        }
+
+        let challenge = challenge("bot", "smartlike_org", 1000, 60);
+        assert_eq!(challenge.domain, "https://t.me/smartlike_org");
+        let code = code(&challenge);
+
+        let response: ChatResponse = serde_json::from_str(&format!(
+            r#"{{"ok": true, "result": {{"id": -1001, "type": "channel", "description": "Blog. {}"}}}}"#,
+            code
+        ))
+        .unwrap();
+        assert!(contains_code(&response.result.unwrap(), &code));
+
+        let response: ChatResponse = serde_json::from_str(&format!(
+            r#"{{"ok": true, "result": {{"id": -1001, "type": "channel", "pinned_message": {{"message_id": 5, "caption": "{}"}}}}}}"#,
+            code
+        ))
+        .unwrap();
+        assert!(contains_code(&response.result.unwrap(), &code));
+
+        let response: ChatResponse = serde_json::from_str(
+            r#"{"ok": true, "result": {"id": -1001, "type": "channel", "description": "Blog"}}"#,
+        )
+        .unwrap();
+        assert!(!contains_code(&response.result.unwrap(), &code));
+    }
+}
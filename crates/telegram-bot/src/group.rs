@@ -0,0 +1,104 @@
+//! Group chats: members like channel posts forwarded to a group by replying
+//! `+1` or `/like [amount]` to them. Group admins choose the accepted
+//! replies and amounts with `/group`.
+
+use crate::amount::{self, Amount};
+
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct GroupSettings {
+    /// `/like` replies are liked.
+    pub like: bool,
+    /// `+1` replies are liked.
+    pub plus_one: bool,
+    /// Amount of replies without one from members without a default amount.
+    pub amount: Option<Amount>,
+    /// Largest amount of `/like`. The bot's `max_amount` if zero.
+    pub max_amount: f64,
+}
+
+impl GroupSettings {
+    /// Whether the amount is above the group's maximum.
+    pub fn exceeds(&self, amount: f64) -> bool {
+        self.max_amount > 0.0 && amount > self.max_amount
+    }
+}
+
+impl Default for GroupSettings {
+    fn default() -> GroupSettings {
+        GroupSettings {
+            like: true,
+            plus_one: true,
+            amount: None,
+            max_amount: 0.0,
+        }
+    }
+}
+
+/// Change of group settings by an admin.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GroupCommand {
+    Like(bool),
+    PlusOne(bool),
+    /// Amount as typed, e.g. "0.2 EUR". None restores members' defaults.
+    Amount(Option<String>),
+    MaxAmount(f64),
+}
+
+fn switch(value: &str) -> Option<bool> {
+    match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    }
+}
+
+impl GroupCommand {
+    /// Parses `/group` arguments, e.g. `like off`, `+1 on`, `amount 0.2 EUR`,
+    /// `amount default` or `max 1`.
+    pub fn parse(args: &str) -> Option<GroupCommand> {
+        let (name, value) = args.trim().split_once(' ')?;
+        let value = value.trim();
+        match name.to_lowercase().as_str() {
+            "like" => switch(value).map(GroupCommand::Like),
+            "+1" => switch(value).map(GroupCommand::PlusOne),
+            "amount" if value == "default" => Some(GroupCommand::Amount(None)),
+            "amount" => amount::parse(value).map(|_| GroupCommand::Amount(Some(value.to_string()))),
+            "max" => value
+                .parse::<f64>()
+                .ok()
+                .filter(|max| max.is_finite() && *max >= 0.0)
+                .map(GroupCommand::MaxAmount),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_commands() {
+        for (args, expected) in [
+            ("like off", Some(GroupCommand::Like(false))),
+            ("+1 on", Some(GroupCommand::PlusOne(true))),
+            (
+                "amount 0.2 EUR",
+                Some(GroupCommand::Amount(Some("0.2 EUR".to_string()))),
+            ),
+            ("amount default", Some(GroupCommand::Amount(None))),
+            ("max 1", Some(GroupCommand::MaxAmount(1.0))),
+            ("amount lots", None),
+            ("max -1", None),
+            ("max inf", None),
+            ("max NaN", None),
+            ("like", None),
+            ("like maybe", None),
+            ("", None),
+        ] {
+            assert_eq!(GroupCommand::parse(args), expected, "{}", args);
+        }
+        let settings = GroupSettings::default();
+        assert!(settings.like && settings.plus_one);
+    }
+}
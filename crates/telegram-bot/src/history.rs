@@ -0,0 +1,228 @@
+//! Recent likes of users, pending and sent, for /history and /undo.
+//!
+//! Entries are stored as `user/{user}/{ts}/{queue key}` so that a user's
+//! likes are ordered by time, with a `key/{queue key}` index used to update
+//! them from the queue worker.
+
+use crate::queue::QueuedLike;
+use rocksdb::{DBWithThreadMode, Direction, IteratorMode, MultiThreaded};
+use smartlike_embed_lib::client::Like;
+
+/// Entries kept per user.
+pub const HISTORY_SIZE: usize = 20;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Waiting in the queue.
+    Pending,
+    Sent,
+    /// Rejected by the network.
+    Failed,
+    /// Waiting for the retraction to be sent.
+    Retracting,
+    /// Removed from the queue or retracted.
+    Undone,
+}
+
+impl Status {
+    /// Message key of the status.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Status::Pending => "history_pending",
+            Status::Sent => "history_sent",
+            Status::Failed => "history_failed",
+            Status::Retracting => "history_retracting",
+            Status::Undone => "history_undone",
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Entry {
+    /// Queue key of the like.
+    pub key: String,
+    pub like: Like,
+    pub ts: u64,
+    pub status: Status,
+}
+
+pub struct History {
+    db: DBWithThreadMode<MultiThreaded>,
+}
+
+fn user_prefix(user: &str) -> String {
+    format!("user/{}/", user)
+}
+
+fn index_key(key: &str) -> String {
+    format!("key/{}", key)
+}
+
+impl History {
+    pub fn open(path: &str) -> Result<History, String> {
+        let db = DBWithThreadMode::<MultiThreaded>::open_default(path)
+            .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        Ok(History { db })
+    }
+
+    /// Records a queued like and drops the oldest entries of the user.
+    pub fn add(&self, queued: &QueuedLike, now: u64) {
+        let key = queued.like_key();
+        let entry = Entry {
+            key: key.clone(),
+            like: queued.like.clone(),
+            ts: now,
+            status: Status::Pending,
+        };
+        let entry_key = format!("{}{:020}/{}", user_prefix(&entry.like.id), now, key);
+        if let Err(e) = self.put(&entry_key, &entry) {
+            error!("Failed to add {} to history: {}", key, e);
+            return;
+        }
+        if let Err(e) = self.db.put(index_key(&key), &entry_key) {
+            error!("DB error: {}", e);
+        }
+        for old in self.keys(&entry.like.id).into_iter().skip(HISTORY_SIZE) {
+            if let Some(entry) = self.get(&old) {
+                // The index may point to a newer like of the same target.
+                if matches!(self.entry_key(&entry.key), Some(k) if k == old) {
+                    let _ = self.db.delete(index_key(&entry.key));
+                }
+            }
+            if let Err(e) = self.db.delete(old) {
+                error!("DB error: {}", e);
+            }
+        }
+    }
+
+    /// Recent likes of the user, newest first.
+    pub fn recent(&self, user: &str, count: usize) -> Vec<Entry> {
+        self.keys(user)
+            .iter()
+            .take(count)
+            .filter_map(|key| self.get(key))
+            .collect()
+    }
+
+    /// Entry of a queued like.
+    pub fn find(&self, key: &str) -> Option<Entry> {
+        self.entry_key(key)
+            .and_then(|entry_key| self.get(&entry_key))
+    }
+
+    /// Changes the status and returns the entry as it was.
+    pub fn set_status(&self, key: &str, status: Status) -> Option<Entry> {
+        let entry_key = self.entry_key(key)?;
+        let entry = self.get(&entry_key)?;
+        let updated = Entry {
+            status,
+            ..entry.clone()
+        };
+        if let Err(e) = self.put(&entry_key, &updated) {
+            error!("Failed to update {} in history: {}", key, e);
+        }
+        Some(entry)
+    }
+
+    /// Entry keys of the user, newest first.
+    fn keys(&self, user: &str) -> Vec<String> {
+        let prefix = user_prefix(user);
+        let mut keys: Vec<String> = self
+            .db
+            .iterator(IteratorMode::From(prefix.as_bytes(), Direction::Forward))
+            .map(|(key, _)| String::from_utf8_lossy(&key).to_string())
+            .take_while(|key| key.starts_with(&prefix))
+            .collect();
+        keys.reverse();
+        keys
+    }
+
+    fn entry_key(&self, key: &str) -> Option<String> {
+        match self.db.get(index_key(key)) {
+            Ok(value) => value.and_then(|v| String::from_utf8(v).ok()),
+            Err(e) => {
+                error!("DB error: {}", e);
+                None
+            }
+        }
+    }
+
+    fn get(&self, entry_key: &str) -> Option<Entry> {
+        match self.db.get(entry_key) {
+            Ok(Some(value)) => serde_json::from_slice(&value)
+                .map_err(|e| error!("Failed to parse history entry {}: {}", entry_key, e))
+                .ok(),
+            Ok(None) => None,
+            Err(e) => {
+                error!("DB error: {}", e);
+                None
+            }
+        }
+    }
+
+    fn put(&self, entry_key: &str, entry: &Entry) -> Result<(), String> {
+        let value = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        self.db
+            .put(entry_key, value)
+            .map_err(|e| format!("DB error: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smartlike_like_queue::testing::TempDir;
+
+    fn queued(user: &str, target: &str) -> QueuedLike {
+        QueuedLike::new(
+            Like {
+                platform: "telegram".to_string(),
+                id: user.to_string(),
+                target: target.to_string(),
+                amount: 0.5,
+                currency: "EUR".to_string(),
+                account: None,
+                recipient: None,
+            },
+            1,
+            10,
+        )
+    }
+
+    #[test]
+    fn test_history() {
+        let dir = TempDir::new("smartlike-telegram-bot-test-history");
+        let history = History::open(dir.path()).unwrap();
+
+        let a = queued("1", "https://t.me/a/1");
+        let b = queued("1", "https://t.me/b/1");
+        history.add(&a, 100);
+        history.add(&b, 200);
+        history.add(&queued("11", "https://t.me/c/1"), 300);
+
+        let recent = history.recent("1", 10);
+        assert_eq!(recent.len(), 2);
+        assert_eq!(recent[0].like.target, "https://t.me/b/1");
+        assert_eq!(recent[1].status, Status::Pending);
+        assert_eq!(history.recent("1", 1).len(), 1);
+        assert_eq!(history.recent("11", 10).len(), 1);
+
+        let previous = history.set_status(&a.like_key(), Status::Sent).unwrap();
+        assert_eq!(previous.status, Status::Pending);
+        assert_eq!(history.find(&a.like_key()).unwrap().status, Status::Sent);
+        assert!(history.set_status("unknown", Status::Sent).is_none());
+
+        // Only recent likes are kept.
+        for i in 0..HISTORY_SIZE {
+            history.add(
+                &queued("1", &format!("https://t.me/d/{}", i)),
+                1000 + i as u64,
+            );
+        }
+        let recent = history.recent("1", 100);
+        assert_eq!(recent.len(), HISTORY_SIZE);
+        assert_eq!(recent[HISTORY_SIZE - 1].like.target, "https://t.me/d/0");
+        assert!(history.find(&a.like_key()).is_none());
+    }
+}
@@ -0,0 +1,264 @@
+//! Rate limits of forwarded likes.
+//!
+//! Likes are counted per user and for all users in sliding windows. Liking the
+//! same target again within `duplicate_window_seconds` is rejected. Liked
+//! targets are kept in `Seen` to survive restarts once their likes are queued,
+//! counters and likes waiting for an amount are in memory.
+
+use crate::seen::Seen;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct LimitsConfiguration {
+    /// Likes a user can send per `user_window_seconds`. Unlimited if zero.
+    pub user_likes: usize,
+    pub user_window_seconds: u64,
+    /// Likes all users can send per `global_window_seconds`. Unlimited if zero.
+    pub global_likes: usize,
+    pub global_window_seconds: u64,
+    /// A user can like a target once per this window. Disabled if zero.
+    pub duplicate_window_seconds: u64,
+}
+
+/// Reasons why a like is rejected.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Limited {
+    /// The user sent too many likes. Seconds until the next one is accepted.
+    User(u64),
+    /// All users sent too many likes.
+    Global(u64),
+    /// The user liked the target recently.
+    Duplicate,
+}
+
+impl Limited {
+    /// Message key of the reason.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Limited::User(_) => "limit_user",
+            Limited::Global(_) => "limit_global",
+            Limited::Duplicate => "limit_duplicate",
+        }
+    }
+
+    /// Minutes until likes are accepted again, rounded up.
+    pub fn minutes(&self) -> u64 {
+        match self {
+            Limited::User(seconds) | Limited::Global(seconds) => seconds.div_ceil(60),
+            Limited::Duplicate => 0,
+        }
+    }
+}
+
+/// Timestamps of likes within a sliding window.
+struct Window {
+    limit: usize,
+    seconds: u64,
+    likes: VecDeque<u64>,
+}
+
+impl Window {
+    fn new(limit: usize, seconds: u64) -> Window {
+        Window {
+            limit,
+            seconds,
+            likes: VecDeque::new(),
+        }
+    }
+
+    /// Seconds until a like is accepted, None if it's accepted now.
+    fn wait(&mut self, now: u64) -> Option<u64> {
+        while matches!(self.likes.front(), Some(ts) if ts + self.seconds <= now) {
+            self.likes.pop_front();
+        }
+        if self.limit == 0 || self.likes.len() < self.limit {
+            return None;
+        }
+        self.likes.front().map(|ts| ts + self.seconds - now)
+    }
+}
+
+fn duplicate_key(user: &str, target: &str) -> String {
+    format!("like/{}/{}", user, target)
+}
+
+/// Targets users liked within `duplicate_window_seconds`, remembered when
+/// their likes are queued. A like lost before, e.g. a forward waiting for its
+/// amount during a restart, doesn't block the target.
+#[derive(Clone)]
+pub struct Duplicates {
+    seen: Arc<Seen>,
+    window_seconds: u64,
+}
+
+impl Duplicates {
+    pub fn new(seen: Arc<Seen>, config: &LimitsConfiguration) -> Duplicates {
+        Duplicates {
+            seen,
+            window_seconds: config.duplicate_window_seconds,
+        }
+    }
+
+    pub fn contains(&self, user: &str, target: &str, now: u64) -> bool {
+        self.window_seconds > 0 && self.seen.contains(&duplicate_key(user, target), now)
+    }
+
+    pub fn insert(&self, user: &str, target: &str, now: u64) {
+        if self.window_seconds > 0 {
+            self.seen
+                .insert(&duplicate_key(user, target), self.window_seconds, now);
+        }
+    }
+
+    pub fn remove(&self, user: &str, target: &str) {
+        self.seen.remove(&duplicate_key(user, target));
+    }
+}
+
+pub struct Limiter {
+    config: LimitsConfiguration,
+    users: HashMap<String, Window>,
+    global: Window,
+    last_purge: u64,
+    duplicates: Duplicates,
+    /// Likes accepted within the duplicate window, queued or not.
+    accepted: HashMap<String, u64>,
+}
+
+impl Limiter {
+    pub fn new(config: &LimitsConfiguration, seen: Arc<Seen>) -> Limiter {
+        Limiter {
+            config: config.clone(),
+            users: HashMap::new(),
+            global: Window::new(config.global_likes, config.global_window_seconds),
+            last_purge: 0,
+            duplicates: Duplicates::new(seen, config),
+            accepted: HashMap::new(),
+        }
+    }
+
+    /// Counts the like if it's accepted.
+    pub fn check(&mut self, user: &str, target: &str, now: u64) -> Result<(), Limited> {
+        self.purge(now);
+
+        let key = duplicate_key(user, target);
+        let window_seconds = self.config.duplicate_window_seconds;
+        if window_seconds > 0
+            && (self.duplicates.contains(user, target, now)
+                || matches!(self.accepted.get(&key), Some(ts) if ts + window_seconds > now))
+        {
+            return Err(Limited::Duplicate);
+        }
+
+        let (user_likes, user_window_seconds) =
+            (self.config.user_likes, self.config.user_window_seconds);
+        let window = self
+            .users
+            .entry(user.to_string())
+            .or_insert_with(|| Window::new(user_likes, user_window_seconds));
+        if let Some(wait) = window.wait(now) {
+            return Err(Limited::User(wait));
+        }
+        if let Some(wait) = self.global.wait(now) {
+            return Err(Limited::Global(wait));
+        }
+
+        window.likes.push_back(now);
+        self.global.likes.push_back(now);
+        if window_seconds > 0 {
+            self.accepted.insert(key, now);
+        }
+        Ok(())
+    }
+
+    /// Allows the user to like the target again, e.g. after the like was
+    /// taken back.
+    pub fn forget(&mut self, user: &str, target: &str) {
+        self.accepted.remove(&duplicate_key(user, target));
+        self.duplicates.remove(user, target);
+    }
+
+    /// Drops counters of users who sent no likes within their window. Runs
+    /// at most once per window.
+    fn purge(&mut self, now: u64) {
+        if self.last_purge + self.config.user_window_seconds > now {
+            return;
+        }
+        self.last_purge = now;
+        self.users.retain(
+            |_, window| matches!(window.likes.back(), Some(ts) if ts + window.seconds > now),
+        );
+        let window_seconds = self.config.duplicate_window_seconds;
+        self.accepted.retain(|_, ts| *ts + window_seconds > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smartlike_like_queue::testing::TempDir;
+
+    fn open(dir: &TempDir, config: LimitsConfiguration) -> (Limiter, Duplicates) {
+        let seen = Arc::new(Seen::open(dir.path()).unwrap());
+        (
+            Limiter::new(&config, seen.clone()),
+            Duplicates::new(seen, &config),
+        )
+    }
+
+    #[test]
+    fn test_user_and_global_limits() {
+        let dir = TempDir::new("smartlike-telegram-bot-test-limits");
+        let (mut limiter, _) = open(
+            &dir,
+            LimitsConfiguration {
+                user_likes: 2,
+                user_window_seconds: 60,
+                global_likes: 3,
+                global_window_seconds: 10,
+                ..Default::default()
+            },
+        );
+        assert_eq!(limiter.check("1", "a", 100), Ok(()));
+        assert_eq!(limiter.check("1", "b", 101), Ok(()));
+        assert_eq!(limiter.check("1", "c", 102), Err(Limited::User(58)));
+        assert_eq!(Limited::User(58).minutes(), 1);
+        assert_eq!(limiter.check("2", "a", 102), Ok(()));
+        assert_eq!(limiter.check("3", "a", 103), Err(Limited::Global(7)));
+        // Rejected likes aren't counted.
+        assert_eq!(limiter.check("3", "a", 110), Ok(()));
+        assert_eq!(limiter.check("1", "c", 160), Ok(()));
+
+        // Unlimited.
+        let dir = TempDir::new("smartlike-telegram-bot-test-no-limits");
+        let (mut limiter, _) = open(&dir, LimitsConfiguration::default());
+        for i in 0..100 {
+            assert_eq!(limiter.check("1", "a", i), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let config = LimitsConfiguration {
+            duplicate_window_seconds: 3600,
+            ..Default::default()
+        };
+        let dir = TempDir::new("smartlike-telegram-bot-test-duplicates");
+        let (mut limiter, duplicates) = open(&dir, config.clone());
+        assert_eq!(limiter.check("1", "a", 100), Ok(()));
+        assert_eq!(limiter.check("1", "a", 200), Err(Limited::Duplicate));
+        assert_eq!(limiter.check("2", "a", 200), Ok(()));
+        limiter.forget("2", "a");
+        assert_eq!(limiter.check("2", "a", 201), Ok(()));
+        assert_eq!(limiter.check("3", "a", 202), Ok(()));
+
+        // Survives restarts once queued, likes lost before don't block.
+        duplicates.insert("1", "a", 100);
+        drop(limiter);
+        let (mut limiter, _) = open(&dir, config);
+        assert_eq!(limiter.check("1", "a", 300), Err(Limited::Duplicate));
+        assert_eq!(limiter.check("3", "a", 300), Ok(()));
+        assert_eq!(limiter.check("1", "a", 3700), Ok(()));
+    }
+}
@@ -0,0 +1,138 @@
+//! Bot messages in the users' languages.
+//!
+//! Messages are loaded from `<language>.toml` files mapping message keys to
+//! templates. Keys missing in a language fall back to English.
+
+use std::collections::{BTreeMap, HashMap};
+
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+pub struct Locales {
+    languages: BTreeMap<String, HashMap<String, String>>,
+}
+
+impl Locales {
+    /// Loads all `*.toml` files of a directory.
+    pub fn load(path: &str) -> Result<Locales, String> {
+        let entries =
+            std::fs::read_dir(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        let mut files = vec![];
+        for entry in entries {
+            let path = entry.map_err(|e| e.to_string())?.path();
+            if path.extension() != Some(std::ffi::OsStr::new("toml")) {
+                continue;
+            }
+            let language = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .unwrap_or_default()
+                .to_lowercase();
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            files.push((language, contents));
+        }
+        Locales::parse(files)
+    }
+
+    /// Parses (language, contents) pairs.
+    pub fn parse(files: Vec<(String, String)>) -> Result<Locales, String> {
+        let mut languages = BTreeMap::new();
+        for (language, contents) in files {
+            let texts = toml::from_str::<HashMap<String, String>>(&contents)
+                .map_err(|e| format!("Failed to parse {} messages: {}", language, e))?;
+            languages.insert(language, texts);
+        }
+        if !languages.contains_key(DEFAULT_LANGUAGE) {
+            return Err(format!("Messages in {} are missing", DEFAULT_LANGUAGE));
+        }
+        Ok(Locales { languages })
+    }
+
+    pub fn languages(&self) -> Vec<&str> {
+        self.languages.keys().map(|l| l.as_str()).collect()
+    }
+
+    pub fn has(&self, language: &str) -> bool {
+        self.languages.contains_key(language)
+    }
+
+    /// Language for a Telegram `language_code`, e.g. "pt-br" or "ru".
+    pub fn language(&self, code: Option<&str>) -> String {
+        let code = code.unwrap_or_default().to_lowercase();
+        let primary = code.split(['-', '_']).next().unwrap_or_default();
+        let language = if self.has(&code) {
+            &code
+        } else if self.has(primary) {
+            primary
+        } else {
+            DEFAULT_LANGUAGE
+        };
+        language.to_string()
+    }
+
+    /// Message with `{name}` placeholders replaced with the arguments.
+    pub fn text(&self, language: &str, key: &str, args: &[(&str, &str)]) -> String {
+        let template = [language, DEFAULT_LANGUAGE]
+            .into_iter()
+            .find_map(|l| self.languages.get(l).and_then(|texts| texts.get(key)));
+        let mut text = match template {
+            Some(template) => template.clone(),
+            None => {
+                error!("Message {} is missing", key);
+                return key.to_string();
+            }
+        };
+        for (name, value) in args {
+            text = text.replace(&format!("{{{}}}", name), value);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locales() {
+        let locales = Locales::load("./locales").unwrap();
+        assert!(locales.languages().contains(&"en"));
+        assert!(locales.has("ru"));
+
+        for (code, language) in [
+            (Some("ru"), "ru"),
+            (Some("ru-RU"), "ru"),
+            (Some("EN"), "en"),
+            (Some("xx"), "en"),
+            (None, "en"),
+        ] {
+            assert_eq!(locales.language(code), language, "{:?}", code);
+        }
+
+        // Every language has the same keys as English.
+        let en = &locales.languages["en"];
+        for (language, texts) in &locales.languages {
+            for key in en.keys() {
+                assert!(texts.contains_key(key), "{} is missing {}", language, key);
+            }
+        }
+
+        let locales = Locales::parse(vec![
+            (
+                "en".to_string(),
+                "liked = \"Liked {target}\"\nhello = \"Hello\"".to_string(),
+            ),
+            ("ru".to_string(), "hello = \"Привет\"".to_string()),
+        ])
+        .unwrap();
+        assert_eq!(locales.text("ru", "hello", &[]), "Привет");
+        assert_eq!(
+            locales.text("ru", "liked", &[("target", "https://t.me/a/1")]),
+            "Liked https://t.me/a/1"
+        );
+        assert_eq!(locales.text("de", "hello", &[]), "Hello");
+        assert_eq!(locales.text("en", "missing", &[]), "missing");
+
+        assert!(Locales::parse(vec![("ru".to_string(), "".to_string())]).is_err());
+    }
+}
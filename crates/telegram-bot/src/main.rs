@@ -1,16 +1,52 @@
-use rocksdb::{DBWithThreadMode, IteratorMode, MultiThreaded};
-use uuid::Uuid;
 #[macro_use]
 extern crate serde;
-use futures::StreamExt;
-use lru::LruCache;
-use sha2::Digest;
-use smartlike_embed_lib::client::{Client, Like};
-use std::{fs::File, io::prelude::*, thread, time::Duration};
+use smartlike_embed_lib::client::Client;
+use smartlike_like_queue::Queue;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{fs::File, io::prelude::*, time::Duration};
 use telegram_bot::*;
 #[macro_use]
 extern crate log;
 
+mod amount;
+mod bot;
+mod claim;
+mod group;
+mod history;
+mod limits;
+mod locale;
+mod polling;
+mod queue;
+mod reaction;
+mod seen;
+mod settings;
+mod target;
+mod telegram;
+mod webhook;
+
+use amount::Drafts;
+use bot::{Bot, BotApi, BotConfiguration, Reply};
+use history::{History, Status};
+use limits::{Duplicates, LimitsConfiguration};
+use locale::Locales;
+use queue::QueuedLike;
+use reaction::{ReactionsConfiguration, Received};
+use seen::Seen;
+use settings::Settings;
+use webhook::WebhookConfiguration;
+
+/// How the bot receives updates.
+#[derive(Deserialize, Serialize, Clone, Default, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    /// Long polling with `getUpdates`.
+    #[default]
+    Polling,
+    /// Telegram posts updates to `webhook.listen_address`.
+    Webhook,
+}
+
 #[derive(Deserialize, Serialize, Clone, Default, Debug)]
 pub struct Configuration {
     telegram_bot_token: String,
@@ -19,7 +55,49 @@ pub struct Configuration {
     smartlike_account: String,
     smartlike_key: String,
     log_target: String,
-    media_group_id_cache_size: usize,
+    #[serde(default)]
+    settings_path: String,
+    /// Location of media groups and liked targets seen recently.
+    #[serde(default)]
+    seen_path: String,
+    /// Location of users' recent likes.
+    #[serde(default)]
+    history_path: String,
+    /// How long media groups are remembered.
+    #[serde(default = "default_media_group_ttl_seconds")]
+    media_group_ttl_seconds: u64,
+    /// Directory of message translations.
+    #[serde(default)]
+    locales_path: String,
+    /// Currencies users can donate in. Any currency if empty.
+    #[serde(default)]
+    currencies: Vec<String>,
+    /// Maximum amount of a single like. Unlimited if zero.
+    #[serde(default)]
+    max_amount: f64,
+    /// How long forwards wait for `/amount`.
+    #[serde(default)]
+    amount_wait_seconds: u64,
+    #[serde(default)]
+    limits: LimitsConfiguration,
+    /// Reactions liked in chats the bot administers.
+    #[serde(default)]
+    reactions: ReactionsConfiguration,
+    #[serde(default)]
+    mode: Mode,
+    #[serde(default)]
+    webhook: WebhookConfiguration,
+}
+
+fn default_media_group_ttl_seconds() -> u64 {
+    86400
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
 }
 
 #[tokio::main]
@@ -43,8 +121,40 @@ async fn main() -> Result<(), Error> {
     f.read_to_string(&mut contents).unwrap();
     let config = toml::from_str::<Configuration>(&contents).unwrap();
 
-    let db =
-        std::sync::Arc::new(DBWithThreadMode::<MultiThreaded>::open_default("./queue").unwrap());
+    let queue = Queue::<QueuedLike>::open("./queue").unwrap();
+
+    let settings = Settings::open(if config.settings_path.is_empty() {
+        "./settings"
+    } else {
+        &config.settings_path
+    })
+    .unwrap();
+    let locales = Arc::new(
+        Locales::load(if config.locales_path.is_empty() {
+            "./locales"
+        } else {
+            &config.locales_path
+        })
+        .unwrap(),
+    );
+    let drafts = Arc::new(Mutex::new(Drafts::new(config.amount_wait_seconds)));
+    let seen = Arc::new(
+        Seen::open(if config.seen_path.is_empty() {
+            "./seen"
+        } else {
+            &config.seen_path
+        })
+        .unwrap(),
+    );
+    let history = Arc::new(
+        History::open(if config.history_path.is_empty() {
+            "./history"
+        } else {
+            &config.history_path
+        })
+        .unwrap(),
+    );
+    let duplicates = Duplicates::new(seen.clone(), &config.limits);
 
     let client = Client::new(
         config.smartlike_account.clone(),
@@ -52,266 +162,185 @@ async fn main() -> Result<(), Error> {
         config.network_address,
     );
 
-    let (tx, rx) = async_channel::unbounded::<(String, Like)>();
+    let api = Api::new(config.telegram_bot_token.clone());
 
     // Load the queue from previous run.
-    let iter = db.iterator(IteratorMode::Start);
-    for (key, value) in iter {
-        info!("Found pending request {:?} {:?}", key, value);
-        if let (Ok(k), Ok(v)) = (
-            String::from_utf8(key.to_vec()),
-            String::from_utf8(value.to_vec()),
-        ) {
-            if let Ok(like) = serde_json::from_str(&v) {
-                match tx.send((k, like)).await {
-                    Ok(_) => {
-                        continue;
-                    }
-                    Err(e) => {
-                        error!("TX Error: {}", e);
-                    }
-                }
-            }
-            error!("Failed to enqueue pending receipt. Rejecting.");
-            match db.delete(key) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("Failed to delete db record: {}", e);
-                }
-            }
-        }
-    }
+    queue.resume().await;
 
     tokio::spawn({
         let client = client.clone();
-        let db = db.clone();
-        let tx = tx.clone();
+        let queue = queue.clone();
+        let api = api.clone();
+        let locales = locales.clone();
+        let history = history.clone();
+        let duplicates = duplicates.clone();
         async move {
             loop {
-                match rx.recv().await {
-                    Ok(msg) => {
-                        match client.forward_like(&msg.1).await {
-                            Ok(_) => db.delete(msg.0).unwrap_or_else(|e| {
-                                panic!("Failed to delete db record: {}", e)
-                            }),
+                match queue.next().await {
+                    Some(msg) => {
+                        let like_key = msg.1.like_key();
+                        if !msg.1.retract
+                            && matches!(history.find(&like_key), Some(entry) if entry.status == Status::Undone)
+                        {
+                            info!("Like of {} was undone", msg.1.like.target);
+                            queue.done(&msg.0);
+                            continue;
+                        }
+                        let result = if msg.1.retract {
+                            client.retract_like(&msg.1.like).await
+                        } else {
+                            client.forward_like(&msg.1.like).await
+                        };
+                        match result {
+                            Ok(status) => {
+                                queue.done(&msg.0);
+                                let sent = status == "ok";
+                                if msg.1.retract {
+                                    history.set_status(
+                                        &like_key,
+                                        if sent { Status::Undone } else { Status::Sent },
+                                    );
+                                } else {
+                                    let previous = history.set_status(
+                                        &like_key,
+                                        if sent { Status::Sent } else { Status::Failed },
+                                    );
+                                    if sent
+                                        && matches!(previous, Some(entry) if entry.status == Status::Undone)
+                                    {
+                                        // Undone while it was being sent.
+                                        history.set_status(&like_key, Status::Retracting);
+                                        queue::enqueue(
+                                            &queue,
+                                            &history,
+                                            &duplicates,
+                                            msg.1.retraction(),
+                                            now(),
+                                        )
+                                        .await;
+                                        continue;
+                                    }
+                                }
+                                if let (Some(chat), Some(message)) =
+                                    (msg.1.chat_id, msg.1.message_id)
+                                {
+                                    api.send(
+                                        Reply::new(
+                                            chat,
+                                            queue::feedback(&locales, &msg.1, &status),
+                                        )
+                                        .reply_to(message),
+                                    );
+                                }
+                            }
                             Err(e) => {
                                 // Communications issues? - Wait and retry.
                                 error!("Failed to forward like: {}", e);
-                                thread::sleep(Duration::from_secs(5));
-                                tx.send(msg).await.unwrap_or_else(|e| panic!("TX Error: {}", e));
+                                queue.retry(msg.0, msg.1).await;
                             }
                         }
                     }
-                    Err(_) => {}
+                    None => break,
                 }
             }
         }
     });
 
-    let mut media_group_ids: LruCache<String, String> =
-        LruCache::new(config.media_group_id_cache_size);
-    let api = Api::new(config.telegram_bot_token);
-    let mut stream = api.stream();
-
-    while let Some(update) = stream.next().await {
-        // If the received update contains a new message...
-        let update = update?;
-        if let UpdateKind::Message(msg) = update.kind {
-            trace!("message: {:?}", &msg);
-
-            if msg.from.is_bot {
-                warn!("Bot ignored");
-                continue;
-            }
-
-            match &msg.kind {
-                telegram_bot::types::MessageKind::Text { data, .. } => {
-                    trace!("Text: {}", data);
-                    match data.find("/start ") {
-                        Some(v) => {
-                            if v == 0 {
-                                let pars: Vec<&str> = data.split(' ').collect();
-                                if pars.len() == 2 {
-                                    let args: Vec<&str> = pars[1].split('_').collect();
-                                    if args.len() == 2 {
-                                        match (Uuid::parse_str(args[0]), args[1].parse::<u32>()) {
-                                            (Ok(account), Ok(donation)) => {
-                                                let mut username = msg.from.first_name.clone();
-                                                if let Some(v) = msg.from.last_name.clone() {
-                                                    username.push_str(" ");
-                                                    username.push_str(&v);
-                                                }
-                                                if let Some(v) = msg.from.username.clone() {
-                                                    username.push_str(" (@");
-                                                    username.push_str(&v);
-                                                    username.push_str(")");
-                                                }
-                                                let float_donation = donation as f32 / 100.0;
-                                                let to_sign = format!(
-                                                    "telegram{}{}{}",
-                                                    msg.from.id, account, float_donation
-                                                );
-                                                let sig = client.sign(&to_sign);
-                                                let url = format!("https://smartlike.org/confirm?platform=telegram&id={}&name={}&account={}&amount={}&proxy={}&signature={}", msg.from.id, username, account, float_donation, config.smartlike_account, sig);
-
-                                                let mut keyboard =
-                                                    telegram_bot::types::InlineKeyboardMarkup::new(
-                                                    );
-                                                let mut v: Vec<
-                                                    telegram_bot::types::InlineKeyboardButton,
-                                                > = Vec::new();
-                                                v.push(
-                                                    telegram_bot::types::InlineKeyboardButton::url(
-                                                        "confirm", url,
-                                                    ),
-                                                );
-                                                keyboard.add_row(v);
-
-                                                api.spawn(msg.to_source_chat().text(
-                                                    "Smartlike bot is a free micro-donation processor. Forward your favorite posts to the bot to support authors and help other users discover great content.\n<a href=\"https://smartlike.org/docs\">read more</a> | <a href='https://smartlike.org/channel/t.me'>charts</a>\n\nPlease follow the link to confirm connection to your Smartlike account:"
-                                                ).parse_mode(telegram_bot::types::ParseMode::Html).reply_markup(keyboard));
-                                            }
-                                            (_, _) => {
-                                                api.spawn(
-                                                    msg.to_source_chat()
-                                                        .text("Wrong parameter(s)."),
-                                                );
-                                            }
-                                        }
-                                    }
-                                }
-                                continue;
-                            }
-                        }
-                        _ => {}
-                    }
-
-                    if data == "/settings" {
-                        let mut keyboard = telegram_bot::types::InlineKeyboardMarkup::new();
-                        let mut v: Vec<telegram_bot::types::InlineKeyboardButton> = Vec::new();
-                        v.push(telegram_bot::types::InlineKeyboardButton::url(
-                            "settings",
-                            "https://smartlike.org/docs/how-to-connect-telegram",
-                        ));
-                        keyboard.add_row(v);
-                        api.spawn(
-                            msg.to_source_chat()
-                                .text("Please follow the link for settings.")
-                                .reply_markup(keyboard),
-                        );
-                        continue;
-                    } else if data == "/help" {
-                        api.spawn(msg.to_source_chat().text(
-                            "Smartlike bot is a free micro-donation processor. Forward your favorite posts to the bot to support authors and help other users discover great content.\n<a href=\"https://smartlike.org/docs\">read more</a> | <a href='https://smartlike.org/channel/t.me'>charts</a> | <a href='https://smartlike.org/docs/how-to-connect-telegram'>settings</a>"
-                        ).parse_mode(telegram_bot::types::ParseMode::Html));
-                        continue;
-                    } else if data == "/start" {
-                        let url = "https://smartlike.org/docs/how-to-connect-telegram";
-
-                        let mut keyboard = telegram_bot::types::InlineKeyboardMarkup::new();
-                        let mut v: Vec<telegram_bot::types::InlineKeyboardButton> = Vec::new();
-                        v.push(telegram_bot::types::InlineKeyboardButton::url(
-                            "connect", url,
-                        ));
-                        keyboard.add_row(v);
-
-                        api.spawn(
-                            msg.to_source_chat().text(
-                                "Smartlike bot is a free micro-donation processor. Forward your favorite posts to the bot to support authors and help other users discover great content.\n<a href=\"https://smartlike.org/docs\">read more</a> | <a href='https://smartlike.org/channel/t.me'>charts</a>\n\nPlease follow the link to connect:",
-                            )
-                            .parse_mode(telegram_bot::types::ParseMode::Html).reply_markup(keyboard));
-                        continue;
-                    }
-                }
-                telegram_bot::types::MessageKind::Photo { media_group_id, .. } => {
-                    if let Some(id) = media_group_id {
-                        if media_group_ids.contains(id) {
-                            debug!("Skipping media group {}", id);
-                            continue;
-                        } else {
-                            media_group_ids.put(id.to_string(), id.to_string());
-                        }
-                    }
-                }
-                telegram_bot::types::MessageKind::Video { media_group_id, .. } => {
-                    if let Some(id) = media_group_id {
-                        if media_group_ids.contains(id) {
-                            debug!("Skipping media group {}", id);
-                            continue;
-                        } else {
-                            media_group_ids.put(id.to_string(), id.to_string());
-                        }
-                    }
-                }
-
-                _ => {
-                    trace!("No message id.");
+    // Send forwards nobody set an amount for.
+    tokio::spawn({
+        let queue = queue.clone();
+        let drafts = drafts.clone();
+        let history = history.clone();
+        let duplicates = duplicates.clone();
+        async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                let likes = drafts.lock().unwrap().expired(now());
+                for queued in likes {
+                    queue::enqueue(&queue, &history, &duplicates, queued, now()).await;
                 }
             }
+        }
+    });
 
-            match msg.forward {
-                Some(forward) => {
-                    let mut channel_id = None;
-                    let mut msg_id = 0;
-                    match forward.from {
-                        telegram_bot::types::ForwardFrom::Channel {
-                            channel,
-                            message_id,
-                            ..
-                        } => match channel.username {
-                            Some(username) => {
-                                channel_id = Some(username);
-                                msg_id = message_id;
-                            }
-                            _ => {
-                                warn!("No message username.");
-                            }
-                        },
-                        _ => {
-                            warn!("No message id.");
-                        }
-                    }
+    // Forget media groups and likes out of their windows.
+    tokio::spawn({
+        let seen = seen.clone();
+        async move {
+            loop {
+                let deleted = seen.purge(now());
+                debug!("Purged {} seen keys", deleted);
+                tokio::time::sleep(Duration::from_secs(3600)).await;
+            }
+        }
+    });
 
-                    match channel_id {
-                        Some(id) => {
-                            let like = Like {
-                                platform: "telegram".to_string(),
-                                id: msg.from.id.to_string(),
-                                target: format!("https://t.me/{}/{}", id, msg_id).to_string(),
-                                amount: 0.0,
-                                currency: "".to_string(),
-                            };
+    let mut bot = Bot::new(
+        api.clone(),
+        client.clone(),
+        BotConfiguration {
+            currencies: config.currencies,
+            max_amount: config.max_amount,
+            media_group_ttl_seconds: config.media_group_ttl_seconds,
+            limits: config.limits,
+        },
+        settings,
+        locales.clone(),
+        drafts,
+        seen,
+        history.clone(),
+    );
 
-                            match serde_json::to_string(&like) {
-                                Ok(message) => {
-                                    let mut hasher = sha2::Sha256::new();
-                                    sha2::Digest::input(&mut hasher, message.as_bytes());
-                                    let key = hex::encode(hasher.result().as_slice().to_vec());
+    // Updates received by polling or by the webhook.
+    let (updates_tx, updates) = async_channel::unbounded::<Received>();
+    let bot_api = format!("https://api.telegram.org/bot{}", config.telegram_bot_token);
+    match config.mode {
+        Mode::Polling => {
+            tokio::spawn(polling::run(bot_api, updates_tx));
+        }
+        Mode::Webhook => {
+            if !config.webhook.url.is_empty() {
+                webhook::register(&bot_api, &config.webhook).await.unwrap();
+            } else if !config.reactions.amounts.is_empty()
+                && !webhook::receives_reactions(&bot_api).await.unwrap()
+            {
+                panic!("Reactions are liked, but the webhook isn't registered for message_reaction updates");
+            }
+            let server = webhook::start(&config.webhook, updates_tx).unwrap();
+            tokio::spawn(server);
+        }
+    }
 
-                                    match db.put(key.clone(), message.clone()) {
-                                        Ok(_) => match tx.send((key, like)).await {
-                                            Ok(_) => {}
-                                            Err(e) => {
-                                                error!("TX Error: {}", e);
-                                            }
-                                        },
-                                        Err(e) => {
-                                            error!("DB error: {}", e);
-                                        }
-                                    }
-                                }
-                                _ => {}
-                            }
-                        }
-                        _ => {
-                            error!("No user id.");
-                        }
-                    }
+    while let Ok(received) = updates.recv().await {
+        let likes = match received {
+            Received::Update(update, media_group_id) => match update.kind {
+                UpdateKind::Message(msg) => {
+                    trace!("message: {:?}", &msg);
+                    let mut incoming = telegram::incoming(&msg, media_group_id);
+                    telegram::check_admin(&api, &msg, &mut incoming).await;
+                    bot.handle(&incoming, now())
                 }
-                _ => {
-                    api.spawn(msg.to_source_chat().text("Unknown command. Please use /help, /settings or forward posts to this bot."));
+                _ => vec![],
+            },
+            Received::Reaction(reaction) => {
+                trace!("reaction: {:?}", &reaction);
+                match reaction.reacted(&config.reactions) {
+                    Some(reacted) => bot.react(&reacted, now()),
+                    None => vec![],
                 }
             }
+        };
+        for queued in likes {
+            queue::enqueue(&queue, &history, &duplicates, queued, now()).await;
+        }
+        for check in bot.take_claims() {
+            tokio::spawn({
+                let token = config.telegram_bot_token.clone();
+                let client = client.clone();
+                let api = api.clone();
+                let locales = locales.clone();
+                async move { claim::verify(check, &token, &client, &api, &locales).await }
+            });
         }
     }
 
@@ -0,0 +1,159 @@
+//! Polling mode: the bot asks Telegram for updates with `getUpdates`. The raw
+//! updates are parsed like in webhook mode, as the client library drops the
+//! album ids of documents and audio.
+
+use crate::reaction::{Received, ALLOWED_UPDATES};
+use std::time::Duration;
+
+/// How long Telegram holds a request open while there are no updates.
+const TIMEOUT_SECONDS: u64 = 30;
+
+/// Delay before a failed request is repeated.
+const RETRY_SECONDS: u64 = 5;
+
+#[derive(Deserialize)]
+struct UpdatesResponse {
+    ok: bool,
+    #[serde(default)]
+    result: Vec<serde_json::Value>,
+    description: Option<String>,
+}
+
+/// Requests updates starting from `offset` and sends them to `tx`. Returns
+/// the offset of the next request.
+async fn poll(
+    http: &reqwest::Client,
+    api: &str,
+    offset: i64,
+    tx: &async_channel::Sender<Received>,
+) -> Result<i64, String> {
+    let body = http
+        .get(format!("{}/getUpdates", api))
+        .query(&[
+            ("offset", offset.to_string()),
+            ("timeout", TIMEOUT_SECONDS.to_string()),
+            ("allowed_updates", ALLOWED_UPDATES.to_string()),
+        ])
+        .send()
+        .await
+        .map_err(|e| format!("getUpdates failed: {}", e))?
+        .text()
+        .await
+        .map_err(|e| format!("getUpdates failed: {}", e))?;
+    let response = serde_json::from_str::<UpdatesResponse>(&body)
+        .map_err(|e| format!("Failed to parse getUpdates response: {}", e))?;
+    if !response.ok {
+        return Err(response.description.unwrap_or(body));
+    }
+    let mut next = offset;
+    for update in response.result {
+        if let Some(id) = update["update_id"].as_i64() {
+            next = next.max(id + 1);
+        }
+        match Received::parse(update.to_string().as_bytes()) {
+            Ok(received) => tx
+                .send(received)
+                .await
+                .map_err(|e| format!("TX Error: {}", e))?,
+            // Confirmed by the next offset like unknown updates of the webhook.
+            Err(e) => warn!("Failed to parse update: {}", e),
+        }
+    }
+    Ok(next)
+}
+
+/// Receives updates until `tx` is closed. `api` is the Bot API URL of the
+/// bot, "https://api.telegram.org/bot{token}".
+pub async fn run(api: String, tx: async_channel::Sender<Received>) {
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(TIMEOUT_SECONDS + 10))
+        .build()
+        .unwrap();
+    let mut offset = 0;
+    while !tx.is_closed() {
+        match poll(&http, &api, offset, &tx).await {
+            Ok(next) => offset = next,
+            Err(e) => {
+                error!("Failed to receive updates: {}", e);
+                tokio::time::sleep(Duration::from_secs(RETRY_SECONDS)).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    /// Local stand-in Bot API returning the updates starting from the offset.
+    /// Like Telegram, it sends reactions only if they are asked for.
+    fn api(updates: Vec<serde_json::Value>) -> String {
+        let server = HttpServer::new(move || {
+            App::new().app_data(web::Data::new(updates.clone())).route(
+                "/bottoken/getUpdates",
+                web::get().to(
+                    |query: web::Query<HashMap<String, String>>,
+                     updates: web::Data<Vec<serde_json::Value>>| async move {
+                        let offset: i64 = query["offset"].parse().unwrap();
+                        let reactions = query.get("allowed_updates").is_some_and(|allowed| {
+                            serde_json::from_str::<Vec<String>>(allowed)
+                                .unwrap()
+                                .iter()
+                                .any(|kind| kind == "message_reaction")
+                        });
+                        let result: Vec<_> = updates
+                            .iter()
+                            .filter(|update| update["update_id"].as_i64().unwrap() >= offset)
+                            .filter(|update| reactions || update.get("message_reaction").is_none())
+                            .collect();
+                        HttpResponse::Ok().json(json!({ "ok": true, "result": result }))
+                    },
+                ),
+            )
+        })
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let url = format!("http://{}/bottoken", server.addrs()[0]);
+        actix_rt::spawn(server.run());
+        url
+    }
+
+    #[actix_rt::test]
+    async fn test_poll() {
+        let read = |path: &str| -> serde_json::Value {
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+        };
+        let mut album = read("./test/forward_document.json");
+        album["update_id"] = 100000002.into();
+        album["message"]["media_group_id"] = "13540283846".into();
+        let mut reaction = read("./test/reaction.json");
+        reaction["update_id"] = 100000003.into();
+        let api = api(vec![read("./test/forward_user.json"), album, reaction]);
+
+        let (tx, rx) = async_channel::unbounded::<Received>();
+        let http = reqwest::Client::new();
+        let offset = poll(&http, &api, 0, &tx).await.unwrap();
+        assert_eq!(offset, 100000004);
+        let mut media_group_ids = vec![];
+        let mut reactions = 0;
+        while let Ok(received) = rx.try_recv() {
+            match received {
+                Received::Update(_, media_group_id) => media_group_ids.push(media_group_id),
+                Received::Reaction(_) => reactions += 1,
+            }
+        }
+        // Albums of documents are recognized like in webhook mode.
+        assert_eq!(media_group_ids, [None, Some("13540283846".to_string())]);
+        assert_eq!(reactions, 1);
+
+        assert_eq!(poll(&http, &api, offset, &tx).await.unwrap(), offset);
+        assert!(rx.try_recv().is_err());
+
+        // Errors of the Bot API.
+        let http_error = poll(&http, "http://127.0.0.1:1/bottoken", 0, &tx).await;
+        assert!(http_error.unwrap_err().starts_with("getUpdates failed"));
+    }
+}
@@ -0,0 +1,227 @@
+use crate::history::History;
+use crate::limits::Duplicates;
+use crate::locale::Locales;
+use smartlike_embed_lib::client::Like;
+use smartlike_like_queue::{like_key, Queue, Queued};
+
+/// Like waiting to be forwarded to the network, with the message it came
+/// from to report the result to.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct QueuedLike {
+    pub like: Like,
+    pub chat_id: Option<i64>,
+    pub message_id: Option<i64>,
+    /// Language of the reply.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Takes back the like instead of sending it.
+    #[serde(default)]
+    pub retract: bool,
+}
+
+impl QueuedLike {
+    pub fn new(like: Like, chat_id: i64, message_id: i64) -> QueuedLike {
+        QueuedLike {
+            like,
+            chat_id: Some(chat_id),
+            message_id: Some(message_id),
+            language: None,
+            retract: false,
+        }
+    }
+
+    /// Like whose result isn't replied to, e.g. a reaction in a channel.
+    pub fn without_reply(like: Like) -> QueuedLike {
+        QueuedLike {
+            like,
+            chat_id: None,
+            message_id: None,
+            language: None,
+            retract: false,
+        }
+    }
+
+    /// Parses a queue record. Records of older versions hold a bare `Like`.
+    pub fn parse(value: &str) -> Option<QueuedLike> {
+        serde_json::from_str::<QueuedLike>(value)
+            .or_else(|_| serde_json::from_str::<Like>(value).map(QueuedLike::without_reply))
+            .ok()
+    }
+
+    /// Retraction of the like, replied to in the same chat.
+    pub fn retraction(&self) -> QueuedLike {
+        QueuedLike {
+            retract: true,
+            ..self.clone()
+        }
+    }
+
+    /// Key of the like in the history.
+    pub fn like_key(&self) -> String {
+        like_key(&self.like)
+    }
+}
+
+impl Queued for QueuedLike {
+    fn key(&self) -> String {
+        if self.retract {
+            format!("retract/{}", self.like_key())
+        } else {
+            self.like_key()
+        }
+    }
+
+    fn parse(value: &[u8]) -> Option<QueuedLike> {
+        QueuedLike::parse(std::str::from_utf8(value).ok()?)
+    }
+}
+
+/// Reply to the user once the network processed the like.
+pub fn feedback(locales: &Locales, queued: &QueuedLike, status: &str) -> String {
+    let key = if queued.retract {
+        if status == "ok" {
+            "undone"
+        } else {
+            "not_undone"
+        }
+    } else if status == "ok" {
+        "liked"
+    } else if status.contains("account") {
+        "not_processed_account"
+    } else {
+        "not_processed"
+    };
+    locales.text(
+        queued.language.as_deref().unwrap_or_default(),
+        key,
+        &[("target", &queued.like.target), ("status", status)],
+    )
+}
+
+/// Stores and sends the like unless the same like is already pending or was
+/// liked within the duplicate window. Likes are added to the history of the
+/// user and remembered as duplicates before they can be sent.
+pub async fn enqueue(
+    queue: &Queue<QueuedLike>,
+    history: &History,
+    duplicates: &Duplicates,
+    queued: QueuedLike,
+    now: u64,
+) {
+    let (user, target) = (&queued.like.id, &queued.like.target);
+    if !queued.retract && duplicates.contains(user, target, now) {
+        debug!("Like of {} by {} dropped as a duplicate", target, user);
+        return;
+    }
+    let key = match queue.store(&queued) {
+        Some(key) => key,
+        None => return,
+    };
+    if !queued.retract {
+        duplicates.insert(user, target, now);
+        history.add(&queued, now);
+    }
+    queue.send(key, queued).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::LimitsConfiguration;
+    use crate::seen::Seen;
+    use smartlike_like_queue::testing::TempDir;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_queued_like() {
+        let like = Like {
+            platform: "telegram".to_string(),
+            id: "1".to_string(),
+            target: "https://t.me/smartlike_org/42".to_string(),
+            amount: 0.5,
+            currency: "EUR".to_string(),
+            account: None,
+            recipient: None,
+        };
+        let queued = QueuedLike::new(like.clone(), 1, 10);
+        let parsed = QueuedLike::parse(&serde_json::to_string(&queued).unwrap()).unwrap();
+        assert_eq!(parsed.like.amount, 0.5);
+        assert_eq!((parsed.chat_id, parsed.message_id), (Some(1), Some(10)));
+
+        // Records written before chats were tracked.
+        let parsed = QueuedLike::parse(&serde_json::to_string(&like).unwrap()).unwrap();
+        assert_eq!(parsed.like.target, like.target);
+        assert_eq!(parsed.chat_id, None);
+        assert_eq!(parsed.key(), queued.key());
+        assert!(!parsed.retract);
+        let retraction = queued.retraction();
+        assert_ne!(retraction.key(), queued.key());
+        assert_eq!(retraction.like_key(), queued.key());
+
+        assert!(QueuedLike::parse("{}").is_none());
+
+        let locales = Locales::load("./locales").unwrap();
+        assert_eq!(
+            feedback(&locales, &queued, "ok"),
+            "Liked https://t.me/smartlike_org/42"
+        );
+        assert!(feedback(&locales, &queued, "unknown account").contains("/start"));
+        assert!(feedback(&locales, &queued, "limit exceeded").ends_with("limit exceeded."));
+        assert_eq!(
+            feedback(&locales, &retraction, "ok"),
+            "The like for https://t.me/smartlike_org/42 was taken back."
+        );
+    }
+
+    #[test]
+    fn test_enqueue() {
+        let dir = TempDir::new("smartlike-telegram-bot-test-queue");
+        let queue = Queue::<QueuedLike>::open(&dir.join("queue")).unwrap();
+        let history = History::open(&dir.join("history")).unwrap();
+        let duplicates = Duplicates::new(
+            Arc::new(Seen::open(&dir.join("seen")).unwrap()),
+            &LimitsConfiguration {
+                duplicate_window_seconds: 3600,
+                ..Default::default()
+            },
+        );
+        let like = Like {
+            platform: "telegram".to_string(),
+            id: "1".to_string(),
+            target: "https://t.me/smartlike_org/42".to_string(),
+            amount: 0.5,
+            currency: "EUR".to_string(),
+            account: None,
+            recipient: None,
+        };
+
+        futures::executor::block_on(async {
+            let queued = QueuedLike::new(like.clone(), 1, 10);
+            enqueue(&queue, &history, &duplicates, queued, 100).await;
+            // Forwarded again while the first one is pending.
+            let queued = QueuedLike::new(like.clone(), 1, 11);
+            enqueue(&queue, &history, &duplicates, queued, 101).await;
+        });
+        assert_eq!(queue.pending().len(), 1);
+        let (key, queued) = futures::executor::block_on(queue.next()).unwrap();
+        assert_eq!(queued.message_id, Some(10));
+        assert_eq!(history.recent("1", 10).len(), 1);
+
+        // Sent, it's a duplicate until it's taken back.
+        queue.done(&key);
+        let queued = QueuedLike::new(like, 1, 12);
+        futures::executor::block_on(enqueue(&queue, &history, &duplicates, queued.clone(), 102));
+        assert!(queue.pending().is_empty());
+        duplicates.remove("1", &queued.like.target);
+        futures::executor::block_on(enqueue(&queue, &history, &duplicates, queued.clone(), 103));
+        let (_, sent) = futures::executor::block_on(queue.next()).unwrap();
+        assert_eq!(sent.message_id, Some(12));
+
+        // Retractions aren't added to the history.
+        let retraction = queued.retraction();
+        futures::executor::block_on(enqueue(&queue, &history, &duplicates, retraction, 104));
+        assert!(futures::executor::block_on(queue.next()).unwrap().1.retract);
+        assert_eq!(history.recent("1", 10).len(), 2);
+        assert_eq!(queue.pending().len(), 2);
+    }
+}
@@ -0,0 +1,241 @@
+//! Reactions to posts in chats the bot administers, liked like forwards.
+//!
+//! The Telegram client library doesn't know `message_reaction` updates, so
+//! they are parsed here. Telegram sends them only to chat administrators and
+//! only if `allowed_updates` includes them.
+
+use crate::amount::Amount;
+use crate::bot::Sender;
+use crate::target;
+pub use smartlike_like_queue::bridge::ReactionsConfiguration;
+use telegram_bot::types::Update;
+
+/// Update kinds the bot asks Telegram for, as `allowed_updates` of
+/// `getUpdates` and `setWebhook`.
+pub const ALLOWED_UPDATES: &str = r#"["message","message_reaction"]"#;
+
+/// Largest amount of the liked reactions. Reactions aren't liked if no
+/// amounts are configured.
+fn amount(config: &ReactionsConfiguration, reactions: &[ReactionType]) -> Option<f64> {
+    reactions
+        .iter()
+        .filter_map(|reaction| match reaction {
+            ReactionType::Emoji { emoji } => config.amount(emoji),
+            ReactionType::Other => None,
+        })
+        .reduce(f64::max)
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReactionType {
+    Emoji {
+        emoji: String,
+    },
+    /// Custom emoji and paid reactions.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ReactionChat {
+    pub id: i64,
+    pub username: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ReactionUser {
+    pub id: i64,
+    #[serde(default)]
+    pub is_bot: bool,
+    pub first_name: String,
+    pub last_name: Option<String>,
+    pub username: Option<String>,
+    pub language_code: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MessageReaction {
+    pub chat: ReactionChat,
+    pub message_id: i64,
+    /// Missing for anonymous reactions.
+    pub user: Option<ReactionUser>,
+    #[serde(default)]
+    pub old_reaction: Vec<ReactionType>,
+    #[serde(default)]
+    pub new_reaction: Vec<ReactionType>,
+}
+
+#[derive(Deserialize)]
+struct ReactionUpdate {
+    message_reaction: MessageReaction,
+}
+
+#[derive(Deserialize)]
+struct AlbumMessage {
+    media_group_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AlbumUpdate {
+    message: Option<AlbumMessage>,
+}
+
+/// Update received from Telegram.
+pub enum Received {
+    /// Update and the `media_group_id` of its message. The client library
+    /// exposes it only for photos and videos, not for documents and audio.
+    Update(Update, Option<String>),
+    Reaction(MessageReaction),
+}
+
+impl Received {
+    pub fn parse(body: &[u8]) -> Result<Received, serde_json::Error> {
+        if let Ok(update) = serde_json::from_slice::<ReactionUpdate>(body) {
+            return Ok(Received::Reaction(update.message_reaction));
+        }
+        let media_group_id = serde_json::from_slice::<AlbumUpdate>(body)
+            .ok()
+            .and_then(|update| update.message)
+            .and_then(|message| message.media_group_id);
+        serde_json::from_slice::<Update>(body)
+            .map(|update| Received::Update(update, media_group_id))
+    }
+}
+
+/// What a reaction does to the like of the post.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Like(Amount),
+    /// The liked reaction was withdrawn.
+    Unlike,
+}
+
+#[derive(Debug, Clone)]
+pub struct Reacted {
+    pub from: Sender,
+    pub target: String,
+    pub change: Change,
+}
+
+impl MessageReaction {
+    /// Like or unlike of the post, None if the reaction changes neither,
+    /// e.g. it's anonymous or switches between liked reactions.
+    pub fn reacted(&self, config: &ReactionsConfiguration) -> Option<Reacted> {
+        let user = self.user.as_ref()?;
+        let change = match (
+            amount(config, &self.new_reaction),
+            amount(config, &self.old_reaction),
+        ) {
+            (Some(amount), None) => Change::Like(Amount {
+                amount,
+                currency: config.currency.clone(),
+            }),
+            (None, Some(_)) => Change::Unlike,
+            _ => return None,
+        };
+        let target = match &self.chat.username {
+            Some(username) => target::public_post(username, self.message_id),
+            None => target::private_post(self.chat.id, self.message_id)?,
+        };
+        Some(Reacted {
+            from: Sender {
+                id: user.id,
+                first_name: user.first_name.clone(),
+                last_name: user.last_name.clone(),
+                username: user.username.clone(),
+                is_bot: user.is_bot,
+                language_code: user.language_code.clone(),
+            },
+            target,
+            change,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reactions() {
+        let body = std::fs::read_to_string("./test/reaction.json").unwrap();
+        let mut reaction = match Received::parse(body.as_bytes()).unwrap() {
+            Received::Reaction(reaction) => reaction,
+            Received::Update(..) => panic!("reaction expected"),
+        };
+        let body = std::fs::read_to_string("./test/forward_user.json").unwrap();
+        assert!(matches!(
+            Received::parse(body.as_bytes()),
+            Ok(Received::Update(..))
+        ));
+
+        let config = ReactionsConfiguration {
+            currency: "USD".to_string(),
+            amounts: [("👍".to_string(), 0.1), ("❤️".to_string(), 0.5)]
+                .into_iter()
+                .collect(),
+        };
+        let reacted = reaction.reacted(&config).unwrap();
+        assert_eq!(reacted.from.id, 7);
+        assert_eq!(reacted.target, "https://t.me/smartlike_org/42");
+        assert_eq!(
+            reacted.change,
+            Change::Like(Amount {
+                amount: 0.5,
+                currency: "USD".to_string()
+            })
+        );
+
+        // Switching between liked reactions keeps the like.
+        reaction.old_reaction = vec![ReactionType::Emoji {
+            emoji: "👍".to_string(),
+        }];
+        assert!(reaction.reacted(&config).is_none());
+
+        reaction.new_reaction = vec![ReactionType::Other];
+        assert_eq!(reaction.reacted(&config).unwrap().change, Change::Unlike);
+
+        // Posts of chats without a username.
+        reaction.chat = ReactionChat {
+            id: -1001234,
+            username: None,
+        };
+        assert_eq!(
+            reaction.reacted(&config).unwrap().target,
+            "https://t.me/c/1234/42"
+        );
+
+        // Reactions aren't liked.
+        assert!(reaction
+            .reacted(&ReactionsConfiguration::default())
+            .is_none());
+
+        reaction.user = None;
+        assert!(reaction.reacted(&config).is_none());
+    }
+
+    #[test]
+    fn test_media_groups() {
+        let body = std::fs::read_to_string("./test/forward_document.json").unwrap();
+        let media_group_id =
+            |update: &serde_json::Value| match Received::parse(update.to_string().as_bytes())
+                .unwrap()
+            {
+                Received::Update(_, media_group_id) => media_group_id,
+                Received::Reaction(_) => panic!("update expected"),
+            };
+
+        // Separate documents forwarded together share the date, not an album.
+        let first: serde_json::Value = serde_json::from_str(&body).unwrap();
+        let mut second = first.clone();
+        second["message"]["message_id"] = 15.into();
+        second["message"]["forward_from_message_id"] = 44.into();
+        assert_eq!(first["message"]["date"], second["message"]["date"]);
+        assert_eq!(media_group_id(&first), None);
+        assert_eq!(media_group_id(&second), None);
+
+        second["message"]["media_group_id"] = "13540283846".into();
+        assert_eq!(media_group_id(&second), Some("13540283846".to_string()));
+    }
+}
@@ -0,0 +1,100 @@
+//! Keys seen recently, e.g. media groups and liked targets.
+//!
+//! Keys expire after their TTL and are kept in RocksDB, so a restart doesn't
+//! cause duplicate likes.
+
+use rocksdb::{DBWithThreadMode, IteratorMode, MultiThreaded};
+
+pub struct Seen {
+    db: DBWithThreadMode<MultiThreaded>,
+}
+
+impl Seen {
+    pub fn open(path: &str) -> Result<Seen, String> {
+        let db = DBWithThreadMode::<MultiThreaded>::open_default(path)
+            .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        Ok(Seen { db })
+    }
+
+    fn expires(value: &[u8]) -> Option<u64> {
+        std::str::from_utf8(value).ok()?.parse::<u64>().ok()
+    }
+
+    pub fn contains(&self, key: &str, now: u64) -> bool {
+        match self.db.get(key) {
+            Ok(Some(value)) => matches!(Seen::expires(&value), Some(ts) if ts > now),
+            Ok(None) => false,
+            Err(e) => {
+                error!("DB error: {}", e);
+                false
+            }
+        }
+    }
+
+    pub fn insert(&self, key: &str, ttl: u64, now: u64) {
+        if let Err(e) = self.db.put(key, (now + ttl).to_string()) {
+            error!("DB error: {}", e);
+        }
+    }
+
+    pub fn remove(&self, key: &str) {
+        if let Err(e) = self.db.delete(key) {
+            error!("DB error: {}", e);
+        }
+    }
+
+    /// Remembers the key and returns true unless it was seen within its TTL.
+    pub fn first(&self, key: &str, ttl: u64, now: u64) -> bool {
+        if self.contains(key, now) {
+            return false;
+        }
+        self.insert(key, ttl, now);
+        true
+    }
+
+    /// Deletes expired keys. Returns the number of deleted keys.
+    pub fn purge(&self, now: u64) -> usize {
+        let mut deleted = 0;
+        for (key, value) in self.db.iterator(IteratorMode::Start) {
+            if matches!(Seen::expires(&value), Some(ts) if ts > now) {
+                continue;
+            }
+            match self.db.delete(key) {
+                Ok(_) => deleted += 1,
+                Err(e) => error!("DB error: {}", e),
+            }
+        }
+        deleted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smartlike_like_queue::testing::TempDir;
+
+    #[test]
+    fn test_seen() {
+        let dir = TempDir::new("smartlike-telegram-bot-test-seen");
+        let seen = Seen::open(dir.path()).unwrap();
+
+        assert!(seen.first("a", 10, 100));
+        assert!(!seen.first("a", 10, 105));
+        assert!(seen.contains("a", 109));
+        // Expired.
+        assert!(!seen.contains("a", 110));
+        assert!(seen.first("a", 10, 110));
+        seen.remove("a");
+        assert!(seen.first("a", 10, 111));
+
+        seen.insert("b", 100, 100);
+        assert_eq!(seen.purge(150), 1);
+        assert!(seen.contains("b", 150));
+
+        // Survives restarts.
+        drop(seen);
+        let seen = Seen::open(dir.path()).unwrap();
+        assert!(seen.contains("b", 150));
+        assert!(!seen.contains("a", 150));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::amount::Amount;
+use crate::group::GroupSettings;
+use rocksdb::{DBWithThreadMode, MultiThreaded};
+use smartlike_embed_lib::challenge::SignedChallenge;
+
+/// Default donation amounts, languages and channel claims of users, and
+/// settings of groups.
+pub struct Settings {
+    db: DBWithThreadMode<MultiThreaded>,
+}
+
+impl Settings {
+    pub fn open(path: &str) -> Result<Settings, String> {
+        let db = DBWithThreadMode::<MultiThreaded>::open_default(path)
+            .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        Ok(Settings { db })
+    }
+
+    pub fn get(&self, user: &str) -> Option<Amount> {
+        match self.db.get(user) {
+            Ok(Some(value)) => serde_json::from_slice(&value)
+                .map_err(|e| error!("Failed to parse settings of {}: {}", user, e))
+                .ok(),
+            Ok(None) => None,
+            Err(e) => {
+                error!("DB error: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn set(&self, user: &str, amount: &Amount) -> Result<(), String> {
+        let value = serde_json::to_string(amount).map_err(|e| e.to_string())?;
+        self.db
+            .put(user, value)
+            .map_err(|e| format!("DB error: {}", e))
+    }
+
+    pub fn language(&self, user: &str) -> Option<String> {
+        match self.db.get(format!("language/{}", user)) {
+            Ok(value) => value.and_then(|v| String::from_utf8(v).ok()),
+            Err(e) => {
+                error!("DB error: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn set_language(&self, user: &str, language: &str) -> Result<(), String> {
+        self.db
+            .put(format!("language/{}", user), language)
+            .map_err(|e| format!("DB error: {}", e))
+    }
+
+    pub fn group(&self, chat_id: i64) -> GroupSettings {
+        match self.db.get(format!("group/{}", chat_id)) {
+            Ok(Some(value)) => serde_json::from_slice(&value)
+                .map_err(|e| error!("Failed to parse settings of group {}: {}", chat_id, e))
+                .unwrap_or_default(),
+            Ok(None) => GroupSettings::default(),
+            Err(e) => {
+                error!("DB error: {}", e);
+                GroupSettings::default()
+            }
+        }
+    }
+
+    pub fn set_group(&self, chat_id: i64, settings: &GroupSettings) -> Result<(), String> {
+        let value = serde_json::to_string(settings).map_err(|e| e.to_string())?;
+        self.db
+            .put(format!("group/{}", chat_id), value)
+            .map_err(|e| format!("DB error: {}", e))
+    }
+
+    /// Challenge issued to the user to claim the channel.
+    pub fn claim(&self, user: &str, channel: &str) -> Option<SignedChallenge> {
+        match self.db.get(format!("claim/{}/{}", user, channel)) {
+            Ok(Some(value)) => serde_json::from_slice(&value)
+                .map_err(|e| error!("Failed to parse claim of {}: {}", user, e))
+                .ok(),
+            Ok(None) => None,
+            Err(e) => {
+                error!("DB error: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn set_claim(
+        &self,
+        user: &str,
+        channel: &str,
+        challenge: &SignedChallenge,
+    ) -> Result<(), String> {
+        let value = serde_json::to_string(challenge).map_err(|e| e.to_string())?;
+        self.db
+            .put(format!("claim/{}/{}", user, channel), value)
+            .map_err(|e| format!("DB error: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smartlike_like_queue::testing::TempDir;
+
+    #[test]
+    fn test_settings() {
+        let dir = TempDir::new("smartlike-telegram-bot-test-settings");
+        let settings = Settings::open(dir.path()).unwrap();
+        assert!(settings.get("1").is_none());
+
+        let amount = Amount {
+            amount: 0.5,
+            currency: "EUR".to_string(),
+        };
+        settings.set("1", &amount).unwrap();
+        assert_eq!(settings.get("1"), Some(amount.clone()));
+        assert!(settings.get("2").is_none());
+
+        assert!(settings.language("1").is_none());
+        settings.set_language("1", "ru").unwrap();
+        assert_eq!(settings.language("1"), Some("ru".to_string()));
+        assert_eq!(settings.get("1"), Some(amount));
+
+        assert!(settings.claim("1", "channel").is_none());
+        let client = smartlike_embed_lib::client::Client::new(
+            "bot".to_string(),
+            "secret".to_string(),
+            "".to_string(),
+        );
+        let challenge = smartlike_embed_lib::challenge::Challenge::new("bot", "", "", 100, 10);
+        let signed = client.issue_challenge(challenge).unwrap();
+        settings.set_claim("1", "channel", &signed).unwrap();
+        assert_eq!(
+            settings.claim("1", "channel").unwrap().signature,
+            signed.signature
+        );
+        assert!(settings.claim("2", "channel").is_none());
+
+        assert_eq!(settings.group(-1), GroupSettings::default());
+        let group = GroupSettings {
+            plus_one: false,
+            ..Default::default()
+        };
+        settings.set_group(-1, &group).unwrap();
+        assert_eq!(settings.group(-1), group);
+        assert_eq!(settings.group(-2), GroupSettings::default());
+    }
+}
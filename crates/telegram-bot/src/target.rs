@@ -0,0 +1,101 @@
+//! Like targets of forwarded messages.
+
+use telegram_bot::types::ForwardFrom;
+
+/// Reasons why a forwarded message can't be liked.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Unsupported {
+    HiddenUser,
+    UserWithoutUsername,
+    Chat,
+    /// A group member replied to a message that isn't a channel post.
+    NotChannelPost,
+}
+
+impl Unsupported {
+    /// Message key of the reason.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Unsupported::HiddenUser => "forward_hidden_user",
+            Unsupported::UserWithoutUsername => "forward_no_username",
+            Unsupported::Chat => "forward_chat",
+            Unsupported::NotChannelPost => "group_not_channel_post",
+        }
+    }
+}
+
+pub fn public_post(username: &str, message_id: i64) -> String {
+    format!("https://t.me/{}/{}", username, message_id)
+}
+
+/// Link to a post in a channel without a username. Such links open only for
+/// channel members.
+pub fn private_post(channel_id: i64, message_id: i64) -> Option<String> {
+    // Bot API channel ids are the internal id used in t.me/c links prefixed with -100.
+    channel_id
+        .to_string()
+        .strip_prefix("-100")
+        .filter(|id| !id.is_empty())
+        .map(|id| format!("https://t.me/c/{}/{}", id, message_id))
+}
+
+pub fn user_profile(username: &str) -> String {
+    format!("https://t.me/{}", username)
+}
+
+/// Resolves the like target of a forwarded message.
+pub fn resolve(from: &ForwardFrom) -> Result<String, Unsupported> {
+    match from {
+        ForwardFrom::Channel {
+            channel,
+            message_id,
+            ..
+        } => match &channel.username {
+            Some(username) => Ok(public_post(username, *message_id)),
+            None => private_post(i64::from(channel.id), *message_id).ok_or(Unsupported::Chat),
+        },
+        // Messages written by users are liked on the author's profile.
+        ForwardFrom::User { user, .. } => match &user.username {
+            Some(username) => Ok(user_profile(username)),
+            None => Err(Unsupported::UserWithoutUsername),
+        },
+        ForwardFrom::ChannelHiddenUser { .. } => Err(Unsupported::HiddenUser),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use telegram_bot::types::{Update, UpdateKind};
+
+    fn forward(path: &str) -> ForwardFrom {
+        let update: Update = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        match update.kind {
+            UpdateKind::Message(msg) => msg.forward.unwrap().from,
+            other => panic!("unexpected update {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_forward_targets() {
+        assert_eq!(
+            resolve(&forward("./test/forward_public_channel.json")),
+            Ok("https://t.me/smartlike_org/42".to_string())
+        );
+        assert_eq!(
+            resolve(&forward("./test/forward_private_channel.json")),
+            Ok("https://t.me/c/1234567890/42".to_string())
+        );
+        assert_eq!(
+            resolve(&forward("./test/forward_user.json")),
+            Ok("https://t.me/bob".to_string())
+        );
+        assert_eq!(
+            resolve(&forward("./test/forward_hidden_user.json")),
+            Err(Unsupported::HiddenUser)
+        );
+
+        assert_eq!(private_post(-42, 1), None);
+        assert_eq!(private_post(-100, 1), None);
+    }
+}
@@ -0,0 +1,99 @@
+//! Telegram Bot API side of the message handler.
+
+use crate::bot::{BotApi, Incoming, Reply, Sender};
+use crate::target::{self, Unsupported};
+use telegram_bot::prelude::*;
+use telegram_bot::types::{
+    ChatMemberStatus, ForwardFrom, InlineKeyboardButton, InlineKeyboardMarkup, Message,
+    MessageChat, MessageKind, MessageOrChannelPost, ParseMode,
+};
+use telegram_bot::{Api, ChatId, GetChatMember, MessageId};
+
+impl BotApi for Api {
+    fn send(&self, reply: Reply) {
+        let mut message = ChatId::new(reply.chat_id).text(reply.text);
+        if reply.html {
+            message.parse_mode(ParseMode::Html);
+        }
+        if let Some(id) = reply.reply_to {
+            message.reply_to(MessageId::new(id));
+        }
+        if let Some(button) = reply.button {
+            let mut keyboard = InlineKeyboardMarkup::new();
+            keyboard.add_row(vec![InlineKeyboardButton::url(button.text, button.url)]);
+            message.reply_markup(keyboard);
+        }
+        self.spawn(message);
+    }
+}
+
+/// Incoming message. `album` is the `media_group_id` of the raw update, if
+/// it was received, as the client exposes it only for photos and videos.
+pub fn incoming(msg: &Message, album: Option<String>) -> Incoming {
+    let (text, media_group_id) = match &msg.kind {
+        MessageKind::Text { data, .. } => (Some(data.clone()), None),
+        MessageKind::Photo { media_group_id, .. } | MessageKind::Video { media_group_id, .. } => {
+            (None, media_group_id.clone())
+        }
+        _ => (None, album),
+    };
+    Incoming {
+        chat_id: i64::from(msg.chat.id()),
+        message_id: i64::from(msg.id),
+        from: Sender {
+            id: i64::from(msg.from.id),
+            first_name: msg.from.first_name.clone(),
+            last_name: msg.from.last_name.clone(),
+            username: msg.from.username.clone(),
+            is_bot: msg.from.is_bot,
+            language_code: msg.from.language_code.clone(),
+        },
+        text,
+        media_group_id,
+        forward: msg.forward.as_ref().map(|f| target::resolve(&f.from)),
+        group: matches!(msg.chat, MessageChat::Group(_) | MessageChat::Supergroup(_)),
+        admin: false,
+        reply_to: msg.reply_to_message.as_deref().map(replied),
+    }
+}
+
+/// Like target of the message a group member replied to. Only posts
+/// forwarded from channels, including those a linked channel forwards to its
+/// discussion group, are liked in groups.
+fn replied(reply: &MessageOrChannelPost) -> Result<String, Unsupported> {
+    match reply {
+        MessageOrChannelPost::Message(message) => match &message.forward {
+            Some(forward) if matches!(forward.from, ForwardFrom::Channel { .. }) => {
+                target::resolve(&forward.from)
+            }
+            _ => Err(Unsupported::NotChannelPost),
+        },
+        MessageOrChannelPost::ChannelPost(_) => Err(Unsupported::NotChannelPost),
+    }
+}
+
+/// Marks `/group` commands of group admins. Other messages don't need the
+/// extra request.
+pub async fn check_admin(api: &Api, msg: &Message, incoming: &mut Incoming) {
+    let command = incoming.text.as_deref().unwrap_or_default();
+    if !incoming.group || !command.starts_with("/group") {
+        return;
+    }
+    match api
+        .send(GetChatMember::new(msg.chat.id(), msg.from.id))
+        .await
+    {
+        Ok(member) => {
+            incoming.admin = matches!(
+                member.status,
+                ChatMemberStatus::Creator | ChatMemberStatus::Administrator
+            )
+        }
+        Err(e) => error!(
+            "Failed to get member {} of {}: {}",
+            msg.from.id,
+            msg.chat.id(),
+            e
+        ),
+    }
+}
@@ -0,0 +1,274 @@
+//! Webhook mode: Telegram posts updates to the bot instead of the bot polling
+//! for them.
+
+use crate::reaction::{Received, ALLOWED_UPDATES};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+
+/// Header carrying the `secret_token` passed to `setWebhook`.
+pub const SECRET_TOKEN_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct WebhookConfiguration {
+    pub listen_address: String,
+    /// Path Telegram posts updates to, e.g. "/telegram".
+    pub path: String,
+    /// Requests without this token are rejected.
+    pub secret_token: String,
+    /// Public URL routed to `listen_address` and `path`, registered with
+    /// `setWebhook` on start. The webhook is registered by hand if empty.
+    #[serde(default)]
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct WebhookInfo {
+    #[serde(default)]
+    allowed_updates: Vec<String>,
+}
+
+async fn call<T: serde::de::DeserializeOwned>(
+    request: reqwest::RequestBuilder,
+    method: &str,
+) -> Result<T, String> {
+    let body = request
+        .send()
+        .await
+        .map_err(|e| format!("{} failed: {}", method, e))?
+        .text()
+        .await
+        .map_err(|e| format!("{} failed: {}", method, e))?;
+    let response = serde_json::from_str::<ApiResponse<T>>(&body)
+        .map_err(|e| format!("Failed to parse {} response: {}", method, e))?;
+    match response.result {
+        Some(result) if response.ok => Ok(result),
+        _ => Err(response.description.unwrap_or(body)),
+    }
+}
+
+/// Registers `url` of the configuration with the update kinds the bot
+/// handles. `api` is the Bot API URL of the bot,
+/// "https://api.telegram.org/bot{token}".
+pub async fn register(api: &str, config: &WebhookConfiguration) -> Result<(), String> {
+    let request = reqwest::Client::new()
+        .post(format!("{}/setWebhook", api))
+        .form(&[
+            ("url", config.url.as_str()),
+            ("secret_token", config.secret_token.as_str()),
+            ("allowed_updates", ALLOWED_UPDATES),
+        ]);
+    call::<bool>(request, "setWebhook").await.map(|_| ())
+}
+
+/// Whether the webhook registered by hand receives reactions.
+pub async fn receives_reactions(api: &str) -> Result<bool, String> {
+    let request = reqwest::Client::new().get(format!("{}/getWebhookInfo", api));
+    let info = call::<WebhookInfo>(request, "getWebhookInfo").await?;
+    Ok(info
+        .allowed_updates
+        .iter()
+        .any(|kind| kind == "message_reaction"))
+}
+
+pub struct Context {
+    pub secret_token: String,
+    pub tx: async_channel::Sender<Received>,
+}
+
+pub async fn update_handler(
+    req: HttpRequest,
+    body: web::Bytes,
+    context: web::Data<Context>,
+) -> HttpResponse {
+    let token = req
+        .headers()
+        .get(SECRET_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok());
+    if token != Some(context.secret_token.as_str()) {
+        warn!("Rejected webhook request from {:?}", req.peer_addr());
+        return HttpResponse::Forbidden().finish();
+    }
+    match Received::parse(&body) {
+        Ok(update) => match context.tx.send(update).await {
+            Ok(_) => HttpResponse::Ok().finish(),
+            Err(e) => {
+                error!("TX Error: {}", e);
+                HttpResponse::ServiceUnavailable().finish()
+            }
+        },
+        // Telegram redelivers updates until they are acknowledged, so updates
+        // the bot doesn't understand are dropped.
+        Err(e) => {
+            warn!("Failed to parse update: {}", e);
+            HttpResponse::Ok().finish()
+        }
+    }
+}
+
+fn configure(cfg: &mut web::ServiceConfig, path: &str) {
+    cfg.service(web::resource(path).route(web::post().to(update_handler)));
+}
+
+/// Starts receiving updates in the background. Received updates are sent to
+/// `tx`.
+pub fn start(
+    config: &WebhookConfiguration,
+    tx: async_channel::Sender<Received>,
+) -> Result<actix_web::dev::Server, String> {
+    if config.secret_token.is_empty() {
+        return Err("webhook.secret_token is not set".to_string());
+    }
+    let path = match config.path.as_str() {
+        "" => "/".to_string(),
+        path => path.to_string(),
+    };
+    let context = web::Data::new(Context {
+        secret_token: config.secret_token.clone(),
+        tx,
+    });
+    info!("Receiving updates at {}{}", config.listen_address, path);
+    Ok(HttpServer::new(move || {
+        let path = path.clone();
+        App::new()
+            .app_data(context.clone())
+            .configure(move |cfg| configure(cfg, &path))
+    })
+    .bind(&config.listen_address)
+    .map_err(|e| format!("Failed to bind {}: {}", config.listen_address, e))?
+    .run())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{http::StatusCode, test};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[actix_rt::test]
+    async fn test_webhook() {
+        let (tx, rx) = async_channel::unbounded::<Received>();
+        let context = web::Data::new(Context {
+            secret_token: "secret".to_string(),
+            tx,
+        });
+        let app = test::init_service(
+            App::new()
+                .app_data(context.clone())
+                .configure(|cfg| configure(cfg, "/telegram")),
+        )
+        .await;
+
+        let post = |token: &str, body: String| {
+            test::TestRequest::post()
+                .uri("/telegram")
+                .insert_header((SECRET_TOKEN_HEADER, token))
+                .insert_header(("content-type", "application/json"))
+                .set_payload(body)
+                .to_request()
+        };
+
+        for path in [
+            "./test/forward_public_channel.json",
+            "./test/forward_private_channel.json",
+            "./test/forward_user.json",
+            "./test/forward_hidden_user.json",
+            "./test/forward_document.json",
+            "./test/reaction.json",
+        ] {
+            let body = std::fs::read_to_string(path).unwrap();
+            let resp = test::call_service(&app, post("secret", body.clone())).await;
+            assert_eq!(resp.status(), StatusCode::OK);
+            let expected: serde_json::Value = serde_json::from_str(&body).unwrap();
+            match rx.try_recv().unwrap() {
+                Received::Update(update, _) => {
+                    assert_eq!(update.id, expected["update_id"].as_i64().unwrap())
+                }
+                Received::Reaction(reaction) => assert_eq!(
+                    reaction.message_id,
+                    expected["message_reaction"]["message_id"].as_i64().unwrap()
+                ),
+            }
+
+            let resp = test::call_service(&app, post("wrong", body)).await;
+            assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+            assert!(rx.try_recv().is_err());
+        }
+
+        // Missing header.
+        let req = test::TestRequest::post()
+            .uri("/telegram")
+            .set_payload("{}")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        // Unknown updates are acknowledged and dropped.
+        let resp = test::call_service(&app, post("secret", "{}".to_string())).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(rx.try_recv().is_err());
+
+        assert!(start(&WebhookConfiguration::default(), context.tx.clone()).is_err());
+    }
+
+    #[actix_rt::test]
+    async fn test_register() {
+        // Local stand-in Bot API keeping the registered update kinds.
+        let registered = web::Data::new(Mutex::new(None::<String>));
+        let server = HttpServer::new({
+            let registered = registered.clone();
+            move || {
+                App::new()
+                    .app_data(registered.clone())
+                    .route(
+                        "/bottoken/setWebhook",
+                        web::post().to(
+                            |form: web::Form<HashMap<String, String>>,
+                             registered: web::Data<Mutex<Option<String>>>| async move {
+                                assert_eq!(form["url"], "https://example.org/telegram");
+                                assert_eq!(form["secret_token"], "secret");
+                                *registered.lock().unwrap() = Some(form["allowed_updates"].clone());
+                                HttpResponse::Ok().json(json!({ "ok": true, "result": true }))
+                            },
+                        ),
+                    )
+                    .route(
+                        "/bottoken/getWebhookInfo",
+                        web::get().to(
+                            |registered: web::Data<Mutex<Option<String>>>| async move {
+                                let mut info = json!({ "url": "" });
+                                if let Some(allowed) = registered.lock().unwrap().as_ref() {
+                                    info["allowed_updates"] = serde_json::from_str(allowed).unwrap();
+                                }
+                                HttpResponse::Ok().json(json!({ "ok": true, "result": info }))
+                            },
+                        ),
+                    )
+            }
+        })
+        .bind("127.0.0.1:0")
+        .unwrap();
+        let api = format!("http://{}/bottoken", server.addrs()[0]);
+        actix_rt::spawn(server.run());
+
+        assert_eq!(receives_reactions(&api).await, Ok(false));
+        let config = WebhookConfiguration {
+            secret_token: "secret".to_string(),
+            url: "https://example.org/telegram".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(register(&api, &config).await, Ok(()));
+        assert_eq!(receives_reactions(&api).await, Ok(true));
+        assert!(receives_reactions("http://127.0.0.1:1/bottoken")
+            .await
+            .unwrap_err()
+            .starts_with("getWebhookInfo failed"));
+    }
+}
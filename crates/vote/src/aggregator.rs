@@ -0,0 +1,479 @@
+use crate::consensus::{default_jump_rounds, default_jump_tolerance_percent, Decision, Jumps};
+use crate::currencies::rebase;
+use crate::history::History;
+use crate::oracle::{SignedVote, Vote};
+use actix_web::{web, HttpResponse};
+use anyhow::anyhow;
+use smartlike_embed_lib::client::{Client, CurrencyExchangeRatesUpdate};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Duration;
+
+pub const AGGREGATE_SOURCE: &str = "aggregate";
+
+/// How far ahead of the aggregator's clock an oracle's clock may be.
+const MAX_CLOCK_SKEW_SECONDS: u32 = 60;
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct OracleConfiguration {
+    pub account: String,
+    pub public_key: String,
+    pub weight: f64,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct AggregatorConfiguration {
+    pub listen_address: String,
+    pub round_seconds: u64,
+    pub max_vote_age_seconds: u32,
+    /// Minimum total weight of oracles voting for a currency.
+    pub quorum_weight: f64,
+    /// Maximum change since the last published update, in percent.
+    pub max_change_percent: f64,
+    /// Rounds in a row a larger change has to be voted for in to be
+    /// published, never if 0.
+    #[serde(default = "default_jump_rounds")]
+    pub jump_rounds: u32,
+    /// Maximum difference between the rates of these rounds, in percent.
+    #[serde(default = "default_jump_tolerance_percent")]
+    pub jump_tolerance_percent: f64,
+    pub oracles: Vec<OracleConfiguration>,
+}
+
+impl AggregatorConfiguration {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.oracles.is_empty() {
+            return Err(anyhow!("No oracles configured"));
+        }
+        for oracle in &self.oracles {
+            if !oracle.weight.is_finite() || oracle.weight <= 0.0 {
+                return Err(anyhow!(
+                    "Invalid weight {} of oracle {}",
+                    oracle.weight,
+                    oracle.account
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Collects oracle votes for the current round.
+pub struct Aggregator {
+    config: AggregatorConfiguration,
+    votes: Mutex<HashMap<String, Vote>>,
+    /// Time of the last accepted vote of every oracle, across rounds.
+    last_votes: Mutex<HashMap<String, u32>>,
+    /// Jumps rejected in the last rounds.
+    jumps: Mutex<Jumps>,
+}
+
+fn weighted_median(values: &mut [(f64, f64)]) -> f64 {
+    values.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    let total: f64 = values.iter().map(|v| v.1).sum();
+    let mut acc = 0.0;
+    for (rate, weight) in values.iter() {
+        acc += weight;
+        if acc * 2.0 >= total {
+            return *rate;
+        }
+    }
+    values[values.len() - 1].0
+}
+
+impl Aggregator {
+    pub fn new(config: AggregatorConfiguration) -> Aggregator {
+        Aggregator {
+            config,
+            votes: Mutex::new(HashMap::new()),
+            last_votes: Mutex::new(HashMap::new()),
+            jumps: Mutex::new(Jumps::default()),
+        }
+    }
+
+    pub fn add_vote(&self, signed: &SignedVote, now: u32) -> anyhow::Result<()> {
+        let oracle = self
+            .config
+            .oracles
+            .iter()
+            .find(|o| o.account == signed.oracle)
+            .ok_or(anyhow!("Unknown oracle {}", signed.oracle))?;
+        let vote = signed.verify(&oracle.public_key)?;
+        if vote.ts.saturating_add(self.config.max_vote_age_seconds) < now {
+            return Err(anyhow!("Vote from {} is out of date", signed.oracle));
+        }
+        // The vote time becomes the oracle's replay watermark, it can't be
+        // far ahead of now.
+        if vote.ts > now.saturating_add(MAX_CLOCK_SKEW_SECONDS) {
+            return Err(anyhow!("Vote from {} is from the future", signed.oracle));
+        }
+
+        // Votes of closed rounds are gone, their times are not.
+        let mut last_votes = self.last_votes.lock().unwrap();
+        if let Some(ts) = last_votes.get(&oracle.account) {
+            if *ts >= vote.ts {
+                return Err(anyhow!("Vote from {} replayed", signed.oracle));
+            }
+        }
+        last_votes.insert(oracle.account.clone(), vote.ts);
+        info!(
+            "Vote from {}: {} rates, base {}",
+            oracle.account,
+            vote.update.rates.len(),
+            vote.update.base
+        );
+        self.votes
+            .lock()
+            .unwrap()
+            .insert(oracle.account.clone(), vote);
+        Ok(())
+    }
+
+    fn weight(&self, oracle: &str) -> f64 {
+        self.config
+            .oracles
+            .iter()
+            .find(|o| o.account == oracle)
+            .map_or(0.0, |o| o.weight)
+    }
+
+    /// Combines votes of the round into per-currency weighted medians and
+    /// starts a new round. `previous` holds the last published rate of every
+    /// currency.
+    pub fn close_round(
+        &self,
+        previous: Option<&CurrencyExchangeRatesUpdate>,
+        now: u32,
+    ) -> anyhow::Result<(CurrencyExchangeRatesUpdate, BTreeMap<String, Decision>)> {
+        let votes: Vec<(String, Vote)> = self.votes.lock().unwrap().drain().collect();
+        let votes: Vec<(String, Vote)> = votes
+            .into_iter()
+            .filter(|(_, v)| v.ts.saturating_add(self.config.max_vote_age_seconds) >= now)
+            .collect();
+        let base = votes
+            .first()
+            .ok_or(anyhow!("No votes"))?
+            .1
+            .update
+            .base
+            .clone();
+
+        let mut quotes: BTreeMap<String, Vec<(f64, f64)>> = BTreeMap::new();
+        let mut ts = 0;
+        for (oracle, vote) in &votes {
+            match rebase(&vote.update, &base) {
+                Ok(update) => {
+                    ts = ts.max(update.ts);
+                    for (currency, rate) in update.rates {
+                        if rate.is_finite() && rate > 0.0 {
+                            quotes
+                                .entry(currency)
+                                .or_insert(vec![])
+                                .push((rate, self.weight(oracle)));
+                        }
+                    }
+                }
+                Err(e) => warn!("Vote from {} ignored: {}", oracle, e),
+            }
+        }
+
+        let previous = previous.and_then(|p| rebase(p, &base).ok());
+        let mut decisions = BTreeMap::new();
+        for (currency, mut values) in quotes {
+            if currency == base {
+                continue;
+            }
+            let weight: f64 = values.iter().map(|v| v.1).sum();
+            let decision = if weight < self.config.quorum_weight {
+                Decision::NoQuorum { weight }
+            } else {
+                let rate = weighted_median(&mut values);
+                let last = previous
+                    .as_ref()
+                    .and_then(|p| p.rates.get(&currency))
+                    .filter(|r| r.is_finite() && **r > 0.0);
+                match last {
+                    Some(l) if (rate - l).abs() / l * 100.0 > self.config.max_change_percent => {
+                        Decision::Jump { previous: *l, rate }
+                    }
+                    _ => Decision::Accepted {
+                        rate,
+                        sources: values.len(),
+                    },
+                }
+            };
+            decisions.insert(currency, decision);
+        }
+        self.jumps.lock().unwrap().review(
+            &mut decisions,
+            ts,
+            self.config.jump_rounds,
+            self.config.jump_tolerance_percent,
+        );
+
+        let mut rates: HashMap<String, f64> = decisions
+            .iter()
+            .filter_map(|(currency, decision)| match decision {
+                Decision::Accepted { rate, .. } | Decision::Confirmed { rate, .. } => {
+                    Some((currency.clone(), *rate))
+                }
+                _ => None,
+            })
+            .collect();
+        rates.insert(base.clone(), 1.0);
+
+        Ok((
+            CurrencyExchangeRatesUpdate {
+                source: AGGREGATE_SOURCE.to_string(),
+                base,
+                ts,
+                rates,
+            },
+            decisions,
+        ))
+    }
+}
+
+pub async fn vote_handler(
+    vote: web::Json<SignedVote>,
+    aggregator: web::Data<Aggregator>,
+) -> HttpResponse {
+    match aggregator.add_vote(&vote, crate::now()) {
+        Ok(_) => HttpResponse::Ok().content_type("text/plain").body("ok"),
+        Err(e) => {
+            warn!("{}", e);
+            HttpResponse::Forbidden()
+                .content_type("text/plain")
+                .body(e.to_string())
+        }
+    }
+}
+
+pub async fn run_rounds(
+    aggregator: web::Data<Aggregator>,
+    client: Client,
+    history: web::Data<History>,
+) {
+    loop {
+        actix_rt::time::delay_for(Duration::from_secs(aggregator.config.round_seconds)).await;
+
+        let previous = history.published();
+        match aggregator.close_round(previous.as_ref(), crate::now()) {
+            Ok((update, decisions)) => {
+                for (currency, decision) in &decisions {
+                    match decision {
+                        Decision::Accepted { .. } => info!("{}: {}", currency, decision),
+                        _ => warn!("{}: {}", currency, decision),
+                    }
+                }
+                if update.rates.len() < 2 {
+                    warn!("No exchange rates accepted");
+                    continue;
+                }
+                info!("Publishing {} aggregated rates", update.rates.len() - 1);
+                match client.update_exchange_rates(&update).await {
+                    Ok(_) => {
+                        if let Err(e) = history.add(crate::now(), &update) {
+                            error!("Failed to store update: {}", e);
+                        }
+                    }
+                    Err(e) => error!("Failed to publish aggregated rates: {}", e),
+                }
+            }
+            Err(e) => info!("Round closed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smartlike_like_queue::testing::TempDir;
+
+    fn oracle(account: &str) -> Client {
+        Client::new(account.to_string(), account.to_string(), "".to_string())
+    }
+
+    fn vote(client: &Client, ts: u32, rates: &[(&str, f64)]) -> SignedVote {
+        let vote = Vote {
+            ts,
+            update: CurrencyExchangeRatesUpdate {
+                source: "consensus".to_string(),
+                base: "USD".to_string(),
+                ts,
+                rates: rates.iter().map(|(c, r)| (c.to_string(), *r)).collect(),
+            },
+        };
+        SignedVote::create(client, &vote).unwrap()
+    }
+
+    #[test]
+    fn test_aggregation() {
+        let oracles = [oracle("a"), oracle("b"), oracle("c"), oracle("d")];
+        let aggregator = Aggregator::new(AggregatorConfiguration {
+            listen_address: "".to_string(),
+            round_seconds: 60,
+            max_vote_age_seconds: 300,
+            quorum_weight: 3.0,
+            max_change_percent: 10.0,
+            jump_rounds: 2,
+            jump_tolerance_percent: 1.0,
+            oracles: oracles[..3]
+                .iter()
+                .zip([1.0, 1.0, 3.0].iter())
+                .map(|(o, w)| OracleConfiguration {
+                    account: o.account.clone(),
+                    public_key: o.public_key(),
+                    weight: *w,
+                })
+                .collect(),
+        });
+
+        let now = 10000;
+        aggregator
+            .add_vote(
+                &vote(&oracles[0], now, &[("EUR", 0.80), ("GBP", 0.73)]),
+                now,
+            )
+            .unwrap();
+        aggregator
+            .add_vote(
+                &vote(&oracles[1], now, &[("EUR", 0.81), ("GBP", 0.73)]),
+                now,
+            )
+            .unwrap();
+        aggregator
+            .add_vote(&vote(&oracles[2], now, &[("EUR", 0.83)]), now)
+            .unwrap();
+
+        // Unknown oracle, replayed and stale votes.
+        assert!(aggregator
+            .add_vote(&vote(&oracles[3], now, &[("EUR", 8.0)]), now)
+            .is_err());
+        assert!(aggregator
+            .add_vote(&vote(&oracles[0], now, &[("EUR", 8.0)]), now)
+            .is_err());
+        assert!(aggregator
+            .add_vote(&vote(&oracles[1], now - 1000, &[("EUR", 8.0)]), now)
+            .is_err());
+
+        let (update, decisions) = aggregator.close_round(None, now).unwrap();
+        // Weights 1, 1, 3: the heaviest oracle holds the median.
+        assert_eq!(update.rates["EUR"], 0.83);
+        assert_eq!(update.source, AGGREGATE_SOURCE);
+        assert_eq!(decisions["GBP"], Decision::NoQuorum { weight: 2.0 });
+        assert!(!update.rates.contains_key("GBP"));
+
+        assert_eq!(
+            aggregator.close_round(None, now).err().unwrap().to_string(),
+            "No votes"
+        );
+
+        // Votes of a closed round can't be replayed into the next one.
+        let replayed = vote(&oracles[0], now, &[("EUR", 0.80)]);
+        assert!(aggregator.add_vote(&replayed, now + 60).is_err());
+        assert!(aggregator
+            .add_vote(&vote(&oracles[0], now + 60, &[("EUR", 0.80)]), now + 60)
+            .is_ok());
+
+        // Future votes would block the oracle's next votes as replays.
+        assert_eq!(
+            aggregator
+                .add_vote(&vote(&oracles[2], now + 1000, &[("EUR", 0.80)]), now)
+                .err()
+                .unwrap()
+                .to_string(),
+            format!("Vote from {} is from the future", oracles[2].account)
+        );
+        assert!(aggregator
+            .add_vote(&vote(&oracles[2], now + 30, &[("EUR", 0.80)]), now)
+            .is_ok());
+
+        // Only positive weights.
+        let mut config = aggregator.config.clone();
+        assert!(config.validate().is_ok());
+        config.oracles[0].weight = f64::NAN;
+        assert!(config.validate().is_err());
+        config.oracles[0].weight = 0.0;
+        assert!(config.validate().is_err());
+        config.oracles.clear();
+        assert!(config.validate().is_err());
+
+        // Times near the end of the range don't overflow.
+        assert!(aggregator
+            .add_vote(&vote(&oracles[1], u32::MAX, &[("EUR", 0.80)]), now)
+            .is_err());
+        assert!(aggregator
+            .add_vote(&vote(&oracles[1], now + 60, &[("EUR", 0.80)]), u32::MAX)
+            .is_err());
+    }
+
+    #[test]
+    fn test_repeated_jump() {
+        let oracles = [oracle("a"), oracle("b")];
+        let aggregator = Aggregator::new(AggregatorConfiguration {
+            listen_address: "".to_string(),
+            round_seconds: 60,
+            max_vote_age_seconds: 300,
+            quorum_weight: 2.0,
+            max_change_percent: 10.0,
+            jump_rounds: 2,
+            jump_tolerance_percent: 1.0,
+            oracles: oracles
+                .iter()
+                .map(|o| OracleConfiguration {
+                    account: o.account.clone(),
+                    public_key: o.public_key(),
+                    weight: 1.0,
+                })
+                .collect(),
+        });
+        let dir = TempDir::new("smartlike-vote-test-aggregator");
+        let history = History::open(dir.path()).unwrap();
+        history
+            .add(
+                900,
+                &CurrencyExchangeRatesUpdate {
+                    source: AGGREGATE_SOURCE.to_string(),
+                    base: "USD".to_string(),
+                    ts: 900,
+                    rates: vec![("EUR".to_string(), 0.82), ("GBP".to_string(), 0.5)]
+                        .into_iter()
+                        .collect(),
+                },
+            )
+            .unwrap();
+
+        let round = |now: u32, gbp: f64| {
+            for oracle in &oracles {
+                aggregator
+                    .add_vote(&vote(oracle, now, &[("EUR", 0.82), ("GBP", gbp)]), now)
+                    .unwrap();
+            }
+            let (update, decisions) = aggregator
+                .close_round(history.published().as_ref(), now)
+                .unwrap();
+            history.add(now, &update).unwrap();
+            decisions["GBP"].clone()
+        };
+
+        // The rejected rate isn't published, so it's still a jump next round
+        // unless the rounds disagree on it.
+        let jump = |rate| Decision::Jump {
+            previous: 0.5,
+            rate,
+        };
+        assert_eq!(round(1000, 0.73), jump(0.73));
+        assert_eq!(round(1060, 0.8), jump(0.8));
+
+        // The currency recovers after 2 rounds in a row.
+        assert_eq!(
+            round(1120, 0.801),
+            Decision::Confirmed {
+                previous: 0.5,
+                rate: 0.801
+            }
+        );
+        assert_eq!(history.published().unwrap().rates["GBP"], 0.801);
+    }
+}
@@ -0,0 +1,394 @@
+use crate::currencies::rebase;
+use anyhow::anyhow;
+use smartlike_embed_lib::client::CurrencyExchangeRatesUpdate;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+pub const CONSENSUS_SOURCE: &str = "consensus";
+
+/// Rules applied to every currency before rates are published.
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct ConsensusConfiguration {
+    /// Minimum number of sources quoting a currency.
+    pub min_sources: usize,
+    /// Maximum deviation of any source from the median, in percent.
+    pub max_deviation_percent: f64,
+    /// Maximum change since the last published update, in percent.
+    pub max_change_percent: f64,
+    /// Rounds in a row a larger change has to be seen in to be published,
+    /// never if 0.
+    #[serde(default = "default_jump_rounds")]
+    pub jump_rounds: u32,
+    /// Maximum difference between the rates of these rounds, in percent.
+    #[serde(default = "default_jump_tolerance_percent")]
+    pub jump_tolerance_percent: f64,
+}
+
+pub fn default_jump_rounds() -> u32 {
+    3
+}
+
+pub fn default_jump_tolerance_percent() -> f64 {
+    2.0
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decision {
+    Accepted { rate: f64, sources: usize },
+    TooFewSources { sources: usize },
+    Disagreement { min: f64, max: f64, median: f64 },
+    Jump { previous: f64, rate: f64 },
+    Confirmed { previous: f64, rate: f64 },
+    NoQuorum { weight: f64 },
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Decision::Accepted { rate, sources } => {
+                write!(f, "accepted {} from {} source(s)", rate, sources)
+            }
+            Decision::TooFewSources { sources } => {
+                write!(f, "rejected, quoted by {} valid source(s)", sources)
+            }
+            Decision::Disagreement { min, max, median } => write!(
+                f,
+                "rejected, sources disagree: min {} max {} median {}",
+                min, max, median
+            ),
+            Decision::Jump { previous, rate } => write!(
+                f,
+                "rejected, moved from {} to {} since the last update",
+                previous, rate
+            ),
+            Decision::Confirmed { previous, rate } => write!(
+                f,
+                "accepted, moved from {} to {} in enough rounds in a row",
+                previous, rate
+            ),
+            Decision::NoQuorum { weight } => {
+                write!(f, "rejected, voted by oracles with total weight {}", weight)
+            }
+        }
+    }
+}
+
+struct Pending {
+    rate: f64,
+    rounds: u32,
+    ts: u32,
+}
+
+/// Jumps rejected in the last rounds. A jump is accepted once it's seen in
+/// enough rounds in a row, so a currency that really moved isn't stuck at its
+/// old rate forever.
+#[derive(Default)]
+pub struct Jumps {
+    pending: HashMap<String, Pending>,
+}
+
+impl Jumps {
+    /// Turns jumps seen in `rounds` rounds in a row, within
+    /// `tolerance_percent` of the first one, into confirmed decisions and
+    /// forgets currencies that didn't jump. Rounds are told apart by `ts`, so
+    /// a retry with the same rates doesn't count but is still confirmed.
+    pub fn review(
+        &mut self,
+        decisions: &mut BTreeMap<String, Decision>,
+        ts: u32,
+        rounds: u32,
+        tolerance_percent: f64,
+    ) {
+        self.pending
+            .retain(|currency, _| matches!(decisions.get(currency), Some(Decision::Jump { .. })));
+        for (currency, decision) in decisions.iter_mut() {
+            if let Decision::Jump { previous, rate } = *decision {
+                let pending = self.pending.entry(currency.clone()).or_insert(Pending {
+                    rate,
+                    rounds: 0,
+                    ts: 0,
+                });
+                if percent(rate, pending.rate) > tolerance_percent {
+                    *pending = Pending {
+                        rate,
+                        rounds: 0,
+                        ts: 0,
+                    };
+                }
+                if pending.ts < ts || pending.rounds == 0 {
+                    pending.rounds += 1;
+                    pending.ts = ts;
+                }
+                if rounds > 0 && pending.rounds >= rounds {
+                    *decision = Decision::Confirmed { previous, rate };
+                }
+            }
+        }
+    }
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mid = values.len() / 2;
+    match values.len() % 2 {
+        0 => (values[mid - 1] + values[mid]) / 2.0,
+        _ => values[mid],
+    }
+}
+
+fn percent(a: f64, b: f64) -> f64 {
+    (a - b).abs() / b * 100.0
+}
+
+/// Computes per-currency medians and filters out currencies that don't satisfy
+/// the rules. `previous` holds the last published rate of every currency,
+/// `jumps` the jumps rejected in the last rounds. Returns the update to
+/// publish and the decision for every currency.
+pub fn aggregate(
+    updates: &[CurrencyExchangeRatesUpdate],
+    previous: Option<&CurrencyExchangeRatesUpdate>,
+    jumps: &mut Jumps,
+    config: &ConsensusConfiguration,
+) -> anyhow::Result<(CurrencyExchangeRatesUpdate, BTreeMap<String, Decision>)> {
+    let base = updates
+        .first()
+        .ok_or(anyhow!("No exchange rates available"))?
+        .base
+        .clone();
+
+    let mut quotes: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    let mut ts = 0;
+    for update in updates {
+        match rebase(update, &base) {
+            Ok(update) => {
+                ts = ts.max(update.ts);
+                for (currency, rate) in update.rates {
+                    let entry = quotes.entry(currency).or_insert(vec![]);
+                    // Zero, negative or missing rates are never valid.
+                    if rate.is_finite() && rate > 0.0 {
+                        entry.push(rate);
+                    }
+                }
+            }
+            Err(e) => warn!("Source ignored: {}", e),
+        }
+    }
+
+    let previous = match previous {
+        Some(p) => match rebase(p, &base) {
+            Ok(p) => Some(p),
+            Err(e) => {
+                warn!("Last published update ignored: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    let mut decisions = BTreeMap::new();
+    for (currency, mut values) in quotes {
+        if currency == base {
+            continue;
+        }
+        let decision = if values.len() < config.min_sources.max(1) {
+            Decision::TooFewSources {
+                sources: values.len(),
+            }
+        } else {
+            let rate = median(&mut values);
+            let (min, max) = (values[0], values[values.len() - 1]);
+            let last = previous
+                .as_ref()
+                .and_then(|p| p.rates.get(&currency))
+                .filter(|r| r.is_finite() && **r > 0.0);
+            if percent(min, rate).max(percent(max, rate)) > config.max_deviation_percent {
+                Decision::Disagreement {
+                    min,
+                    max,
+                    median: rate,
+                }
+            } else if let Some(previous) =
+                last.filter(|l| percent(rate, **l) > config.max_change_percent)
+            {
+                Decision::Jump {
+                    previous: *previous,
+                    rate,
+                }
+            } else {
+                Decision::Accepted {
+                    rate,
+                    sources: values.len(),
+                }
+            }
+        };
+        decisions.insert(currency, decision);
+    }
+    jumps.review(
+        &mut decisions,
+        ts,
+        config.jump_rounds,
+        config.jump_tolerance_percent,
+    );
+
+    let mut rates: HashMap<String, f64> = decisions
+        .iter()
+        .filter_map(|(currency, decision)| match decision {
+            Decision::Accepted { rate, .. } | Decision::Confirmed { rate, .. } => {
+                Some((currency.clone(), *rate))
+            }
+            _ => None,
+        })
+        .collect();
+    rates.insert(base.clone(), 1.0);
+
+    Ok((
+        CurrencyExchangeRatesUpdate {
+            source: CONSENSUS_SOURCE.to_string(),
+            base,
+            ts,
+            rates,
+        },
+        decisions,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::History;
+    use smartlike_like_queue::testing::TempDir;
+
+    fn update(source: &str, base: &str, rates: &[(&str, f64)]) -> CurrencyExchangeRatesUpdate {
+        CurrencyExchangeRatesUpdate {
+            source: source.to_string(),
+            base: base.to_string(),
+            ts: 1611878400,
+            rates: rates.iter().map(|(c, r)| (c.to_string(), *r)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_consensus() {
+        let config = ConsensusConfiguration {
+            min_sources: 2,
+            max_deviation_percent: 1.0,
+            max_change_percent: 10.0,
+            jump_rounds: 3,
+            jump_tolerance_percent: 1.0,
+        };
+        let updates = vec![
+            update(
+                "a",
+                "USD",
+                &[("EUR", 0.82), ("GBP", 0.73), ("JPY", 104.0), ("RUB", 76.0)],
+            ),
+            update(
+                "b",
+                "USD",
+                &[("EUR", 0.824), ("GBP", 0.0), ("JPY", 10.4), ("CHF", 0.89)],
+            ),
+            // EUR based source is rebased to USD.
+            update(
+                "c",
+                "EUR",
+                &[("USD", 1.2136), ("GBP", 0.88525), ("JPY", 126.2)],
+            ),
+        ];
+        let previous = update("consensus", "USD", &[("EUR", 0.82), ("GBP", 0.5)]);
+
+        let (result, decisions) =
+            aggregate(&updates, Some(&previous), &mut Jumps::default(), &config).unwrap();
+        assert_eq!(result.base, "USD");
+        assert_eq!(result.source, CONSENSUS_SOURCE);
+
+        match decisions["EUR"] {
+            Decision::Accepted { rate, sources } => {
+                assert!((rate - 0.824).abs() < 0.0001);
+                assert_eq!(sources, 3);
+            }
+            _ => panic!("unexpected decision {:?}", decisions["EUR"]),
+        }
+        assert_eq!(result.rates["USD"], 1.0);
+
+        // Zero rate dropped, the rest moved too far since the previous update.
+        match decisions["GBP"] {
+            Decision::Jump { previous, .. } => assert_eq!(previous, 0.5),
+            _ => panic!("unexpected decision {:?}", decisions["GBP"]),
+        }
+        // Decimal shift.
+        match decisions["JPY"] {
+            Decision::Disagreement { min, .. } => assert_eq!(min, 10.4),
+            _ => panic!("unexpected decision {:?}", decisions["JPY"]),
+        }
+        assert_eq!(decisions["RUB"], Decision::TooFewSources { sources: 1 });
+        assert_eq!(decisions["CHF"], Decision::TooFewSources { sources: 1 });
+
+        assert_eq!(result.rates.len(), 2);
+        assert!(result.rates.contains_key("EUR"));
+    }
+
+    #[test]
+    fn test_repeated_jump() {
+        let config = ConsensusConfiguration {
+            min_sources: 2,
+            max_deviation_percent: 1.0,
+            max_change_percent: 10.0,
+            jump_rounds: 3,
+            jump_tolerance_percent: 1.0,
+        };
+        let dir = TempDir::new("smartlike-vote-test-consensus");
+        let history = History::open(dir.path()).unwrap();
+        history
+            .add(
+                900,
+                &update("consensus", "USD", &[("EUR", 0.82), ("GBP", 0.5)]),
+            )
+            .unwrap();
+
+        let mut jumps = Jumps::default();
+        let mut round = |ts: u32, gbp: f64| {
+            let mut updates = vec![
+                update("a", "USD", &[("EUR", 0.82), ("GBP", gbp)]),
+                update("b", "USD", &[("EUR", 0.821), ("GBP", gbp)]),
+            ];
+            for u in updates.iter_mut() {
+                u.ts = ts;
+            }
+            let previous = history.published();
+            let (result, decisions) =
+                aggregate(&updates, previous.as_ref(), &mut jumps, &config).unwrap();
+            history.add(ts, &result).unwrap();
+            decisions["GBP"].clone()
+        };
+        let jump = |rate| Decision::Jump {
+            previous: 0.5,
+            rate,
+        };
+
+        // The rejected rate isn't published, so it's still a jump next round.
+        assert_eq!(round(1000, 0.73), jump(0.73));
+        // A retry with the same rates isn't another round.
+        assert_eq!(round(1000, 0.73), jump(0.73));
+        assert_eq!(round(2000, 0.73), jump(0.73));
+        // Rounds disagreeing on the new rate start over.
+        assert_eq!(round(3000, 0.8), jump(0.8));
+        assert_eq!(round(4000, 0.803), jump(0.803));
+
+        // The currency recovers after 3 rounds in a row.
+        assert_eq!(
+            round(5000, 0.801),
+            Decision::Confirmed {
+                previous: 0.5,
+                rate: 0.801
+            }
+        );
+        assert_eq!(history.published().unwrap().rates["GBP"], 0.801);
+        assert_eq!(
+            round(6000, 0.802),
+            Decision::Accepted {
+                rate: 0.802,
+                sources: 2
+            }
+        );
+    }
+}
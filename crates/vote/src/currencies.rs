@@ -0,0 +1,168 @@
+use anyhow::anyhow;
+use smartlike_embed_lib::client::CurrencyExchangeRatesUpdate;
+use std::collections::HashMap;
+
+/// Active ISO 4217 currency codes. Precious metals (XAU, XAG, ...), special
+/// drawing rights (XDR), funds and testing codes are not currencies.
+const ISO_4217: &[&str] = &[
+    "AED", "AFN", "ALL", "AMD", "ANG", "AOA", "ARS", "AUD", "AWG", "AZN", "BAM", "BBD", "BDT",
+    "BGN", "BHD", "BIF", "BMD", "BND", "BOB", "BRL", "BSD", "BTN", "BWP", "BYN", "BZD", "CAD",
+    "CDF", "CHF", "CLP", "CNY", "COP", "CRC", "CUC", "CUP", "CVE", "CZK", "DJF", "DKK", "DOP",
+    "DZD", "EGP", "ERN", "ETB", "EUR", "FJD", "FKP", "GBP", "GEL", "GHS", "GIP", "GMD", "GNF",
+    "GTQ", "GYD", "HKD", "HNL", "HRK", "HTG", "HUF", "IDR", "ILS", "INR", "IQD", "IRR", "ISK",
+    "JMD", "JOD", "JPY", "KES", "KGS", "KHR", "KMF", "KPW", "KRW", "KWD", "KYD", "KZT", "LAK",
+    "LBP", "LKR", "LRD", "LSL", "LYD", "MAD", "MDL", "MGA", "MKD", "MMK", "MNT", "MOP", "MRU",
+    "MUR", "MVR", "MWK", "MXN", "MYR", "MZN", "NAD", "NGN", "NIO", "NOK", "NPR", "NZD", "OMR",
+    "PAB", "PEN", "PGK", "PHP", "PKR", "PLN", "PYG", "QAR", "RON", "RSD", "RUB", "RWF", "SAR",
+    "SBD", "SCR", "SDG", "SEK", "SGD", "SHP", "SLE", "SLL", "SOS", "SRD", "SSP", "STN", "SVC",
+    "SYP", "SZL", "THB", "TJS", "TMT", "TND", "TOP", "TRY", "TTD", "TWD", "TZS", "UAH", "UGX",
+    "USD", "UYU", "UZS", "VED", "VES", "VND", "VUV", "WST", "XAF", "XCD", "XCG", "XOF", "XPF",
+    "YER", "ZAR", "ZMW", "ZWG", "ZWL",
+];
+
+pub fn is_currency(code: &str) -> bool {
+    ISO_4217.binary_search(&code).is_ok()
+}
+
+/// Currencies of published updates.
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct CurrencyConfiguration {
+    /// Base currency of published updates. Sources keep their own base if empty.
+    pub base: String,
+    /// Published currencies. All ISO 4217 currencies if empty.
+    pub allowed: Vec<String>,
+}
+
+impl CurrencyConfiguration {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for code in self.allowed.iter().chain(Some(&self.base)) {
+            if !code.is_empty() && !is_currency(code) {
+                return Err(anyhow!("{} is not an ISO 4217 currency", code));
+            }
+        }
+        Ok(())
+    }
+
+    fn is_allowed(&self, code: &str) -> bool {
+        is_currency(code) && (self.allowed.is_empty() || self.allowed.iter().any(|c| c == code))
+    }
+}
+
+/// Converts rates to another base through the update's own base.
+pub fn rebase(
+    update: &CurrencyExchangeRatesUpdate,
+    base: &str,
+) -> anyhow::Result<CurrencyExchangeRatesUpdate> {
+    if update.base == base {
+        return Ok(update.clone());
+    }
+    let base_rate =
+        *update
+            .rates
+            .get(base)
+            .ok_or(anyhow!("{} doesn't quote {}", update.source, base))?;
+    if !base_rate.is_finite() || base_rate <= 0.0 {
+        return Err(anyhow!(
+            "{} quotes {} at {}",
+            update.source,
+            base,
+            base_rate
+        ));
+    }
+    let mut rates: HashMap<String, f64> = update
+        .rates
+        .iter()
+        .map(|(currency, rate)| (currency.clone(), rate / base_rate))
+        .collect();
+    rates.insert(update.base.clone(), 1.0 / base_rate);
+    Ok(CurrencyExchangeRatesUpdate {
+        source: update.source.clone(),
+        base: base.to_string(),
+        ts: update.ts,
+        rates,
+    })
+}
+
+/// Rebases a source update to the configured base and drops codes that are not
+/// allowed ISO 4217 currencies.
+pub fn normalize(
+    update: &CurrencyExchangeRatesUpdate,
+    config: &CurrencyConfiguration,
+) -> anyhow::Result<CurrencyExchangeRatesUpdate> {
+    let mut update = match config.base.as_str() {
+        "" => update.clone(),
+        base => rebase(update, base)?,
+    };
+    if !is_currency(&update.base) {
+        return Err(anyhow!(
+            "{} base {} is not an ISO 4217 currency",
+            update.source,
+            update.base
+        ));
+    }
+
+    let base = update.base.clone();
+    let mut dropped = vec![];
+    update.rates.retain(|code, _| {
+        let keep = *code == base || config.is_allowed(code);
+        if !keep {
+            dropped.push(code.clone());
+        }
+        keep
+    });
+    if !dropped.is_empty() {
+        dropped.sort();
+        debug!("{}: dropped {}", update.source, dropped.join(" "));
+    }
+    Ok(update)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalization() {
+        assert!(ISO_4217.windows(2).all(|w| w[0] < w[1]));
+
+        let resp = std::fs::read_to_string("./test/openexchangerates.json").unwrap();
+        let update: serde_json::Value = serde_json::from_str(&resp).unwrap();
+        let update = CurrencyExchangeRatesUpdate {
+            source: "openexchangerates.org".to_string(),
+            base: "USD".to_string(),
+            ts: 1611860400,
+            rates: serde_json::from_value(update["rates"].clone()).unwrap(),
+        };
+
+        let config = CurrencyConfiguration {
+            base: "EUR".to_string(),
+            allowed: vec![],
+        };
+        let normalized = normalize(&update, &config).unwrap();
+        assert_eq!(normalized.base, "EUR");
+        assert_eq!(normalized.rates["EUR"], 1.0);
+        assert!((normalized.rates["USD"] * update.rates["EUR"] - 1.0).abs() < 1e-12);
+        for code in &["XAU", "XAG", "XDR", "XPD", "XPT"] {
+            assert!(update.rates.contains_key(*code));
+            assert!(!normalized.rates.contains_key(*code));
+        }
+        assert!(normalized.rates.contains_key("XOF"));
+
+        let config = CurrencyConfiguration {
+            base: "EUR".to_string(),
+            allowed: vec!["USD".to_string(), "GBP".to_string()],
+        };
+        let normalized = normalize(&update, &config).unwrap();
+        let mut codes: Vec<&String> = normalized.rates.keys().collect();
+        codes.sort();
+        assert_eq!(codes, vec!["EUR", "GBP", "USD"]);
+
+        // The provider doesn't quote the configured base.
+        let config = CurrencyConfiguration {
+            base: "XYZ".to_string(),
+            allowed: vec![],
+        };
+        assert!(config.validate().is_err());
+        assert!(normalize(&update, &config).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::currencies::rebase;
+use smartlike_embed_lib::client::CurrencyExchangeRatesUpdate;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Rate change of a currency between two updates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub currency: String,
+    pub previous: Option<f64>,
+    pub rate: Option<f64>,
+}
+
+impl Change {
+    pub fn percent(&self) -> Option<f64> {
+        match (self.previous, self.rate) {
+            (Some(previous), Some(rate)) => Some((rate - previous) / previous * 100.0),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.previous, self.rate) {
+            (Some(previous), Some(rate)) => write!(
+                f,
+                "{:<4} {:>16} -> {:<16} {:+.2}%",
+                self.currency,
+                previous,
+                rate,
+                self.percent().unwrap_or_default()
+            ),
+            (None, Some(rate)) => write!(f, "{:<4} {:>16} -> {:<16} new", self.currency, "", rate),
+            (Some(previous), None) => write!(
+                f,
+                "{:<4} {:>16} -> {:<16} removed",
+                self.currency, previous, ""
+            ),
+            (None, None) => write!(f, "{:<4}", self.currency),
+        }
+    }
+}
+
+/// Compares an update with the previous one in the update's base currency.
+pub fn diff(
+    previous: Option<&CurrencyExchangeRatesUpdate>,
+    update: &CurrencyExchangeRatesUpdate,
+) -> Vec<Change> {
+    let previous = match previous.map(|p| rebase(p, &update.base)) {
+        Some(Ok(p)) => Some(p),
+        Some(Err(e)) => {
+            warn!("Last published update ignored: {}", e);
+            None
+        }
+        None => None,
+    };
+
+    let mut currencies: BTreeSet<&String> = update.rates.keys().collect();
+    if let Some(p) = &previous {
+        currencies.extend(p.rates.keys());
+    }
+    currencies
+        .into_iter()
+        .filter(|currency| **currency != update.base)
+        .map(|currency| Change {
+            currency: currency.clone(),
+            previous: previous
+                .as_ref()
+                .and_then(|p| p.rates.get(currency).cloned()),
+            rate: update.rates.get(currency).cloned(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(base: &str, rates: &[(&str, f64)]) -> CurrencyExchangeRatesUpdate {
+        CurrencyExchangeRatesUpdate {
+            source: "consensus".to_string(),
+            base: base.to_string(),
+            ts: 1611878400,
+            rates: rates.iter().map(|(c, r)| (c.to_string(), *r)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let previous = update("EUR", &[("USD", 1.25), ("GBP", 0.9), ("EUR", 1.0)]);
+        let next = update("USD", &[("EUR", 0.82), ("JPY", 104.0), ("USD", 1.0)]);
+
+        let changes = diff(Some(&previous), &next);
+        assert_eq!(
+            changes
+                .iter()
+                .map(|c| c.currency.as_str())
+                .collect::<Vec<_>>(),
+            vec!["EUR", "GBP", "JPY"]
+        );
+        assert_eq!(changes[0].previous, Some(0.8));
+        assert!((changes[0].percent().unwrap() - 2.5).abs() < 1e-9);
+        assert_eq!(changes[1].rate, None);
+        assert_eq!(changes[2].previous, None);
+        assert!(changes[1].to_string().ends_with("removed"));
+        assert!(changes[2].to_string().ends_with("new"));
+
+        assert!(diff(None, &next).iter().all(|c| c.previous.is_none()));
+    }
+}
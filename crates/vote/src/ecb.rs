@@ -0,0 +1,84 @@
+use crate::rates::RateSource;
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use smartlike_embed_lib::client::CurrencyExchangeRatesUpdate;
+use std::collections::HashMap;
+
+/// European Central Bank daily reference rates, e.g.
+/// https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml
+pub struct Ecb {
+    pub name: String,
+    pub query: String,
+}
+
+impl RateSource for Ecb {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn query(&self) -> &str {
+        &self.query
+    }
+
+    fn parse(&self, resp: &str) -> anyhow::Result<CurrencyExchangeRatesUpdate> {
+        parse(&self.name, resp)
+    }
+}
+
+fn parse(source: &str, resp: &str) -> anyhow::Result<CurrencyExchangeRatesUpdate> {
+    let doc = roxmltree::Document::parse(resp).map_err(|err| anyhow!("Parse error: {}", err))?;
+
+    // <Cube><Cube time="2021-01-29"><Cube currency="USD" rate="1.2136"/>...</Cube></Cube>
+    let day = doc
+        .descendants()
+        .find(|n| n.tag_name().name() == "Cube" && n.has_attribute("time"))
+        .ok_or(anyhow!("Missing rates"))?;
+    let time = day.attribute("time").unwrap_or_default();
+    let ts = NaiveDate::parse_from_str(time, "%Y-%m-%d")
+        .map_err(|err| anyhow!("Failed to parse date '{}': {}", time, err))?
+        .signed_duration_since(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap())
+        .num_seconds() as u32;
+
+    let mut rates = HashMap::new();
+    for cube in day.children().filter(|n| n.tag_name().name() == "Cube") {
+        match (cube.attribute("currency"), cube.attribute("rate")) {
+            (Some(currency), Some(rate)) => {
+                let rate = rate
+                    .parse::<f64>()
+                    .map_err(|err| anyhow!("Failed to parse rate for {}: {}", currency, err))?;
+                rates.insert(currency.to_string(), rate);
+            }
+            _ => return Err(anyhow!("Malformed rate entry")),
+        }
+    }
+    if rates.is_empty() {
+        return Err(anyhow!("Missing rates"));
+    }
+    // Reference rates are quoted against the euro.
+    rates.insert("EUR".to_string(), 1.0);
+
+    Ok(CurrencyExchangeRatesUpdate {
+        source: source.to_string(),
+        base: "EUR".to_string(),
+        ts,
+        rates,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ecb_parsing() {
+        let resp = std::fs::read_to_string("./test/ecb.xml").unwrap();
+        let update = parse("ecb.europa.eu", &resp).unwrap();
+        assert_eq!(update.base, "EUR");
+        assert_eq!(update.ts, 1611878400);
+        assert_eq!(update.rates.len(), 33);
+        assert_eq!(update.rates["USD"], 1.2136);
+        assert_eq!(update.rates["EUR"], 1.0);
+
+        assert!(parse("ecb.europa.eu", "<Cube></Cube>").is_err());
+    }
+}
@@ -0,0 +1,203 @@
+use crate::currencies::rebase;
+use anyhow::anyhow;
+use rocksdb::{DBWithThreadMode, Direction, IteratorMode, MultiThreaded};
+use smartlike_embed_lib::client::CurrencyExchangeRatesUpdate;
+use std::sync::Mutex;
+
+/// Published update.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Record {
+    pub published: u32,
+    pub update: CurrencyExchangeRatesUpdate,
+}
+
+/// Rate of a single currency in a published update.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RatePoint {
+    pub published: u32,
+    pub ts: u32,
+    pub source: String,
+    pub base: String,
+    pub rate: f64,
+}
+
+/// Key of the last published rate of every currency, sorted after the
+/// records.
+const PUBLISHED_KEY: &str = "published";
+
+/// Log of published updates ordered by publication time.
+pub struct History {
+    db: DBWithThreadMode<MultiThreaded>,
+    /// Serializes numbering of records published in the same second.
+    adding: Mutex<()>,
+}
+
+impl History {
+    pub fn open(path: &str) -> anyhow::Result<History> {
+        let db = DBWithThreadMode::<MultiThreaded>::open_default(path)
+            .map_err(|e| anyhow!("Failed to open {}: {}", path, e))?;
+        Ok(History {
+            db,
+            adding: Mutex::new(()),
+        })
+    }
+
+    pub fn add(&self, published: u32, update: &CurrencyExchangeRatesUpdate) -> anyhow::Result<()> {
+        let record = Record {
+            published,
+            update: update.clone(),
+        };
+        // Zero padded keys keep records in chronological order, the sequence
+        // number keeps the ones published in the same second.
+        let _adding = self.adding.lock().unwrap();
+        let second = format!("{:010}", published);
+        let sequence = self
+            .db
+            .iterator(IteratorMode::From(second.as_bytes(), Direction::Forward))
+            .take_while(|(key, _)| key.starts_with(second.as_bytes()))
+            .count();
+        self.db
+            .put(
+                format!("{}.{:04}", second, sequence),
+                serde_json::to_string(&record)?,
+            )
+            .map_err(|e| anyhow!("DB error: {}", e))?;
+
+        // Records added out of order are rare, the snapshot is rebuilt then.
+        let latest = self.latest().next().map(|r| r.published) == Some(published);
+        let snapshot = match self.published() {
+            Some(older) if latest => merge(update.clone(), &older),
+            _ => self.merge_records().unwrap_or_else(|| update.clone()),
+        };
+        self.db
+            .put(PUBLISHED_KEY, serde_json::to_string(&snapshot)?)
+            .map_err(|e| anyhow!("DB error: {}", e))
+    }
+
+    /// Iterates records starting from the latest one.
+    fn latest(&self) -> impl Iterator<Item = Record> + '_ {
+        self.db
+            .iterator(IteratorMode::End)
+            .filter(|(key, _)| &**key != PUBLISHED_KEY.as_bytes())
+            .filter_map(
+                |(key, value)| match serde_json::from_slice::<Record>(&value) {
+                    Ok(record) => Some(record),
+                    Err(e) => {
+                        error!("Failed to parse history record {:?}: {}", key, e);
+                        None
+                    }
+                },
+            )
+    }
+
+    /// Latest published rate of every currency, in the base of the last
+    /// update. Currencies left out of later updates, e.g. rejected jumps, keep
+    /// the rate they were last published at.
+    pub fn published(&self) -> Option<CurrencyExchangeRatesUpdate> {
+        match self.db.get(PUBLISHED_KEY) {
+            Ok(Some(value)) => match serde_json::from_slice(&value) {
+                Ok(published) => return Some(published),
+                Err(e) => error!("Failed to parse published rates: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => error!("DB error: {}", e),
+        }
+        // Histories written before the snapshot was kept.
+        self.merge_records()
+    }
+
+    fn merge_records(&self) -> Option<CurrencyExchangeRatesUpdate> {
+        let mut records = self.latest();
+        let published = records.next()?.update;
+        Some(records.fold(published, |published, record| {
+            merge(published, &record.update)
+        }))
+    }
+
+    /// Returns up to `n` latest updates.
+    pub fn updates(&self, n: usize) -> Vec<Record> {
+        self.latest().take(n).collect()
+    }
+
+    /// Returns up to `n` latest rates of the currency.
+    pub fn rates(&self, currency: &str, n: usize) -> Vec<RatePoint> {
+        self.latest()
+            .filter_map(|record| {
+                record.update.rates.get(currency).map(|rate| RatePoint {
+                    published: record.published,
+                    ts: record.update.ts,
+                    source: record.update.source.clone(),
+                    base: record.update.base.clone(),
+                    rate: *rate,
+                })
+            })
+            .take(n)
+            .collect()
+    }
+}
+
+/// Adds rates of currencies missing from `newer` from an older update.
+fn merge(
+    mut newer: CurrencyExchangeRatesUpdate,
+    older: &CurrencyExchangeRatesUpdate,
+) -> CurrencyExchangeRatesUpdate {
+    match rebase(older, &newer.base) {
+        Ok(older) => {
+            for (currency, rate) in older.rates {
+                newer.rates.entry(currency).or_insert(rate);
+            }
+        }
+        Err(e) => debug!("Rates of {} ignored: {}", older.ts, e),
+    }
+    newer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use smartlike_like_queue::testing::TempDir;
+
+    fn update(ts: u32, rates: &[(&str, f64)]) -> CurrencyExchangeRatesUpdate {
+        CurrencyExchangeRatesUpdate {
+            source: "consensus".to_string(),
+            base: "USD".to_string(),
+            ts,
+            rates: rates.iter().map(|(c, r)| (c.to_string(), *r)).collect(),
+        }
+    }
+
+    #[test]
+    fn test_history() {
+        let dir = TempDir::new("smartlike-vote-test-history");
+        let history = History::open(dir.path()).unwrap();
+        assert!(history.published().is_none());
+
+        history.add(900, &update(800, &[("EUR", 0.81)])).unwrap();
+        history
+            .add(1000, &update(950, &[("EUR", 0.82), ("GBP", 0.73)]))
+            .unwrap();
+        history.add(10000, &update(9000, &[("EUR", 0.83)])).unwrap();
+
+        assert_eq!(history.updates(1)[0].published, 10000);
+        assert_eq!(history.updates(2).len(), 2);
+
+        // Updates published in the same second are all kept, in order.
+        history.add(900, &update(850, &[("EUR", 0.815)])).unwrap();
+        assert_eq!(history.updates(10).len(), 4);
+        let rates = history.rates("EUR", 10);
+        assert_eq!(rates[2].rate, 0.815);
+        assert_eq!(rates[3].rate, 0.81);
+
+        let rates = history.rates("EUR", 2);
+        assert_eq!(rates.len(), 2);
+        assert_eq!(rates[0].rate, 0.83);
+        assert_eq!(rates[0].ts, 9000);
+        assert_eq!(rates[1].rate, 0.82);
+        assert_eq!(history.rates("GBP", 10).len(), 1);
+        assert!(history.rates("JPY", 10).is_empty());
+
+        let published = history.published().unwrap();
+        assert_eq!(published.rates["EUR"], 0.83);
+        assert_eq!(published.rates["GBP"], 0.73);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::rates::{RateSource, SourceConfiguration};
+use anyhow::anyhow;
+use smartlike_embed_lib::client::CurrencyExchangeRatesUpdate;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Generic JSON feed. Rates, base currency and timestamp are located with
+/// JSON pointers (RFC 6901), e.g. "/data/rates".
+pub struct JsonRates {
+    pub name: String,
+    pub query: String,
+    pub rates_pointer: String,
+    pub base_pointer: String,
+    pub timestamp_pointer: String,
+    pub base: String,
+}
+
+impl JsonRates {
+    pub fn create(name: String, config: &SourceConfiguration) -> anyhow::Result<JsonRates> {
+        if config.rates_pointer.is_empty() {
+            return Err(anyhow!("{}: missing rates_pointer", name));
+        }
+        if config.base_pointer.is_empty() && config.base.is_empty() {
+            return Err(anyhow!("{}: missing base_pointer or base", name));
+        }
+        Ok(JsonRates {
+            name,
+            query: config.query.clone(),
+            rates_pointer: config.rates_pointer.clone(),
+            base_pointer: config.base_pointer.clone(),
+            timestamp_pointer: config.timestamp_pointer.clone(),
+            base: config.base.clone(),
+        })
+    }
+}
+
+impl RateSource for JsonRates {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn query(&self) -> &str {
+        &self.query
+    }
+
+    fn parse(&self, resp: &str) -> anyhow::Result<CurrencyExchangeRatesUpdate> {
+        let j: serde_json::Value =
+            serde_json::from_str(resp).map_err(|err| anyhow!("Parse error: {} {}", err, resp))?;
+
+        let mut rates = HashMap::new();
+        let object = j
+            .pointer(&self.rates_pointer)
+            .and_then(|v| v.as_object())
+            .ok_or(anyhow!("Missing rates at {}", self.rates_pointer))?;
+        for (currency, value) in object {
+            rates.insert(
+                currency.to_string(),
+                to_f64(value).ok_or(anyhow!("Failed to parse rate for {}", currency))?,
+            );
+        }
+
+        let base = match self.base_pointer.is_empty() {
+            true => self.base.clone(),
+            false => j
+                .pointer(&self.base_pointer)
+                .and_then(|v| v.as_str())
+                .ok_or(anyhow!("Missing base at {}", self.base_pointer))?
+                .to_string(),
+        };
+
+        // Feeds without a timestamp are stamped with the download time.
+        let ts = match self.timestamp_pointer.is_empty() {
+            true => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|err| anyhow!("Failed get timestamp: {}", err))?
+                .as_secs() as u32,
+            false => j
+                .pointer(&self.timestamp_pointer)
+                .and_then(to_f64)
+                .ok_or(anyhow!("Missing timestamp at {}", self.timestamp_pointer))?
+                as u32,
+        };
+
+        Ok(CurrencyExchangeRatesUpdate {
+            source: self.name.clone(),
+            base,
+            ts,
+            rates,
+        })
+    }
+}
+
+fn to_f64(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.parse::<f64>().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_parsing() {
+        let config = SourceConfiguration {
+            kind: "json".to_string(),
+            query: "".to_string(),
+            rates_pointer: "/conversion_rates".to_string(),
+            base_pointer: "/base_code".to_string(),
+            timestamp_pointer: "/time_last_update_unix".to_string(),
+            ..Default::default()
+        };
+        let source = JsonRates::create("exchangerate-api.com".to_string(), &config).unwrap();
+        let resp = std::fs::read_to_string("./test/jsonrates.json").unwrap();
+        let update = source.parse(&resp).unwrap();
+        assert_eq!(update.source, "exchangerate-api.com");
+        assert_eq!(update.base, "USD");
+        assert_eq!(update.ts, 1611878401);
+        assert_eq!(update.rates.len(), 10);
+        assert_eq!(update.rates["EUR"], 0.8241);
+        assert_eq!(update.rates["JPY"], 104.4);
+
+        let config = SourceConfiguration {
+            base_pointer: "".to_string(),
+            base: "EUR".to_string(),
+            timestamp_pointer: "/missing".to_string(),
+            ..config
+        };
+        let source = JsonRates::create("exchangerate-api.com".to_string(), &config).unwrap();
+        assert_eq!(
+            source.parse(&resp).err().unwrap().to_string(),
+            "Missing timestamp at /missing"
+        );
+    }
+}
@@ -6,32 +6,241 @@ extern crate serde;
 #[macro_use]
 extern crate log;
 
-use smartlike_embed_lib::client::Client;
+use actix_web::{web, App, HttpServer};
+use aggregator::{Aggregator, AggregatorConfiguration};
+use consensus::{ConsensusConfiguration, Decision, Jumps};
+use currencies::CurrencyConfiguration;
+use history::History;
+use oracle::{SignedVote, Vote};
+use rates::{RateSource, SourceConfiguration};
+use server::ServerConfiguration;
+use smartlike_embed_lib::client::{Client, CurrencyExchangeRatesUpdate, SignedTransaction};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{fs::File, io::prelude::*};
 
+mod aggregator;
+mod consensus;
+mod currencies;
+mod diff;
+mod ecb;
+mod history;
+mod jsonrates;
 mod openexchangerates;
+mod oracle;
+mod rates;
+mod server;
 
 #[derive(Deserialize, Serialize, Clone, Default, Debug)]
 pub struct Configuration {
     pub network_address: String,
     pub smartlike_account: String,
     pub smartlike_key: String,
-    pub currency_exchange_source: String,
-    pub currency_exchange_query: String,
+    pub sources: Vec<SourceConfiguration>,
+    #[serde(default)]
+    pub currencies: CurrencyConfiguration,
+    pub consensus: ConsensusConfiguration,
+    pub daemon: DaemonConfiguration,
+    pub history_path: String,
+    /// Votes are sent to this aggregator instead of being published directly.
+    #[serde(default)]
+    pub aggregator_address: String,
+    #[serde(default)]
+    pub aggregator: AggregatorConfiguration,
+    #[serde(default)]
+    pub server: ServerConfiguration,
 }
 
-async fn fetch_exchange_rates(client: &Client, config: &Configuration) -> anyhow::Result<()> {
-    if config.currency_exchange_source == "openexchangerates.org" {
-        let rates = openexchangerates::download(&config).await?;
-        client.update_exchange_rates(&rates).await?;
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct DaemonConfiguration {
+    pub interval_seconds: u64,
+    pub retry_seconds: u64,
+    pub max_retry_seconds: u64,
+    pub max_retries: u32,
+}
+
+fn now() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32
+}
+
+/// Downloads rates from all sources and applies the consensus rules.
+async fn collect_exchange_rates(
+    config: &Configuration,
+    sources: &[Box<dyn RateSource>],
+    history: &History,
+    jumps: &mut Jumps,
+) -> anyhow::Result<(CurrencyExchangeRatesUpdate, usize)> {
+    let mut updates = vec![];
+    for source in sources {
+        match rates::download(source.as_ref())
+            .await
+            .and_then(|rates| currencies::normalize(&rates, &config.currencies))
+        {
+            Ok(rates) => {
+                info!(
+                    "{}: {} rates, base {}, ts {}",
+                    source.name(),
+                    rates.rates.len(),
+                    rates.base,
+                    rates.ts
+                );
+                updates.push(rates);
+            }
+            Err(e) => {
+                error!("Failed to fetch rates from {}: {}", source.name(), e);
+            }
+        }
+    }
+
+    let previous = history.published();
+    let (update, decisions) =
+        consensus::aggregate(&updates, previous.as_ref(), jumps, &config.consensus)?;
+    for (currency, decision) in &decisions {
+        match decision {
+            Decision::Accepted { .. } => info!("{}: {}", currency, decision),
+            _ => warn!("{}: {}", currency, decision),
+        }
+    }
+    if update.rates.len() < 2 {
+        return Err(anyhow::anyhow!("No exchange rates accepted"));
+    }
+    Ok((update, decisions.len()))
+}
+
+async fn fetch_exchange_rates(
+    client: &Client,
+    config: &Configuration,
+    sources: &[Box<dyn RateSource>],
+    history: &History,
+    jumps: &mut Jumps,
+) -> anyhow::Result<()> {
+    let (update, quoted) = collect_exchange_rates(config, sources, history, jumps).await?;
+    if config.aggregator_address.is_empty() {
+        info!("Publishing {} of {} rates", update.rates.len() - 1, quoted);
+        client.update_exchange_rates(&update).await?;
+        history.add(now(), &update)
     } else {
+        // Votes aren't published rates, the aggregator keeps the history.
+        info!("Voting for {} of {} rates", update.rates.len() - 1, quoted);
+        let vote = Vote { ts: now(), update };
+        oracle::send(
+            &config.aggregator_address,
+            &SignedVote::create(client, &vote)?,
+        )
+        .await
+    }
+}
+
+async fn run_daemon(
+    client: &Client,
+    config: &Configuration,
+    sources: &[Box<dyn RateSource>],
+    history: &History,
+) {
+    let mut jumps = Jumps::default();
+    loop {
+        let mut delay = config.daemon.retry_seconds;
+        let mut attempt = 0;
+        while let Err(e) = fetch_exchange_rates(client, config, sources, history, &mut jumps).await
+        {
+            attempt += 1;
+            if attempt > config.daemon.max_retries {
+                error!("Error: {}. Giving up until the next run.", e);
+                break;
+            }
+            error!("Error: {}. Retry in {} sec.", e, delay);
+            actix_rt::time::delay_for(Duration::from_secs(delay)).await;
+            delay = (delay * 2).min(config.daemon.max_retry_seconds);
+        }
+        actix_rt::time::delay_for(Duration::from_secs(config.daemon.interval_seconds)).await;
     }
+}
+
+async fn run_aggregator(
+    client: Client,
+    config: &AggregatorConfiguration,
+    history: web::Data<History>,
+) -> anyhow::Result<()> {
+    config.validate()?;
+    let aggregator = web::Data::new(Aggregator::new(config.clone()));
+    actix_rt::spawn(aggregator::run_rounds(aggregator.clone(), client, history));
+
+    info!("Collecting votes at {}", config.listen_address);
+    HttpServer::new(move || {
+        App::new()
+            .app_data(aggregator.clone())
+            .service(web::resource("/vote").route(web::post().to(aggregator::vote_handler)))
+    })
+    .bind(&config.listen_address)?
+    .run()
+    .await?;
     Ok(())
 }
 
-#[actix_rt::main]
-async fn main() -> anyhow::Result<()> {
-    let matches = clap::App::new("smartlike-gateway")
+fn print_diff(history: &History, update: &CurrencyExchangeRatesUpdate) {
+    let previous = history.published();
+    println!("Base: {}, ts: {}", update.base, update.ts);
+    for change in diff::diff(previous.as_ref(), update) {
+        println!("{}", change);
+    }
+}
+
+/// Prints the changes and saves the signed but unsent transaction.
+async fn dry_run(
+    client: &Client,
+    config: &Configuration,
+    sources: &[Box<dyn RateSource>],
+    history: &History,
+    output: &str,
+) -> anyhow::Result<()> {
+    let (update, _) =
+        collect_exchange_rates(config, sources, history, &mut Jumps::default()).await?;
+    print_diff(history, &update);
+    let tx = client.sign_exchange_rates_update(&update)?;
+    std::fs::write(output, serde_json::to_string_pretty(&tx)?)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", output, e))?;
+    println!("Signed transaction saved to {}", output);
+    Ok(())
+}
+
+/// Sends a transaction saved by a dry run.
+async fn submit(client: &Client, history: &History, path: &str) -> anyhow::Result<()> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path, e))?;
+    let tx: SignedTransaction = serde_json::from_str(&contents)
+        .map_err(|e| anyhow::anyhow!("Failed to parse {}: {}", path, e))?;
+    if tx.method != "update_exchange_rates" || tx.sender != client.account {
+        return Err(anyhow::anyhow!(
+            "Unexpected transaction {} from {}",
+            tx.method,
+            tx.sender
+        ));
+    }
+    tx.verify(&client.public_key())?;
+    let update: CurrencyExchangeRatesUpdate = tx.parameters()?;
+    print_diff(history, &update);
+    info!(
+        "Submitting {} rates signed at {}",
+        update.rates.len() - 1,
+        tx.ts()?
+    );
+    client.send_transaction(&tx, None).await?;
+    history.add(now(), &update)
+}
+
+fn print_history(history: &History, currency: Option<&str>, n: usize) -> anyhow::Result<()> {
+    let output = match currency {
+        Some(c) => serde_json::to_string_pretty(&history.rates(c, n))?,
+        None => serde_json::to_string_pretty(&history.updates(n))?,
+    };
+    println!("{}", output);
+    Ok(())
+}
+
+fn app() -> clap::App<'static, 'static> {
+    clap::App::new("smartlike-gateway")
         .version(option_env!("CARGO_PKG_VERSION").unwrap_or(""))
         .about("smartlike-gateway")
         .arg(
@@ -42,7 +251,61 @@ async fn main() -> anyhow::Result<()> {
                 .takes_value(true)
                 .default_value(""),
         )
-        .get_matches();
+        .arg(
+            clap::Arg::with_name("daemon")
+                .short("d")
+                .long("daemon")
+                .help("Publish rates periodically"),
+        )
+        .arg(
+            clap::Arg::with_name("dry-run")
+                .long("dry-run")
+                .help("Show changes and save the signed transaction without sending it")
+                .requires("output"),
+        )
+        .arg(
+            clap::Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .help("File the dry run saves the transaction to")
+                .takes_value(true),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("history")
+                .about("Show published updates")
+                .arg(clap::Arg::with_name("currency").help("Currency code"))
+                .arg(
+                    clap::Arg::with_name("last")
+                        .short("n")
+                        .long("last")
+                        .help("Number of updates")
+                        .takes_value(true)
+                        .default_value("10"),
+                ),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("aggregate")
+                .about("Collect oracle votes and publish aggregated rates"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("submit")
+                .about("Send a transaction saved by a dry run")
+                .arg(
+                    clap::Arg::with_name("file")
+                        .help("Saved transaction")
+                        .required(true),
+                ),
+        )
+        .subcommand(clap::SubCommand::with_name("serve").about("Serve published rates"))
+        .subcommand(
+            clap::SubCommand::with_name("public-key")
+                .about("Show the public key to register with an aggregator"),
+        )
+}
+
+#[actix_rt::main]
+async fn main() -> anyhow::Result<()> {
+    let matches = app().get_matches();
 
     let config = matches.value_of("config").unwrap();
     let mut f = File::open(config).unwrap();
@@ -56,10 +319,125 @@ async fn main() -> anyhow::Result<()> {
         config.smartlike_key.clone(),
         config.network_address.clone(),
     );
+    if matches.subcommand_matches("public-key").is_some() {
+        println!("{}", client.public_key());
+        return Ok(());
+    }
+
+    let history = web::Data::new(History::open(&config.history_path)?);
+    if let Some(m) = matches.subcommand_matches("history") {
+        let n = m
+            .value_of("last")
+            .unwrap()
+            .parse::<usize>()
+            .map_err(|e| anyhow::anyhow!("Invalid number of updates: {}", e))?;
+        return print_history(&history, m.value_of("currency"), n);
+    }
+    if let Some(m) = matches.subcommand_matches("submit") {
+        return submit(&client, &history, m.value_of("file").unwrap()).await;
+    }
+    if matches.subcommand_matches("serve").is_some() {
+        if config.server.listen_address.is_empty() {
+            return Err(anyhow::anyhow!("Server listen address is not configured"));
+        }
+        server::start(&config.server, client, history)?.await?;
+        return Ok(());
+    }
+
+    // Long running modes also serve published rates if configured.
+    let aggregate = matches.subcommand_matches("aggregate").is_some();
+    let _server = if (aggregate || matches.is_present("daemon"))
+        && !config.server.listen_address.is_empty()
+    {
+        Some(server::start(
+            &config.server,
+            client.clone(),
+            history.clone(),
+        )?)
+    } else {
+        None
+    };
+    if aggregate {
+        return run_aggregator(client, &config.aggregator, history).await;
+    }
+
+    config.currencies.validate()?;
+    let sources = config
+        .sources
+        .iter()
+        .map(rates::create)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if sources.is_empty() {
+        return Err(anyhow::anyhow!("No exchange rate sources configured"));
+    }
 
-    match fetch_exchange_rates(&client, &config).await {
-        Ok(_) => {}
-        Err(e) => error!("Error: {}", e),
+    if matches.is_present("dry-run") {
+        dry_run(
+            &client,
+            &config,
+            &sources,
+            &history,
+            matches.value_of("output").unwrap(),
+        )
+        .await?;
+    } else if matches.is_present("daemon") {
+        info!(
+            "Publishing rates every {} sec.",
+            config.daemon.interval_seconds
+        );
+        run_daemon(&client, &config, &sources, &history).await;
+    } else {
+        match fetch_exchange_rates(&client, &config, &sources, &history, &mut Jumps::default())
+            .await
+        {
+            Ok(_) => {}
+            Err(e) => error!("Error: {}", e),
+        }
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arguments() {
+        let matches = app()
+            .get_matches_from_safe(vec![
+                "smartlike-vote",
+                "-c",
+                "cfg/vote.toml",
+                "--dry-run",
+                "-o",
+                "update.json",
+            ])
+            .unwrap();
+        assert!(matches.is_present("dry-run"));
+        assert_eq!(matches.value_of("output"), Some("update.json"));
+
+        let matches = app()
+            .get_matches_from_safe(vec![
+                "smartlike-vote",
+                "-c",
+                "cfg/vote.toml",
+                "submit",
+                "update.json",
+            ])
+            .unwrap();
+        assert_eq!(
+            matches
+                .subcommand_matches("submit")
+                .and_then(|m| m.value_of("file")),
+            Some("update.json")
+        );
+
+        // Nowhere to save the transaction to, nothing to submit.
+        assert!(app()
+            .get_matches_from_safe(vec!["smartlike-vote", "--dry-run"])
+            .is_err());
+        assert!(app()
+            .get_matches_from_safe(vec!["smartlike-vote", "submit"])
+            .is_err());
+    }
+}
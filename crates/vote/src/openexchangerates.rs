@@ -1,8 +1,7 @@
-use crate::{Configuration};
-use std::collections::HashMap;
-use std::string::ToString;
-use smartlike_embed_lib::client::CurrencyExchangeRatesUpdate;
+use crate::rates::RateSource;
 use anyhow::anyhow;
+use smartlike_embed_lib::client::CurrencyExchangeRatesUpdate;
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Rates {
@@ -12,38 +11,35 @@ pub struct Rates {
     base: String,
     rates: HashMap<String, f64>,
 }
-pub async fn download(config: &Configuration) -> anyhow::Result<CurrencyExchangeRatesUpdate> {
-    trace!("Querying {}", config.currency_exchange_query);
-
-    let client = reqwest::Client::new();
-    let resp = client
-        .get(&config.currency_exchange_query)
-        .send()
-        .await
-        .map_err(|err| anyhow!("HTTP GET error: {}", err))?
-        .text()
-        .await
-        .map_err(|err| anyhow!("Failed to get request body: {}", err))?;
-
-    parse(&config.currency_exchange_source, &resp)
+
+pub struct OpenExchangeRates {
+    pub name: String,
+    pub query: String,
 }
 
-fn parse(source: &str, resp: &str) -> anyhow::Result<CurrencyExchangeRatesUpdate> {
-    let res: Result<Rates, String> = serde_json::from_str(&resp)
-        .map_err(|err| anyhow!("Parse error: {} {}", err, resp).to_string());
-    match res {
-        Ok(r) => {
-            return Ok(CurrencyExchangeRatesUpdate {
-                source: source.to_string(),
-                base: r.base,
-                ts: r.timestamp,
-                rates: r.rates,
-            });
-        }
-        Err(e) => {
-            return Err(anyhow!("{}", e));
-        }
+impl RateSource for OpenExchangeRates {
+    fn name(&self) -> &str {
+        &self.name
     }
+
+    fn query(&self) -> &str {
+        &self.query
+    }
+
+    fn parse(&self, resp: &str) -> anyhow::Result<CurrencyExchangeRatesUpdate> {
+        parse(&self.name, resp)
+    }
+}
+
+fn parse(source: &str, resp: &str) -> anyhow::Result<CurrencyExchangeRatesUpdate> {
+    let r: Rates =
+        serde_json::from_str(resp).map_err(|err| anyhow!("Parse error: {} {}", err, resp))?;
+    Ok(CurrencyExchangeRatesUpdate {
+        source: source.to_string(),
+        base: r.base,
+        ts: r.timestamp,
+        rates: r.rates,
+    })
 }
 
 #[cfg(test)]
@@ -53,9 +49,10 @@ mod tests {
     #[test]
     fn test_openexchange_parsing() {
         let resp = std::fs::read_to_string("./test/openexchangerates.json").unwrap();
-        assert_eq!(
-            parse("openexchangerates.org", &resp).is_ok(),
-            true
-        );
+        let update = parse("openexchangerates.org", &resp).unwrap();
+        assert_eq!(update.source, "openexchangerates.org");
+        assert_eq!(update.base, "USD");
+        assert_eq!(update.ts, 1611860400);
+        assert_eq!(update.rates["AED"], 3.672991);
     }
 }
@@ -0,0 +1,97 @@
+use anyhow::anyhow;
+use smartlike_embed_lib::client::{verify, Client, CurrencyExchangeRatesUpdate};
+
+const VOTE_TAG: &str = "smartlike-rates-vote:";
+
+/// Exchange rates proposed by an oracle operator.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Vote {
+    pub ts: u32,
+    pub update: CurrencyExchangeRatesUpdate,
+}
+
+/// Serialized vote signed with the oracle's Smartlike key.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedVote {
+    pub oracle: String,
+    pub data: String,
+    pub signature: String,
+}
+
+impl SignedVote {
+    pub fn create(client: &Client, vote: &Vote) -> anyhow::Result<SignedVote> {
+        let data = serde_json::to_string(vote)?;
+        Ok(SignedVote {
+            oracle: client.account.clone(),
+            signature: client.sign(&format!("{}{}", VOTE_TAG, data)),
+            data,
+        })
+    }
+
+    /// Checks the signature and returns the vote.
+    pub fn verify(&self, public_key: &str) -> anyhow::Result<Vote> {
+        verify(
+            public_key,
+            &format!("{}{}", VOTE_TAG, self.data),
+            &self.signature,
+        )
+        .map_err(|e| anyhow!("Vote from {} rejected: {}", self.oracle, e))?;
+        serde_json::from_str(&self.data).map_err(|e| anyhow!("Failed to parse vote: {}", e))
+    }
+}
+
+pub async fn send(address: &str, vote: &SignedVote) -> anyhow::Result<()> {
+    let resp = reqwest::Client::new()
+        .post(address)
+        .json(vote)
+        .send()
+        .await
+        .map_err(|err| anyhow!("Send error: {}", err))?;
+    if resp.status() == 200 {
+        Ok(())
+    } else {
+        let status = resp.status();
+        Err(anyhow!(
+            "Vote rejected: {} {}",
+            status,
+            resp.text().await.unwrap_or_default()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vote_signature() {
+        let oracle = Client::new("a".to_string(), "secret".to_string(), "".to_string());
+        let vote = Vote {
+            ts: 1000,
+            update: CurrencyExchangeRatesUpdate {
+                source: "consensus".to_string(),
+                base: "USD".to_string(),
+                ts: 900,
+                rates: vec![("EUR".to_string(), 0.82), ("GBP".to_string(), 0.73)]
+                    .into_iter()
+                    .collect(),
+            },
+        };
+        let signed = SignedVote::create(&oracle, &vote).unwrap();
+        assert_eq!(signed.oracle, "a");
+        let verified = signed.verify(&oracle.public_key()).unwrap();
+        assert_eq!(verified.update.rates["EUR"], 0.82);
+
+        let other = Client::new("b".to_string(), "other".to_string(), "".to_string());
+        assert!(signed.verify(&other.public_key()).is_err());
+
+        let mut tampered = signed.clone();
+        tampered.data = tampered.data.replace("0.82", "0.92");
+        assert!(tampered.verify(&oracle.public_key()).is_err());
+
+        // Plain signatures of the same data are not valid votes.
+        let mut untagged = signed;
+        untagged.signature = oracle.sign(&untagged.data);
+        assert!(untagged.verify(&oracle.public_key()).is_err());
+    }
+}
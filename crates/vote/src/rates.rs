@@ -0,0 +1,65 @@
+use crate::{ecb, jsonrates, openexchangerates};
+use anyhow::anyhow;
+use smartlike_embed_lib::client::CurrencyExchangeRatesUpdate;
+
+/// Exchange rate source parameters.
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct SourceConfiguration {
+    /// "openexchangerates.org", "ecb.europa.eu" or "json".
+    pub kind: String,
+    pub query: String,
+    /// Source name reported to the network, defaults to the kind.
+    #[serde(default)]
+    pub name: String,
+    /// JSON pointers for the generic JSON source.
+    #[serde(default)]
+    pub rates_pointer: String,
+    #[serde(default)]
+    pub base_pointer: String,
+    #[serde(default)]
+    pub timestamp_pointer: String,
+    /// Fixed base currency for feeds that don't report it.
+    #[serde(default)]
+    pub base: String,
+}
+
+pub trait RateSource: Send + Sync {
+    fn name(&self) -> &str;
+    fn query(&self) -> &str;
+    fn parse(&self, resp: &str) -> anyhow::Result<CurrencyExchangeRatesUpdate>;
+}
+
+pub fn create(config: &SourceConfiguration) -> anyhow::Result<Box<dyn RateSource>> {
+    let name = match config.name.is_empty() {
+        true => config.kind.clone(),
+        false => config.name.clone(),
+    };
+    match config.kind.as_str() {
+        "openexchangerates.org" => Ok(Box::new(openexchangerates::OpenExchangeRates {
+            name,
+            query: config.query.clone(),
+        })),
+        "ecb.europa.eu" => Ok(Box::new(ecb::Ecb {
+            name,
+            query: config.query.clone(),
+        })),
+        "json" => Ok(Box::new(jsonrates::JsonRates::create(name, config)?)),
+        _ => Err(anyhow!("Unknown exchange rate source: {}", config.kind)),
+    }
+}
+
+pub async fn download(source: &dyn RateSource) -> anyhow::Result<CurrencyExchangeRatesUpdate> {
+    trace!("Querying {}", source.query());
+
+    let client = reqwest::Client::new();
+    let resp = client
+        .get(source.query())
+        .send()
+        .await
+        .map_err(|err| anyhow!("HTTP GET error: {}", err))?
+        .text()
+        .await
+        .map_err(|err| anyhow!("Failed to get request body: {}", err))?;
+
+    source.parse(&resp)
+}
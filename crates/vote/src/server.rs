@@ -0,0 +1,171 @@
+use crate::history::History;
+use actix_web::{web, App, HttpResponse, HttpServer};
+use serde::Serialize;
+use smartlike_embed_lib::client::Client;
+use smartlike_embed_lib::currency::SignedRates;
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+pub struct ServerConfiguration {
+    /// Rates are served only when set.
+    pub listen_address: String,
+    /// Maximum number of history entries in a response.
+    pub max_history: usize,
+}
+
+pub struct Context {
+    pub client: Client,
+    pub max_history: usize,
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    pub n: Option<usize>,
+}
+
+fn signed<T: Serialize>(context: &Context, value: &T) -> HttpResponse {
+    match SignedRates::create(&context.client, value) {
+        Ok(signed) => HttpResponse::Ok().json(signed),
+        Err(e) => {
+            error!("{}", e);
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Last published rate of every currency, currencies left out of later
+/// updates keep their earlier rates.
+pub async fn latest_handler(
+    context: web::Data<Context>,
+    history: web::Data<History>,
+) -> HttpResponse {
+    match history.published() {
+        Some(update) => signed(&context, &update),
+        None => HttpResponse::NotFound()
+            .content_type("text/plain")
+            .body("No rates published"),
+    }
+}
+
+/// Latest published updates.
+pub async fn history_handler(
+    query: web::Query<HistoryQuery>,
+    context: web::Data<Context>,
+    history: web::Data<History>,
+) -> HttpResponse {
+    let n = query
+        .n
+        .unwrap_or(context.max_history)
+        .min(context.max_history);
+    signed(&context, &history.updates(n))
+}
+
+/// Latest published rates of a currency.
+pub async fn currency_history_handler(
+    currency: web::Path<String>,
+    query: web::Query<HistoryQuery>,
+    context: web::Data<Context>,
+    history: web::Data<History>,
+) -> HttpResponse {
+    let n = query
+        .n
+        .unwrap_or(context.max_history)
+        .min(context.max_history);
+    signed(&context, &history.rates(&currency.to_uppercase(), n))
+}
+
+fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/rates").route(web::get().to(latest_handler)))
+        .service(web::resource("/rates/history").route(web::get().to(history_handler)))
+        .service(
+            web::resource("/rates/history/{currency}")
+                .route(web::get().to(currency_history_handler)),
+        );
+}
+
+/// Starts serving rates in the background.
+pub fn start(
+    config: &ServerConfiguration,
+    client: Client,
+    history: web::Data<History>,
+) -> anyhow::Result<actix_web::dev::Server> {
+    let context = web::Data::new(Context {
+        client,
+        max_history: config.max_history,
+    });
+    info!("Serving rates at {}", config.listen_address);
+    Ok(HttpServer::new(move || {
+        App::new()
+            .app_data(context.clone())
+            .app_data(history.clone())
+            .configure(configure)
+    })
+    .bind(&config.listen_address)?
+    .run())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{RatePoint, Record};
+    use actix_web::test;
+    use smartlike_embed_lib::client::CurrencyExchangeRatesUpdate;
+    use smartlike_like_queue::testing::TempDir;
+
+    #[actix_rt::test]
+    async fn test_rates_server() {
+        let dir = TempDir::new("smartlike-vote-test-server");
+        let history = web::Data::new(History::open(dir.path()).unwrap());
+        let operator = Client::new("a".to_string(), "secret".to_string(), "".to_string());
+        let context = web::Data::new(Context {
+            client: operator.clone(),
+            max_history: 2,
+        });
+        let mut app = test::init_service(
+            App::new()
+                .app_data(context)
+                .app_data(history.clone())
+                .configure(configure),
+        )
+        .await;
+
+        let req = test::TestRequest::get().uri("/rates").to_request();
+        assert_eq!(test::call_service(&mut app, req).await.status(), 404);
+
+        let updates: [(u32, &[(&str, f64)]); 3] = [
+            (900, &[("EUR", 0.81), ("GBP", 0.73)]),
+            (1000, &[("EUR", 0.82)]),
+            (1100, &[("EUR", 0.83)]),
+        ];
+        for (published, rates) in &updates {
+            let update = CurrencyExchangeRatesUpdate {
+                source: "consensus".to_string(),
+                base: "USD".to_string(),
+                ts: *published,
+                rates: rates.iter().map(|(c, r)| (c.to_string(), *r)).collect(),
+            };
+            history.add(*published, &update).unwrap();
+        }
+
+        let req = test::TestRequest::get().uri("/rates").to_request();
+        let signed: SignedRates = test::read_response_json(&mut app, req).await;
+        let update: CurrencyExchangeRatesUpdate = signed.verify(&operator.public_key()).unwrap();
+        assert_eq!(update.rates["EUR"], 0.83);
+        // Left out of later updates.
+        assert_eq!(update.rates["GBP"], 0.73);
+
+        let req = test::TestRequest::get()
+            .uri("/rates/history?n=10")
+            .to_request();
+        let signed: SignedRates = test::read_response_json(&mut app, req).await;
+        let records: Vec<Record> = signed.verify(&operator.public_key()).unwrap();
+        assert_eq!(records.len(), 2);
+
+        let req = test::TestRequest::get()
+            .uri("/rates/history/eur?n=1")
+            .to_request();
+        let signed: SignedRates = test::read_response_json(&mut app, req).await;
+        let points: Vec<RatePoint> = signed.verify(&operator.public_key()).unwrap();
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[0].rate, 0.83);
+    }
+}
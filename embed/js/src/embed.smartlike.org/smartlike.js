@@ -155,7 +155,7 @@ let profile = null;
 function checkParameters() {
     if (pars.has("type")) {
         const required = {
-            "login": ["title", "token", "callback"],
+            "login": ["title", "challenge", "callback"],
             "subscribe": ["title", "token", "recipient", "callback"],
             "smartlike": ["title", "recipient", "callback"],
             "donate": ["title", "recipient", "callback"]
@@ -194,14 +194,22 @@ function submit() {
 function checkoutImpl(password) {
 
     if (pars.get("type") == "login") {
-        let [publicKey, signature] = signHex(pars.get("token"), password)
+        // Only tagged challenge responses are signed, never arbitrary tokens.
+        const challenge = JSON.parse(pars.get("challenge"))
+        const publicKey = publicKeyHex(password)
+        const response = CHALLENGE_RESPONSE_TAG + JSON.stringify({
+            challenge: challenge,
+            account: publicKey,
+            public_key: publicKey
+        })
+        let [, signature] = signHex(response, password)
         callbackImpl({
             type: "checkout",
             data: {
                 state: "ok",
                 type: pars.get("type"),
                 publicKey: publicKey,
-                token: pars.get("token"),
+                challenge: challenge,
                 signature: signature
             }
         })
@@ -391,6 +399,7 @@ function createMutationObserver() {
 }
 
 const NETWORK = "https://smartlike.org/network"
+const CHALLENGE_RESPONSE_TAG = "smartlike-challenge-response:"
 
 function arr2hex(buffer) {
     return [...new Uint8Array(buffer)]
@@ -398,9 +407,17 @@ function arr2hex(buffer) {
         .join("")
 }
 
-function signHex(message, secret) {
+function keyPair(secret) {
     var seed = blakejs.blake2b(new TextEncoder().encode(secret), undefined).slice(0, 32)
-    var keys = nacl.sign.keyPair.fromSeed(seed)
+    return nacl.sign.keyPair.fromSeed(seed)
+}
+
+function publicKeyHex(secret) {
+    return arr2hex(keyPair(secret).publicKey)
+}
+
+function signHex(message, secret) {
+    var keys = keyPair(secret)
     var sig = nacl.sign(new TextEncoder().encode(message), keys.secretKey)
     return [arr2hex(keys.publicKey), arr2hex(sig.subarray(0, nacl.sign.signatureLength))]
 }
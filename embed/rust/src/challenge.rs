@@ -0,0 +1,239 @@
+//! Challenge-response authentication.
+//!
+//! A challenge is issued by a service (e.g. a payment gateway) and signed by
+//! the user to prove control over a Smartlike key. Signed strings are prefixed
+//! with a tag so that neither side can be tricked into signing a transaction
+//! or any other message.
+
+use crate::client::{verify, Client, SignedTransaction};
+use rand::Rng;
+
+const CHALLENGE_TAG: &str = "smartlike-challenge:";
+const CHALLENGE_RESPONSE_TAG: &str = "smartlike-challenge-response:";
+
+/// Challenge parameters.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Challenge {
+    pub issuer: String,
+    pub scope: String,
+    pub domain: String,
+    pub nonce: String,
+    pub ts: u32,
+    pub expires: u32,
+}
+
+/// Challenge signed by its issuer.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedChallenge {
+    pub challenge: Challenge,
+    pub signature: String,
+}
+
+/// Challenge signed by a user.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChallengeResponse {
+    pub challenge: Challenge,
+    /// Account the user logs in to.
+    pub account: String,
+    /// Key that signed the response. Accounts and keys are different
+    /// strings, the key must be one registered for the account.
+    pub public_key: String,
+    pub signature: String,
+}
+
+/// Issuer's statement that a user proved control over a channel by posting
+/// its challenge there.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OwnershipAttestation {
+    pub platform: String,
+    /// Platform id of the user.
+    pub id: String,
+    /// Channel URL.
+    pub target: String,
+    pub challenge: SignedChallenge,
+}
+
+impl Challenge {
+    pub fn new(issuer: &str, scope: &str, domain: &str, ts: u32, ttl: u32) -> Challenge {
+        Challenge {
+            issuer: issuer.to_string(),
+            scope: scope.to_string(),
+            domain: domain.to_string(),
+            nonce: hex::encode(rand::thread_rng().gen::<[u8; 16]>()),
+            ts,
+            expires: ts + ttl,
+        }
+    }
+
+    /// String signed by the issuer.
+    pub fn message(&self) -> anyhow::Result<String> {
+        let serialized = serde_json::to_string(&self)
+            .map_err(|err| anyhow::anyhow!("Failed to serialize challenge: {}", err))?;
+        Ok(format!("{}{}", CHALLENGE_TAG, serialized))
+    }
+
+    pub fn is_expired(&self, now: u32) -> bool {
+        now >= self.expires
+    }
+}
+
+impl SignedChallenge {
+    pub fn verify(&self, issuer_public_key: &str) -> anyhow::Result<()> {
+        verify(
+            issuer_public_key,
+            &self.challenge.message()?,
+            &self.signature,
+        )
+    }
+}
+
+impl ChallengeResponse {
+    /// String signed by the user.
+    pub fn message(
+        challenge: &Challenge,
+        account: &str,
+        public_key: &str,
+    ) -> anyhow::Result<String> {
+        let serialized = serde_json::to_string(&json!({
+            "challenge": challenge,
+            "account": account,
+            "public_key": public_key,
+        }))
+        .map_err(|err| anyhow::anyhow!("Failed to serialize challenge response: {}", err))?;
+        Ok(format!("{}{}", CHALLENGE_RESPONSE_TAG, serialized))
+    }
+
+    /// Checks expiration and the user's signature of the challenge, the
+    /// account and the key. It proves control over `public_key` only: whether
+    /// the key is registered for `account` is checked with the network, like
+    /// issuer records, e.g. whether the nonce is still pending, by the caller.
+    pub fn verify(&self, now: u32) -> anyhow::Result<()> {
+        if self.challenge.is_expired(now) {
+            return Err(anyhow::anyhow!("Challenge expired"));
+        }
+        verify(
+            &self.public_key,
+            &ChallengeResponse::message(&self.challenge, &self.account, &self.public_key)?,
+            &self.signature,
+        )
+    }
+}
+
+impl Client {
+    pub fn issue_challenge(&self, challenge: Challenge) -> anyhow::Result<SignedChallenge> {
+        let signature = self.sign(&challenge.message()?);
+        Ok(SignedChallenge {
+            challenge,
+            signature,
+        })
+    }
+
+    pub fn sign_ownership_attestation(
+        &self,
+        attestation: &OwnershipAttestation,
+    ) -> anyhow::Result<SignedTransaction> {
+        let parameters = serde_json::to_string(attestation)
+            .map_err(|err| anyhow::anyhow!("Failed to serialize attestation: {}", err))?;
+        self.sign_transaction("attest_ownership", &parameters)
+    }
+
+    pub async fn attest_ownership(
+        &self,
+        attestation: &OwnershipAttestation,
+    ) -> anyhow::Result<String> {
+        let tx = self.sign_ownership_attestation(attestation)?;
+        self.send_transaction(&tx, None).await
+    }
+
+    pub fn respond_to_challenge(&self, challenge: &Challenge) -> anyhow::Result<ChallengeResponse> {
+        Ok(ChallengeResponse {
+            challenge: challenge.clone(),
+            account: self.account.clone(),
+            public_key: self.public_key(),
+            signature: self.sign(&ChallengeResponse::message(
+                challenge,
+                &self.account,
+                &self.public_key(),
+            )?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(account: &str, secret: &str) -> Client {
+        Client::new(account.to_string(), secret.to_string(), "".to_string())
+    }
+
+    #[test]
+    fn test_challenge_response() {
+        let gateway = client("gateway", "gateway secret");
+        let user = client("a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7", "user secret");
+
+        let challenge = Challenge::new("gateway", "login", "example.com", 1000, 60);
+        let signed = gateway.issue_challenge(challenge.clone()).unwrap();
+        assert!(signed.verify(&gateway.public_key()).is_ok());
+        assert!(signed.verify(&user.public_key()).is_err());
+
+        let response = user.respond_to_challenge(&signed.challenge).unwrap();
+        assert_eq!(response.account, "a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7");
+        assert_eq!(response.public_key, user.public_key());
+        assert!(response.verify(1059).is_ok());
+        assert_eq!(
+            response.verify(1060).err().unwrap().to_string(),
+            "Challenge expired"
+        );
+
+        // Tampered challenge.
+        let mut tampered = response.clone();
+        tampered.challenge.scope = "payment".to_string();
+        assert!(tampered.verify(1000).is_err());
+
+        // Claimed account is covered by the signature.
+        let mut tampered = response.clone();
+        tampered.account = "b0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7".to_string();
+        assert!(tampered.verify(1000).is_err());
+
+        // Another key claiming the account proves control over that key only,
+        // which isn't registered for the account.
+        let attacker = client("a0a1a2a3-b0b1-c0c1-d0d1-d2d3d4d5d6d7", "attacker secret");
+        let claimed = attacker.respond_to_challenge(&signed.challenge).unwrap();
+        assert!(claimed.verify(1000).is_ok());
+        assert_ne!(claimed.public_key, user.public_key());
+        let mut swapped = claimed;
+        swapped.public_key = user.public_key();
+        assert!(swapped.verify(1000).is_err());
+
+        // Issuer and response signatures are not interchangeable.
+        let mut forged = response;
+        forged.public_key = gateway.public_key();
+        forged.signature = signed.signature;
+        assert!(forged.verify(1000).is_err());
+    }
+
+    #[test]
+    fn test_ownership_attestation() {
+        let bot = client("bot", "bot secret");
+        let challenge = Challenge::new("bot", "telegram-channel", "https://t.me/a", 1000, 60);
+        let attestation = OwnershipAttestation {
+            platform: "telegram".to_string(),
+            id: "7".to_string(),
+            target: "https://t.me/a".to_string(),
+            challenge: bot.issue_challenge(challenge).unwrap(),
+        };
+        let tx = bot.sign_ownership_attestation(&attestation).unwrap();
+        assert_eq!(tx.method, "attest_ownership");
+        assert!(tx.verify(&bot.public_key()).is_ok());
+
+        let parsed = tx.parameters::<OwnershipAttestation>().unwrap();
+        assert_eq!(parsed.target, "https://t.me/a");
+        assert_eq!(parsed.challenge.challenge, attestation.challenge.challenge);
+        assert!(parsed.challenge.verify(&bot.public_key()).is_ok());
+    }
+}
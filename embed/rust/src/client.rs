@@ -1,5 +1,5 @@
 use blake2::{Blake2b, Digest};
-use ed25519_dalek::{ExpandedSecretKey, Keypair, PublicKey, SecretKey};
+use ed25519_dalek::{ExpandedSecretKey, Keypair, PublicKey, SecretKey, Signature};
 use rand::Rng;
 use std::collections::HashMap;
 use std::string::ToString;
@@ -54,6 +54,24 @@ pub struct Like {
     pub target: String,
     pub amount: f64,
     pub currency: String,
+    /// Smartlike account of the liker, sent by bridges to platforms where
+    /// users link their account in a signed profile rather than confirm it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account: Option<String>,
+    /// Smartlike account of the author of the target, sent by bridges that
+    /// resolve it the same way.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipient: Option<String>,
+}
+
+/// Signed RPC call.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedTransaction {
+    pub method: String,
+    pub sender: String,
+    pub signature: String,
+    pub data: String,
 }
 
 /// A specialized Response type for Smartlike RPC.
@@ -112,9 +130,8 @@ impl Client {
         &self,
         update: &CurrencyExchangeRatesUpdate,
     ) -> anyhow::Result<String> {
-        let parameters = serde_json::to_string(&update)
-            .map_err(|err| anyhow::anyhow!("Failed to serialize message: {}", err.to_string()))?;
-        self.rpc("update_exchange_rates", &parameters, None).await
+        let tx = self.sign_exchange_rates_update(update)?;
+        self.send_transaction(&tx, None).await
     }
 
     pub async fn relay_apub(&self, receipt: &ApubMessage) -> anyhow::Result<String> {
@@ -129,6 +146,13 @@ impl Client {
         self.rpc("forward_like", &parameters, None).await
     }
 
+    /// Takes back a like sent with `forward_like`.
+    pub async fn retract_like(&self, like: &Like) -> anyhow::Result<String> {
+        let parameters = serde_json::to_string(&like)
+            .map_err(|err| anyhow::anyhow!("Failed to serialize message: {}", err))?;
+        self.rpc("retract_like", &parameters, None).await
+    }
+
     pub fn sign(&self, message: &str) -> String {
         let expanded: ExpandedSecretKey = (&self.keys.secret).into();
         let sig = expanded.sign(message.as_bytes(), &self.keys.public);
@@ -140,7 +164,16 @@ impl Client {
         strs.join("")
     }
 
-    async fn rpc(&self, method: &str, parameters: &str, id: Option<u64>) -> anyhow::Result<String> {
+    pub fn public_key(&self) -> String {
+        hex::encode(self.keys.public.as_bytes())
+    }
+
+    /// Signs an RPC call without sending it, e.g. to review it first.
+    pub fn sign_transaction(
+        &self,
+        method: &str,
+        parameters: &str,
+    ) -> anyhow::Result<SignedTransaction> {
         let now = SystemTime::now();
         let ts: i32 = now
             .duration_since(UNIX_EPOCH)
@@ -153,7 +186,28 @@ impl Client {
         });
         let msg = serde_json::to_string(&tx)
             .map_err(|err| anyhow::anyhow!("Failed to serialize message: {}", err.to_string()))?;
+        Ok(SignedTransaction {
+            method: method.to_string(),
+            sender: self.account.clone(),
+            signature: self.sign(&msg),
+            data: msg,
+        })
+    }
 
+    pub fn sign_exchange_rates_update(
+        &self,
+        update: &CurrencyExchangeRatesUpdate,
+    ) -> anyhow::Result<SignedTransaction> {
+        let parameters = serde_json::to_string(&update)
+            .map_err(|err| anyhow::anyhow!("Failed to serialize message: {}", err))?;
+        self.sign_transaction("update_exchange_rates", &parameters)
+    }
+
+    pub async fn send_transaction(
+        &self,
+        tx: &SignedTransaction,
+        id: Option<u64>,
+    ) -> anyhow::Result<String> {
         let rpc_id = match id {
             Some(v) => v,
             None => rand::thread_rng().gen::<u64>(),
@@ -161,13 +215,13 @@ impl Client {
 
         let body = json!({
         "jsonrpc": "2.0",
-        "method": method,
+        "method": tx.method,
         "id": rpc_id,
         "params": {
             "signed_message": {
-                "sender": self.account,
-                "signature": self.sign(&msg),
-                "data": msg,
+                "sender": tx.sender,
+                "signature": tx.signature,
+                "data": tx.data,
               },
             }
         });
@@ -195,4 +249,87 @@ impl Client {
             Err(anyhow::anyhow!("HTTP response code: {}", resp.status()))
         }
     }
+
+    async fn rpc(&self, method: &str, parameters: &str, id: Option<u64>) -> anyhow::Result<String> {
+        let tx = self.sign_transaction(method, parameters)?;
+        self.send_transaction(&tx, id).await
+    }
+}
+
+impl SignedTransaction {
+    /// Checks the sender's signature.
+    pub fn verify(&self, public_key: &str) -> anyhow::Result<()> {
+        verify(public_key, &self.data, &self.signature)
+    }
+
+    /// Signing time.
+    pub fn ts(&self) -> anyhow::Result<i64> {
+        self.field("ts")?
+            .as_i64()
+            .ok_or(anyhow::anyhow!("Invalid transaction timestamp"))
+    }
+
+    /// Decodes parameters of the call.
+    pub fn parameters<T: serde::de::DeserializeOwned>(&self) -> anyhow::Result<T> {
+        let parameters = self.field("data")?;
+        serde_json::from_str(
+            parameters
+                .as_str()
+                .ok_or(anyhow::anyhow!("Invalid transaction data"))?,
+        )
+        .map_err(|err| anyhow::anyhow!("Failed to parse parameters: {}", err))
+    }
+
+    fn field(&self, name: &str) -> anyhow::Result<serde_json::Value> {
+        let mut tx: serde_json::Value = serde_json::from_str(&self.data)
+            .map_err(|err| anyhow::anyhow!("Failed to parse transaction: {}", err))?;
+        Ok(tx[name].take())
+    }
+}
+
+/// Checks a hex encoded signature produced by `Client::sign`.
+///
+pub fn verify(public_key: &str, message: &str, signature: &str) -> anyhow::Result<()> {
+    let public_key = hex::decode(public_key)
+        .map_err(|err| anyhow::anyhow!("Failed to decode public key: {}", err))?;
+    let public_key = PublicKey::from_bytes(&public_key)
+        .map_err(|err| anyhow::anyhow!("Invalid public key: {}", err))?;
+    let signature = hex::decode(signature)
+        .map_err(|err| anyhow::anyhow!("Failed to decode signature: {}", err))?;
+    let signature = Signature::from_bytes(&signature)
+        .map_err(|err| anyhow::anyhow!("Invalid signature: {}", err))?;
+    public_key
+        .verify(message.as_bytes(), &signature)
+        .map_err(|_| anyhow::anyhow!("Signature verification failed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signed_transaction() {
+        let client = Client::new("a".to_string(), "secret".to_string(), "".to_string());
+        let update = CurrencyExchangeRatesUpdate {
+            source: "consensus".to_string(),
+            base: "USD".to_string(),
+            ts: 1000,
+            rates: vec![("EUR".to_string(), 0.82)].into_iter().collect(),
+        };
+        let tx = client.sign_exchange_rates_update(&update).unwrap();
+        assert_eq!(tx.method, "update_exchange_rates");
+        assert_eq!(tx.sender, "a");
+        tx.verify(&client.public_key()).unwrap();
+        assert!(tx.ts().unwrap() > 0);
+
+        // Transactions survive a round trip through a file.
+        let tx: SignedTransaction =
+            serde_json::from_str(&serde_json::to_string(&tx).unwrap()).unwrap();
+        let parameters: CurrencyExchangeRatesUpdate = tx.parameters().unwrap();
+        assert_eq!(parameters.rates["EUR"], 0.82);
+
+        let mut tampered = tx;
+        tampered.data = tampered.data.replace("0.82", "0.92");
+        assert!(tampered.verify(&client.public_key()).is_err());
+    }
 }
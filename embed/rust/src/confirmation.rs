@@ -0,0 +1,241 @@
+//! Account confirmation links.
+//!
+//! A proxy, e.g. a chat bot liking on behalf of its users, asks a user to
+//! confirm that likes of their platform id are paid from their Smartlike
+//! account. The confirmation is signed by the proxy as a tagged JSON string,
+//! expires and carries a nonce so that a leaked link can't be reused for long.
+//! The amount is signed and linked with a fixed number of decimals, so a
+//! verifier in another language, e.g. JavaScript printing 1.0 as "1", builds
+//! the same string.
+
+use crate::client::{verify, Client};
+use anyhow::anyhow;
+use rand::Rng;
+use std::collections::HashMap;
+
+const CONFIRMATION_TAG: &str = "smartlike-confirmation:";
+
+const AMOUNT_DECIMALS: usize = 6;
+
+/// Page that confirms the link.
+pub const CONFIRM_URL: &str = "https://smartlike.org/confirm";
+
+/// Confirmation parameters.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Confirmation {
+    pub platform: String,
+    pub id: String,
+    pub name: String,
+    pub account: String,
+    pub amount: f64,
+    pub proxy: String,
+    pub nonce: String,
+    pub ts: u32,
+    pub expires: u32,
+}
+
+/// Confirmation signed by the proxy.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedConfirmation {
+    pub confirmation: Confirmation,
+    pub signature: String,
+}
+
+impl Confirmation {
+    pub fn new(
+        platform: &str,
+        id: &str,
+        account: &str,
+        amount: f64,
+        ts: u32,
+        ttl: u32,
+    ) -> Confirmation {
+        Confirmation {
+            platform: platform.to_string(),
+            id: id.to_string(),
+            name: String::new(),
+            account: account.to_string(),
+            amount,
+            proxy: String::new(),
+            nonce: hex::encode(rand::thread_rng().gen::<[u8; 16]>()),
+            ts,
+            expires: ts.saturating_add(ttl),
+        }
+    }
+
+    /// Name of the user shown on the confirmation page.
+    pub fn name(mut self, name: &str) -> Confirmation {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Amount as it's signed and linked, e.g. "1.000000".
+    pub fn formatted_amount(&self) -> String {
+        format!("{:.*}", AMOUNT_DECIMALS, self.amount)
+    }
+
+    /// String signed by the proxy.
+    pub fn message(&self) -> anyhow::Result<String> {
+        let mut value = serde_json::to_value(self)
+            .map_err(|err| anyhow!("Failed to serialize confirmation: {}", err))?;
+        value["amount"] = json!(self.formatted_amount());
+        Ok(format!("{}{}", CONFIRMATION_TAG, value))
+    }
+
+    pub fn is_expired(&self, now: u32) -> bool {
+        now >= self.expires
+    }
+}
+
+impl SignedConfirmation {
+    /// Link to the confirmation page with URL-encoded parameters.
+    pub fn url(&self, base: &str) -> anyhow::Result<String> {
+        let c = &self.confirmation;
+        let url = url::Url::parse_with_params(
+            base,
+            &[
+                ("platform", c.platform.clone()),
+                ("id", c.id.clone()),
+                ("name", c.name.clone()),
+                ("account", c.account.clone()),
+                ("amount", c.formatted_amount()),
+                ("proxy", c.proxy.clone()),
+                ("nonce", c.nonce.clone()),
+                ("ts", c.ts.to_string()),
+                ("expires", c.expires.to_string()),
+                ("signature", self.signature.clone()),
+            ],
+        )
+        .map_err(|err| anyhow!("Invalid confirmation URL {}: {}", base, err))?;
+        Ok(url.to_string())
+    }
+
+    /// Parses a link built by `url`.
+    pub fn from_url(url: &str) -> anyhow::Result<SignedConfirmation> {
+        let url = url::Url::parse(url).map_err(|err| anyhow!("Invalid URL: {}", err))?;
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        let param = |name: &str| {
+            params
+                .get(name)
+                .cloned()
+                .ok_or_else(|| anyhow!("Missing {}", name))
+        };
+        let number = |name: &str| {
+            param(name)?
+                .parse::<u32>()
+                .map_err(|err| anyhow!("Invalid {}: {}", name, err))
+        };
+        Ok(SignedConfirmation {
+            confirmation: Confirmation {
+                platform: param("platform")?,
+                id: param("id")?,
+                name: param("name")?,
+                account: param("account")?,
+                amount: param("amount")?
+                    .parse::<f64>()
+                    .map_err(|err| anyhow!("Invalid amount: {}", err))?,
+                proxy: param("proxy")?,
+                nonce: param("nonce")?,
+                ts: number("ts")?,
+                expires: number("expires")?,
+            },
+            signature: param("signature")?,
+        })
+    }
+
+    /// Checks expiration and the proxy's signature. Whether the nonce was
+    /// already used is the caller's responsibility.
+    pub fn verify(&self, proxy_public_key: &str, now: u32) -> anyhow::Result<()> {
+        if self.confirmation.is_expired(now) {
+            return Err(anyhow!("Confirmation expired"));
+        }
+        verify(
+            proxy_public_key,
+            &self.confirmation.message()?,
+            &self.signature,
+        )
+    }
+}
+
+impl Client {
+    /// Signs the confirmation with this account as the proxy.
+    pub fn sign_confirmation(
+        &self,
+        mut confirmation: Confirmation,
+    ) -> anyhow::Result<SignedConfirmation> {
+        confirmation.proxy = self.account.clone();
+        let signature = self.sign(&confirmation.message()?);
+        Ok(SignedConfirmation {
+            confirmation,
+            signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confirmation_link() {
+        let proxy = Client::new(
+            "proxy".to_string(),
+            "proxy secret".to_string(),
+            "".to_string(),
+        );
+        let confirmation = Confirmation::new("telegram", "7", "account", 0.5, 1000, 600)
+            .name("Alice & Bob #1 (@ab)");
+        let signed = proxy.sign_confirmation(confirmation.clone()).unwrap();
+        assert_eq!(signed.confirmation.proxy, "proxy");
+
+        let url = signed.url(CONFIRM_URL).unwrap();
+        assert!(url.starts_with("https://smartlike.org/confirm?platform=telegram&id=7&name=Alice+%26+Bob+%231+%28%40ab%29&account=account&amount=0.500000&proxy=proxy&"));
+
+        let parsed = SignedConfirmation::from_url(&url).unwrap();
+        assert_eq!(parsed.confirmation, signed.confirmation);
+        assert!(parsed.verify(&proxy.public_key(), 1599).is_ok());
+        assert_eq!(
+            parsed
+                .verify(&proxy.public_key(), 1600)
+                .err()
+                .unwrap()
+                .to_string(),
+            "Confirmation expired"
+        );
+
+        // Tampered parameters.
+        let tampered = url.replace("amount=0.500000", "amount=50");
+        let parsed = SignedConfirmation::from_url(&tampered).unwrap();
+        assert!(parsed.verify(&proxy.public_key(), 1000).is_err());
+        let tampered = url.replace("expires=1600", "expires=9600");
+        let parsed = SignedConfirmation::from_url(&tampered).unwrap();
+        assert!(parsed.verify(&proxy.public_key(), 1000).is_err());
+
+        // Other signers.
+        let other = Client::new("other".to_string(), "other".to_string(), "".to_string());
+        assert!(signed.verify(&other.public_key(), 1000).is_err());
+
+        assert!(SignedConfirmation::from_url("https://smartlike.org/confirm?id=7").is_err());
+
+        // Whole amounts are signed the same way in any language.
+        let whole = Confirmation::new("telegram", "7", "account", 1.0, 1000, 600);
+        assert!(whole.message().unwrap().contains(r#""amount":"1.000000""#));
+        let url = proxy
+            .sign_confirmation(whole)
+            .unwrap()
+            .url(CONFIRM_URL)
+            .unwrap();
+        let parsed = SignedConfirmation::from_url(&url.replace("amount=1.000000", "amount=1"));
+        assert!(parsed.unwrap().verify(&proxy.public_key(), 1000).is_ok());
+
+        // Expiry of long lived links doesn't overflow.
+        let long = Confirmation::new("telegram", "7", "account", 0.5, 1000, u32::MAX);
+        assert_eq!(long.expires, u32::MAX);
+
+        // Every link has its own nonce.
+        let again = Confirmation::new("telegram", "7", "account", 0.5, 1000, 600);
+        assert_ne!(confirmation.nonce, again.nonce);
+    }
+}
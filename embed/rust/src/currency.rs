@@ -0,0 +1,242 @@
+//! Currency conversion.
+//!
+//! Amounts are converted through the base currency of a published
+//! `CurrencyExchangeRatesUpdate` and rounded to the minor units of the target
+//! currency. Rate services sign their responses with `SignedRates`.
+
+use crate::client::{verify, Client, CurrencyExchangeRatesUpdate};
+use anyhow::anyhow;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+
+const RATES_TAG: &str = "smartlike-rates:";
+
+/// Number of digits after the decimal separator (ISO 4217 minor units).
+pub fn minor_units(currency: &str) -> i32 {
+    match currency {
+        "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX"
+        | "UYI" | "VND" | "VUV" | "XAF" | "XOF" | "XPF" => 0,
+        "BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND" => 3,
+        "CLF" | "UYW" => 4,
+        _ => 2,
+    }
+}
+
+/// Rounds the amount to the minor units of the currency.
+pub fn round(amount: f64, currency: &str) -> f64 {
+    let scale = 10f64.powi(minor_units(currency));
+    (amount * scale).round() / scale
+}
+
+/// Validated exchange rates.
+///
+#[derive(Debug, Clone)]
+pub struct RateTable {
+    base: String,
+    ts: u32,
+    rates: HashMap<String, f64>,
+}
+
+impl RateTable {
+    /// Creates a table from an update that is at most `max_age` seconds old.
+    pub fn new(
+        update: &CurrencyExchangeRatesUpdate,
+        now: u32,
+        max_age: u32,
+    ) -> anyhow::Result<RateTable> {
+        let mut rates = HashMap::new();
+        for (currency, rate) in &update.rates {
+            if !rate.is_finite() || *rate <= 0.0 {
+                return Err(anyhow!("Invalid rate for {}: {}", currency, rate));
+            }
+            rates.insert(currency.clone(), *rate);
+        }
+        rates.insert(update.base.clone(), 1.0);
+
+        let table = RateTable {
+            base: update.base.clone(),
+            ts: update.ts,
+            rates,
+        };
+        if table.is_stale(now, max_age) {
+            return Err(anyhow!(
+                "Exchange rates are {} sec old, {} sec allowed",
+                table.age(now),
+                max_age
+            ));
+        }
+        Ok(table)
+    }
+
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    pub fn ts(&self) -> u32 {
+        self.ts
+    }
+
+    /// Seconds since the rates were published.
+    pub fn age(&self, now: u32) -> u32 {
+        now.saturating_sub(self.ts)
+    }
+
+    pub fn is_stale(&self, now: u32, max_age: u32) -> bool {
+        self.age(now) > max_age
+    }
+
+    pub fn contains(&self, currency: &str) -> bool {
+        self.rates.contains_key(currency)
+    }
+
+    /// Price of one unit of `from` in `to`.
+    pub fn rate(&self, from: &str, to: &str) -> anyhow::Result<f64> {
+        let get = |currency: &str| {
+            self.rates
+                .get(currency)
+                .ok_or(anyhow!("No exchange rate for {}", currency))
+        };
+        Ok(get(to)? / get(from)?)
+    }
+
+    pub fn convert(&self, amount: f64, from: &str, to: &str) -> anyhow::Result<f64> {
+        Ok(amount * self.rate(from, to)?)
+    }
+
+    /// Converts and rounds the result to the minor units of `to`.
+    pub fn convert_rounded(&self, amount: f64, from: &str, to: &str) -> anyhow::Result<f64> {
+        Ok(round(self.convert(amount, from, to)?, to))
+    }
+}
+
+/// Exchange rate data signed by the operator of a rate service.
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SignedRates {
+    pub signer: String,
+    pub data: String,
+    pub signature: String,
+}
+
+impl SignedRates {
+    pub fn create<T: Serialize>(client: &Client, value: &T) -> anyhow::Result<SignedRates> {
+        let data = serde_json::to_string(value)
+            .map_err(|err| anyhow!("Failed to serialize rates: {}", err))?;
+        Ok(SignedRates {
+            signer: client.account.clone(),
+            signature: client.sign(&format!("{}{}", RATES_TAG, data)),
+            data,
+        })
+    }
+
+    /// Checks the operator's signature and returns the payload.
+    pub fn verify<T: DeserializeOwned>(&self, public_key: &str) -> anyhow::Result<T> {
+        verify(
+            public_key,
+            &format!("{}{}", RATES_TAG, self.data),
+            &self.signature,
+        )?;
+        serde_json::from_str(&self.data).map_err(|err| anyhow!("Failed to parse rates: {}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const CURRENCIES: &[&str] = &["USD", "EUR", "JPY", "KWD", "GBP", "CLF"];
+
+    fn update(ts: u32) -> CurrencyExchangeRatesUpdate {
+        CurrencyExchangeRatesUpdate {
+            source: "consensus".to_string(),
+            base: "USD".to_string(),
+            ts,
+            rates: vec![
+                ("EUR".to_string(), 0.82),
+                ("JPY".to_string(), 104.0),
+                ("KWD".to_string(), 0.3027),
+                ("GBP".to_string(), 0.73),
+                ("CLF".to_string(), 0.0266),
+            ]
+            .into_iter()
+            .collect(),
+        }
+    }
+
+    #[test]
+    fn test_rate_table() {
+        let table = RateTable::new(&update(1000), 1100, 3600).unwrap();
+        assert_eq!(table.base(), "USD");
+        assert_eq!(table.age(1100), 100);
+        assert_eq!(table.convert_rounded(1.0, "USD", "EUR").unwrap(), 0.82);
+        assert_eq!(table.convert_rounded(1.0, "EUR", "JPY").unwrap(), 127.0);
+        assert_eq!(table.convert_rounded(10.0, "JPY", "KWD").unwrap(), 0.029);
+        assert!(table.convert(1.0, "USD", "XYZ").is_err());
+
+        assert!(RateTable::new(&update(1000), 5000, 3600).is_err());
+        assert!(table.is_stale(5000, 3600));
+
+        let mut invalid = update(1000);
+        invalid.rates.insert("RUB".to_string(), 0.0);
+        assert!(RateTable::new(&invalid, 1000, 3600).is_err());
+
+        assert_eq!(round(0.125, "USD"), 0.13);
+        assert_eq!(round(1.5, "JPY"), 2.0);
+    }
+
+    #[test]
+    fn test_signed_rates() {
+        let operator = Client::new("a".to_string(), "secret".to_string(), "".to_string());
+        let signed = SignedRates::create(&operator, &update(1000)).unwrap();
+        let verified: CurrencyExchangeRatesUpdate = signed.verify(&operator.public_key()).unwrap();
+        let table = RateTable::new(&verified, 1000, 3600).unwrap();
+        assert_eq!(table.rate("USD", "EUR").unwrap(), 0.82);
+
+        let other = Client::new("b".to_string(), "other".to_string(), "".to_string());
+        assert!(signed
+            .verify::<CurrencyExchangeRatesUpdate>(&other.public_key())
+            .is_err());
+
+        let mut tampered = signed;
+        tampered.data = tampered.data.replace("0.82", "0.92");
+        assert!(tampered
+            .verify::<CurrencyExchangeRatesUpdate>(&operator.public_key())
+            .is_err());
+    }
+
+    fn currency() -> impl Strategy<Value = &'static str> {
+        prop::sample::select(CURRENCIES)
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(amount in 0.0..1e9f64, from in currency(), to in currency()) {
+            let table = RateTable::new(&update(1000), 1000, 0).unwrap();
+            let there = table.convert(amount, from, to).unwrap();
+            let back = table.convert(there, to, from).unwrap();
+            prop_assert!((back - amount).abs() <= amount * 1e-12);
+        }
+
+        #[test]
+        fn test_rounded_round_trip(amount in 0.0..1e9f64, from in currency(), to in currency()) {
+            let table = RateTable::new(&update(1000), 1000, 0).unwrap();
+            let amount = round(amount, from);
+            let there = table.convert_rounded(amount, from, to).unwrap();
+            let back = table.convert_rounded(there, to, from).unwrap();
+            // Each rounding loses at most half of a minor unit.
+            let error = 0.5 * 10f64.powi(-minor_units(to)) * table.rate(to, from).unwrap()
+                + 0.5 * 10f64.powi(-minor_units(from));
+            prop_assert!((back - amount).abs() <= error + amount * 1e-12);
+        }
+
+        #[test]
+        fn test_cross_rates(a in currency(), b in currency(), c in currency()) {
+            let table = RateTable::new(&update(1000), 1000, 0).unwrap();
+            let direct = table.rate(a, c).unwrap();
+            let through = table.rate(a, b).unwrap() * table.rate(b, c).unwrap();
+            prop_assert!((direct - through).abs() <= direct * 1e-12);
+        }
+    }
+}
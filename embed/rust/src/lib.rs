@@ -12,4 +12,7 @@ extern crate serde_json;
 extern crate blake2;
 extern crate ed25519_dalek;
 
+pub mod challenge;
 pub mod client;
+pub mod confirmation;
+pub mod currency;